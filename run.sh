@@ -1,17 +1,26 @@
 i=0
 PID=0
-RESTART_AT_IN_SECONDS=900
+# restart interval, 900 seconds unless set in the environment
+RESTART_AT_IN_SECONDS=${RESTART_AT_IN_SECONDS:-900}
+
+# crawler logs to crawler.log, out.log only keeps cargo output
+export CRAWLER_LOGGING_FILE=${CRAWLER_LOGGING_FILE:-$PWD/crawler.log}
+# settings and commands are described in crawler.example.toml
+
+# secret protecting the private keys, see [encryption] in crawler.example.toml
+if [ -z "$CRYPTO_EXT_SECRET" ]
+then
+	echo "CRYPTO_EXT_SECRET environment variable is not set"
+	exit 1
+fi
 
 while true
 do
 	echo "\n\niteration $i"
 	echo "" > out.log
-	echo "killing previously started instance PID"
-	killall retrieve-all-steam-apps-details-demo-app >> out.log &
-	touch stop.sh
-	sh stop.sh
+	echo "stopping previously started instance"
+	sh stop.sh >> out.log 2>&1
 	cargo run >> out.log &
-	echo "kill -9 $!" > stop.sh
 	echo "started app with PID $!, output redirected to out.log"
 	echo "instance will be restarted in $RESTART_AT_IN_SECONDS seconds"
 	echo "end of iteration $i"
@@ -0,0 +1,266 @@
+use std::fs::{self, OpenOptions, read_to_string};
+use std::io::Write;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use sha256::digest;
+use crate::crypto_ext::algorithm::KeyAlgorithm;
+use crate::crypto_ext::error::CryptoError;
+use crate::crypto_ext::keyring::{get_unix_timestamp, Keyring};
+
+#[cfg(test)]
+mod tests;
+
+pub const AUDIT_LOG_FILENAME: &str = "audit.log";
+pub const DEFAULT_SEAL_INTERVAL: u64 = 100;
+
+/// Previous hash of the first entry in the log.
+pub const GENESIS_SHA256: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuditEvent {
+    AppFetched { app_id: i64, name: String },
+    AppFailed { app_id: i64, error: String },
+    AppRetried { app_id: i64 },
+    BackupTaken,
+    RestorePerformed,
+    KeyRotated { key_id: String, algorithm: KeyAlgorithm },
+    KeyImported { key_id: String, algorithm: KeyAlgorithm },
+    /// Signature over `previous_sha256` of the seal, covers every entry before it.
+    Seal { signature: String },
+}
+
+/// One line of the audit log. Hash of the entry is the SHA-256 of the line
+/// as written, the next entry refers to it in `previous_sha256`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub sequence: u64,
+    pub timestamp: u64,
+    pub previous_sha256: String,
+    pub event: AuditEvent,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BrokenLink {
+    /// Line number in the log, starting with 1.
+    pub line: usize,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    pub entries: u64,
+    pub seals: u64,
+    /// Entries after the last seal, chained but not signed yet.
+    pub unsealed_entries: u64,
+    pub broken_link: Option<BrokenLink>,
+}
+
+/// Append-only log of crawl events. Every entry includes the hash of the previous
+/// one, every `seal_interval` entries the chain is sealed with a keyring signature.
+/// Entries appended by another handle are picked up before the next write.
+pub struct AuditLog {
+    path: String,
+    seal_interval: u64,
+    sequence: u64,
+    last_sha256: String,
+    unsealed_entries: u64,
+    /// Size of the log when its chain was last read or written by this handle.
+    length: u64,
+}
+
+impl AuditLog {
+    /// Opens the log and continues the chain from its last entry, creates the log if absent.
+    pub fn open(path: &str, seal_interval: u64) -> Result<AuditLog, String> {
+        let mut audit_log = AuditLog {
+            path: path.to_string(),
+            seal_interval,
+            sequence: 0,
+            last_sha256: GENESIS_SHA256.to_string(),
+            unsealed_entries: 0,
+            length: 0,
+        };
+        if !Path::new(path).is_file() {
+            return Ok(audit_log);
+        }
+
+        let boxed_content = read_to_string(path);
+        if boxed_content.is_err() {
+            let message = format!("unable to read audit log {}: {}", path, boxed_content.err().unwrap());
+            return Err(message)
+        }
+        let content = boxed_content.unwrap();
+        audit_log.length = content.len() as u64;
+
+        for line in content.lines() {
+            let boxed_entry: Result<AuditEntry, _> = serde_json::from_str(line);
+            if boxed_entry.is_err() {
+                let message = format!("audit log entry {} is malformed: {}", audit_log.sequence + 1, boxed_entry.err().unwrap());
+                return Err(message)
+            }
+            let entry = boxed_entry.unwrap();
+
+            audit_log.sequence = entry.sequence;
+            audit_log.last_sha256 = digest(line);
+            audit_log.unsealed_entries = match entry.event {
+                AuditEvent::Seal { .. } => 0,
+                _ => audit_log.unsealed_entries + 1,
+            };
+        }
+
+        Ok(audit_log)
+    }
+
+    /// Appends the event and seals the chain once `seal_interval` entries are unsealed.
+    pub fn append(&mut self, event: AuditEvent, keyring: &Keyring) -> Result<(), String> {
+        let boxed_append = self.write_entry(event);
+        if boxed_append.is_err() {
+            return Err(boxed_append.err().unwrap());
+        }
+
+        if self.unsealed_entries >= self.seal_interval {
+            return self.seal(keyring);
+        }
+        Ok(())
+    }
+
+    /// Signs the hash of the last entry, does nothing if everything is sealed already.
+    pub fn seal(&mut self, keyring: &Keyring) -> Result<(), String> {
+        if self.unsealed_entries == 0 {
+            return Ok(());
+        }
+
+        let boxed_signature = keyring.sign(self.last_sha256.as_bytes());
+        if boxed_signature.is_err() {
+            let message = format!("unable to seal audit log: {}", boxed_signature.err().unwrap());
+            return Err(message)
+        }
+
+        self.write_entry(AuditEvent::Seal { signature: boxed_signature.unwrap() })
+    }
+
+    /// Continues the chain from the entries another handle appended since.
+    fn sync(&mut self) -> Result<(), String> {
+        let length = fs::metadata(&self.path).map(|metadata| metadata.len()).unwrap_or(0);
+        if length == self.length {
+            return Ok(());
+        }
+        let boxed_audit_log = AuditLog::open(self.path.as_str(), self.seal_interval);
+        if boxed_audit_log.is_err() {
+            return Err(boxed_audit_log.err().unwrap());
+        }
+        *self = boxed_audit_log.unwrap();
+        Ok(())
+    }
+
+    fn write_entry(&mut self, event: AuditEvent) -> Result<(), String> {
+        let boxed_sync = self.sync();
+        if boxed_sync.is_err() {
+            return Err(boxed_sync.err().unwrap());
+        }
+        let is_seal = matches!(event, AuditEvent::Seal { .. });
+        let entry = AuditEntry {
+            sequence: self.sequence + 1,
+            timestamp: get_unix_timestamp(),
+            previous_sha256: self.last_sha256.to_string(),
+            event,
+        };
+        let boxed_line = serde_json::to_string(&entry);
+        if boxed_line.is_err() {
+            let message = format!("unable to serialize audit log entry: {}", boxed_line.err().unwrap());
+            return Err(message)
+        }
+        let line = boxed_line.unwrap();
+
+        let boxed_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path);
+        if boxed_file.is_err() {
+            let message = format!("unable to open audit log {}: {}", self.path, boxed_file.err().unwrap());
+            return Err(message)
+        }
+
+        // entry and line break are written at once, so a line is never shared by two entries
+        let boxed_write = boxed_file.unwrap().write_all([line.as_str(), "\n"].join("").as_bytes());
+        if boxed_write.is_err() {
+            let message = format!("unable to write audit log {}: {}", self.path, boxed_write.err().unwrap());
+            return Err(message)
+        }
+
+        self.sequence = entry.sequence;
+        self.length = self.length + line.len() as u64 + 1;
+        self.last_sha256 = digest(line.as_str());
+        self.unsealed_entries = if is_seal { 0 } else { self.unsealed_entries + 1 };
+        Ok(())
+    }
+}
+
+/// Walks the chain from the first entry and stops at the first broken link:
+/// malformed entry, gap in the sequence, hash mismatch or invalid seal signature.
+pub fn verify_audit_log(path: &str, keyring: &Keyring) -> Result<AuditReport, String> {
+    let boxed_content = read_to_string(path);
+    if boxed_content.is_err() {
+        let message = format!("unable to read audit log {}: {}", path, boxed_content.err().unwrap());
+        return Err(message)
+    }
+    let content = boxed_content.unwrap();
+
+    let mut report = AuditReport::default();
+    let mut previous_sha256 = GENESIS_SHA256.to_string();
+    for (index, line) in content.lines().enumerate() {
+        let boxed_entry: Result<AuditEntry, _> = serde_json::from_str(line);
+        if boxed_entry.is_err() {
+            let reason = format!("malformed entry: {}", boxed_entry.err().unwrap());
+            report.broken_link = Some(BrokenLink { line: index + 1, reason });
+            return Ok(report);
+        }
+        let entry = boxed_entry.unwrap();
+
+        let boxed_reason = check_link(&entry, report.entries + 1, previous_sha256.as_str(), keyring);
+        if boxed_reason.is_err() {
+            return Err(boxed_reason.err().unwrap());
+        }
+        if let Some(reason) = boxed_reason.unwrap() {
+            report.broken_link = Some(BrokenLink { line: index + 1, reason });
+            return Ok(report);
+        }
+
+        report.entries += 1;
+        if matches!(entry.event, AuditEvent::Seal { .. }) {
+            report.seals += 1;
+            report.unsealed_entries = 0;
+        } else {
+            report.unsealed_entries += 1;
+        }
+        previous_sha256 = digest(line);
+    }
+
+    Ok(report)
+}
+
+/// Returns the reason the link is broken, error is returned only if the keyring is not usable.
+fn check_link(entry: &AuditEntry, sequence: u64, previous_sha256: &str, keyring: &Keyring) -> Result<Option<String>, String> {
+    if entry.sequence != sequence {
+        return Ok(Some(format!("expected sequence {}, found {}", sequence, entry.sequence)));
+    }
+    if entry.previous_sha256 != previous_sha256 {
+        return Ok(Some("previous entry hash does not match".to_string()));
+    }
+
+    if let AuditEvent::Seal { signature } = &entry.event {
+        let boxed_verify = keyring.verify(entry.previous_sha256.as_bytes(), signature.as_str());
+        if boxed_verify.is_err() {
+            let error = boxed_verify.err().unwrap();
+            return match error {
+                CryptoError::UnknownKey(_) | CryptoError::Unsupported(_) => Ok(Some(format!("seal is signed with a foreign key: {}", error))),
+                _ => Err(format!("unable to verify seal: {}", error)),
+            }
+        }
+        if !boxed_verify.unwrap() {
+            return Ok(Some("seal signature does not match".to_string()));
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::path::Path;
+use crate::audit::{AuditEvent, AuditLog, BrokenLink, verify_audit_log};
+use crate::crypto_ext::KeyOptions;
+use crate::crypto_ext::algorithm::KeyAlgorithm;
+use crate::crypto_ext::keyring::Keyring;
+use crate::crypto_ext::key_store::KeyStore;
+
+const TEST_SECRET: &str = "audit test secret";
+
+fn setup(name: &str) -> (String, Keyring) {
+    // target directory is ignored by git, log and keyring are recreated on every run
+    let key_store = KeyStore::new(Path::new("target/audit_tests").join(name)).unwrap();
+    let _ = fs::remove_dir_all(key_store.get_directory());
+    let keyring = Keyring::open_with_options(&key_store, TEST_SECRET, &KeyOptions::new(KeyAlgorithm::Ed25519)).unwrap();
+    let directory = [key_store.get_directory().to_str().unwrap(), "/"].join("");
+    ([directory.as_str(), "audit.log"].join(""), keyring)
+}
+
+fn append_events(path: &str, keyring: &Keyring, count: i64) {
+    let mut audit_log = AuditLog::open(path, 3).unwrap();
+    for app_id in 0..count {
+        audit_log.append(AuditEvent::AppFetched { app_id, name: "app".to_string() }, keyring).unwrap();
+    }
+}
+
+#[test]
+fn chain() {
+    let (path, keyring) = setup("chain");
+    append_events(path.as_str(), &keyring, 4);
+
+    // reopened log continues the chain and the seal interval
+    let mut audit_log = AuditLog::open(path.as_str(), 3).unwrap();
+    audit_log.append(AuditEvent::AppFailed { app_id: 4, error: "no response from API".to_string() }, &keyring).unwrap();
+    audit_log.append(AuditEvent::BackupTaken, &keyring).unwrap();
+
+    let report = verify_audit_log(path.as_str(), &keyring).unwrap();
+    assert_eq!(8, report.entries);
+    assert_eq!(2, report.seals);
+    assert_eq!(0, report.unsealed_entries);
+    assert_eq!(None, report.broken_link);
+
+    audit_log.append(AuditEvent::RestorePerformed, &keyring).unwrap();
+    let report = verify_audit_log(path.as_str(), &keyring).unwrap();
+    assert_eq!(1, report.unsealed_entries);
+
+    audit_log.seal(&keyring).unwrap();
+    audit_log.seal(&keyring).unwrap();
+    let report = verify_audit_log(path.as_str(), &keyring).unwrap();
+    assert_eq!(10, report.entries);
+    assert_eq!(0, report.unsealed_entries);
+
+    // entries of another handle, as of a nested crawl, are chained on by this one
+    append_events(path.as_str(), &keyring, 2);
+    audit_log.append(AuditEvent::BackupTaken, &keyring).unwrap();
+    let report = verify_audit_log(path.as_str(), &keyring).unwrap();
+    assert_eq!((14, None), (report.entries, report.broken_link));
+}
+
+#[test]
+fn broken_link() {
+    let (path, keyring) = setup("broken_link");
+    append_events(path.as_str(), &keyring, 6);
+    let content = fs::read_to_string(&path).unwrap();
+
+    // edited entry breaks the hash of the next one
+    let edited = content.replacen("\"app_id\":1,", "\"app_id\":100,", 1);
+    fs::write(&path, edited).unwrap();
+    let report = verify_audit_log(path.as_str(), &keyring).unwrap();
+    assert_eq!(Some(BrokenLink { line: 3, reason: "previous entry hash does not match".to_string() }), report.broken_link);
+    assert_eq!(2, report.entries);
+
+    // removed entry leaves a gap in the sequence
+    let lines: Vec<&str> = content.lines().collect();
+    let removed = [lines[0], lines[2], lines[3]].join("\n");
+    fs::write(&path, removed).unwrap();
+    let report = verify_audit_log(path.as_str(), &keyring).unwrap();
+    assert_eq!(2, report.broken_link.unwrap().line);
+}
+
+#[test]
+fn rewritten_chain() {
+    let (path, keyring) = setup("rewritten_chain");
+    append_events(path.as_str(), &keyring, 3);
+    let content = fs::read_to_string(&path).unwrap();
+    let seal = content.lines().nth(3).unwrap().to_string();
+
+    // chain rebuilt by someone without the key is consistent, but its seal is not
+    let (forged_path, _) = setup("rewritten_chain_forged");
+    let mut forged_log = AuditLog::open(forged_path.as_str(), 100).unwrap();
+    for app_id in [0, 1, 200] {
+        forged_log.append(AuditEvent::AppFetched { app_id, name: "app".to_string() }, &keyring).unwrap();
+    }
+    let forged_content = fs::read_to_string(&forged_path).unwrap();
+    let forged_last_sha256 = sha256::digest(forged_content.lines().nth(2).unwrap());
+    let original_last_sha256 = sha256::digest(content.lines().nth(2).unwrap());
+    let forged_seal = seal.replace(original_last_sha256.as_str(), forged_last_sha256.as_str());
+    fs::write(&path, [forged_content.as_str(), forged_seal.as_str()].join("")).unwrap();
+
+    let report = verify_audit_log(path.as_str(), &keyring).unwrap();
+    assert_eq!(Some(BrokenLink { line: 4, reason: "seal signature does not match".to_string() }), report.broken_link);
+}
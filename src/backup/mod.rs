@@ -0,0 +1,436 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use openssl::sha::Sha256;
+use serde::{Deserialize, Serialize};
+use crate::crypto_ext::envelope;
+use crate::crypto_ext::error::CryptoError;
+use crate::crypto_ext::key_store::{DIRECTORY_MODE, FILE_MODE, KEY_STORE_DIRECTORY_NAME, restrict_permissions};
+use crate::crypto_ext::keyring::{get_unix_timestamp, Keyring};
+
+#[cfg(test)]
+mod tests;
+
+pub const BACKUP_DIRECTORY_ENVIRONMENT_VARIABLE: &str = "BACKUP_DIRECTORY";
+pub const BACKUP_FILENAME: &str = "cache.backup";
+/// Version 1 archive is sealed in memory, version 2 archive is sealed as it is packed.
+pub const BACKUP_VERSION: u8 = 2;
+
+const SEALED_FILENAME: &str = "cache.backup.sealed";
+const PENDING_SUFFIX: &str = ".tmp";
+const MAX_HEADER_LENGTH: u64 = 4096;
+
+/// First line of the backup file, the encrypted archive follows it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BackupHeader {
+    pub version: u8,
+    pub created_at: u64,
+    /// Keyring signature over the version, creation time and SHA-256 of the encrypted archive.
+    pub signature: String,
+}
+
+/// Backup directory from `BACKUP_DIRECTORY` if set, otherwise `backups` directory
+/// under `$XDG_DATA_HOME` or `$HOME/.local/share`, away from the cache it protects.
+pub fn get_backup_directory() -> Result<PathBuf, String> {
+    let boxed_path = env::var(BACKUP_DIRECTORY_ENVIRONMENT_VARIABLE).ok().filter(|path| !path.is_empty());
+    if let Some(path) = boxed_path {
+        return Ok(PathBuf::from(path));
+    }
+
+    let boxed_data_home = env::var("XDG_DATA_HOME").ok().filter(|data_home| Path::new(data_home).is_absolute());
+    let data_home = if let Some(data_home) = boxed_data_home {
+        PathBuf::from(data_home)
+    } else {
+        let boxed_home = env::var("HOME");
+        if boxed_home.is_err() || boxed_home.as_ref().unwrap().is_empty() {
+            let message = format!("neither {}, XDG_DATA_HOME nor HOME environment variable is set", BACKUP_DIRECTORY_ENVIRONMENT_VARIABLE);
+            return Err(message)
+        }
+        PathBuf::from(boxed_home.unwrap()).join(".local").join("share")
+    };
+
+    Ok(data_home.join(KEY_STORE_DIRECTORY_NAME).join("backups"))
+}
+
+/// Packs the cache entries present in the directory into a compressed archive,
+/// encrypts and signs it with the keyring and replaces the backup in the backup directory.
+/// Archive is encrypted as it is packed and never held in memory.
+pub fn create_backup(cache_directory: &str, entries: &[String], backup_directory: &Path, keyring: &Keyring) -> Result<BackupHeader, String> {
+    let boxed_create = create_backup_directory(backup_directory);
+    if boxed_create.is_err() {
+        return Err(boxed_create.err().unwrap());
+    }
+
+    let sealed_path = backup_directory.join([SEALED_FILENAME, PENDING_SUFFIX].join(""));
+    let boxed_sealed = create_private_file(sealed_path.as_path());
+    if boxed_sealed.is_err() {
+        return Err(boxed_sealed.err().unwrap());
+    }
+    let mut sealed = HashingWriter { inner: boxed_sealed.unwrap(), hasher: Sha256::new() };
+
+    let boxed_pack = pack(cache_directory, entries, keyring, &mut sealed);
+    if boxed_pack.is_err() {
+        let _ = fs::remove_file(&sealed_path);
+        return Err(boxed_pack.err().unwrap());
+    }
+    let sealed_digest = hex::encode(sealed.hasher.finish());
+
+    let created_at = get_unix_timestamp();
+    let signed_data = get_signed_data(BACKUP_VERSION, created_at, sealed_digest.as_str());
+    let boxed_signature = keyring.sign(signed_data.as_bytes());
+    if boxed_signature.is_err() {
+        let _ = fs::remove_file(&sealed_path);
+        let message = format!("unable to sign backup: {}", boxed_signature.err().unwrap());
+        return Err(message)
+    }
+    let header = BackupHeader {
+        version: BACKUP_VERSION,
+        created_at,
+        signature: boxed_signature.unwrap(),
+    };
+
+    let boxed_write = write_backup_file(backup_directory, &header, sealed_path.as_path());
+    let _ = fs::remove_file(&sealed_path);
+    if boxed_write.is_err() {
+        return Err(boxed_write.err().unwrap());
+    }
+
+    Ok(header)
+}
+
+/// Checks the signature of the backup, nothing is decrypted. Returns the header
+/// and the backup file positioned at the start of the encrypted archive.
+pub fn verify_backup(backup_path: &Path, keyring: &Keyring) -> Result<(BackupHeader, File), String> {
+    let boxed_file = File::open(backup_path);
+    if boxed_file.is_err() {
+        let message = format!("unable to read backup {}: {}", backup_path.display(), boxed_file.err().unwrap());
+        return Err(message)
+    }
+    let mut reader = BufReader::new(boxed_file.unwrap());
+
+    let mut header_line: Vec<u8> = vec![];
+    let boxed_read = reader.by_ref().take(MAX_HEADER_LENGTH).read_until(b'\n', &mut header_line);
+    if boxed_read.is_err() {
+        let message = format!("unable to read backup {}: {}", backup_path.display(), boxed_read.err().unwrap());
+        return Err(message)
+    }
+    if header_line.last() != Some(&b'\n') {
+        return Err("backup has no header".to_string());
+    }
+
+    let boxed_header: Result<BackupHeader, _> = serde_json::from_slice(&header_line[..header_line.len() - 1]);
+    if boxed_header.is_err() {
+        let message = format!("unable to parse backup header: {}", boxed_header.err().unwrap());
+        return Err(message)
+    }
+    let header = boxed_header.unwrap();
+    if header.version == 0 || header.version > BACKUP_VERSION {
+        let message = format!("unsupported backup version: {}", header.version);
+        return Err(message)
+    }
+
+    let mut sealed = HashingWriter { inner: io::sink(), hasher: Sha256::new() };
+    let boxed_copy = io::copy(&mut reader, &mut sealed);
+    if boxed_copy.is_err() {
+        let message = format!("unable to read backup {}: {}", backup_path.display(), boxed_copy.err().unwrap());
+        return Err(message)
+    }
+    let sealed_digest = hex::encode(sealed.hasher.finish());
+
+    let signed_data = get_signed_data(header.version, header.created_at, sealed_digest.as_str());
+    let boxed_verify = keyring.verify(signed_data.as_bytes(), header.signature.as_str());
+    if boxed_verify.is_err() {
+        let error = boxed_verify.err().unwrap();
+        return match error {
+            CryptoError::UnknownKey(_) | CryptoError::Unsupported(_) => Err(format!("backup is signed with a foreign key: {}", error)),
+            _ => Err(format!("unable to verify backup: {}", error)),
+        }
+    }
+    if !boxed_verify.unwrap() {
+        return Err("backup signature does not match".to_string());
+    }
+
+    let mut file = reader.into_inner();
+    let boxed_seek = file.seek(SeekFrom::Start(header_line.len() as u64));
+    if boxed_seek.is_err() {
+        let message = format!("unable to read backup {}: {}", backup_path.display(), boxed_seek.err().unwrap());
+        return Err(message)
+    }
+
+    Ok((header, file))
+}
+
+/// Verifies the backup before anything is decrypted or unpacked, then unpacks
+/// the archive next to the cache directory as it is decrypted. Files are moved
+/// over the cache only once the whole archive was decrypted and authenticated.
+/// Returns the number of restored files.
+pub fn restore_backup(backup_path: &Path, cache_directory: &str, keyring: &Keyring) -> Result<usize, String> {
+    let boxed_verified = verify_backup(backup_path, keyring);
+    if boxed_verified.is_err() {
+        return Err(boxed_verified.err().unwrap());
+    }
+    let (header, mut file) = boxed_verified.unwrap();
+
+    // version 1 archive is sealed as a whole and decrypted in memory
+    if header.version == 1 {
+        let mut sealed: Vec<u8> = vec![];
+        let boxed_read = file.read_to_end(&mut sealed);
+        if boxed_read.is_err() {
+            let message = format!("unable to read backup {}: {}", backup_path.display(), boxed_read.err().unwrap());
+            return Err(message)
+        }
+        let boxed_archive = envelope::open(keyring, sealed.as_slice());
+        if boxed_archive.is_err() {
+            let message = format!("unable to decrypt backup: {}", boxed_archive.err().unwrap());
+            return Err(message)
+        }
+        return unpack(boxed_archive.unwrap().as_slice(), cache_directory);
+    }
+
+    let boxed_archive = envelope::open_stream(keyring, BufReader::new(file));
+    if boxed_archive.is_err() {
+        let message = format!("unable to decrypt backup: {}", boxed_archive.err().unwrap());
+        return Err(message)
+    }
+
+    unpack(boxed_archive.unwrap(), cache_directory)
+}
+
+/// Packs the entries into a compressed archive encrypted into the writer.
+fn pack<W: Write>(cache_directory: &str, entries: &[String], keyring: &Keyring, writer: W) -> Result<W, String> {
+    let boxed_sealing_writer = envelope::seal_stream(keyring, writer);
+    if boxed_sealing_writer.is_err() {
+        let message = format!("unable to encrypt backup: {}", boxed_sealing_writer.err().unwrap());
+        return Err(message)
+    }
+
+    let mut builder = tar::Builder::new(GzEncoder::new(boxed_sealing_writer.unwrap(), Compression::default()));
+    for entry in entries {
+        let path = Path::new(cache_directory).join(entry);
+        let boxed_append = if path.is_file() {
+            builder.append_path_with_name(&path, entry)
+        } else if path.is_dir() {
+            builder.append_dir_all(entry, &path)
+        } else {
+            continue;
+        };
+        if boxed_append.is_err() {
+            let message = format!("unable to pack {}: {}", path.display(), boxed_append.err().unwrap());
+            return Err(message)
+        }
+    }
+
+    let boxed_sealing_writer = builder.into_inner().and_then(|encoder| encoder.finish());
+    if boxed_sealing_writer.is_err() {
+        let message = format!("unable to pack backup: {}", boxed_sealing_writer.err().unwrap());
+        return Err(message)
+    }
+    let boxed_writer = boxed_sealing_writer.unwrap().finish();
+    if boxed_writer.is_err() {
+        let message = format!("unable to encrypt backup: {}", boxed_writer.err().unwrap());
+        return Err(message)
+    }
+    Ok(boxed_writer.unwrap())
+}
+
+fn unpack<R: Read>(archive: R, cache_directory: &str) -> Result<usize, String> {
+    let staging_directory = [cache_directory.trim_end_matches('/'), PENDING_SUFFIX].join("");
+    let _ = fs::remove_dir_all(&staging_directory);
+    let boxed_create = fs::create_dir_all(&staging_directory);
+    if boxed_create.is_err() {
+        let message = format!("unable to create {}: {}", staging_directory, boxed_create.err().unwrap());
+        return Err(message)
+    }
+
+    let boxed_restored_files = unpack_in(archive, staging_directory.as_str());
+    if boxed_restored_files.is_err() {
+        let _ = fs::remove_dir_all(&staging_directory);
+        return Err(boxed_restored_files.err().unwrap());
+    }
+
+    let boxed_create = fs::create_dir_all(cache_directory);
+    if boxed_create.is_err() {
+        let message = format!("unable to create {}: {}", cache_directory, boxed_create.err().unwrap());
+        return Err(message)
+    }
+    let boxed_move = move_into(Path::new(staging_directory.as_str()), Path::new(cache_directory));
+    if boxed_move.is_err() {
+        return Err(boxed_move.err().unwrap());
+    }
+    let _ = fs::remove_dir_all(&staging_directory);
+    Ok(boxed_restored_files.unwrap())
+}
+
+/// Unpacks the archive into the directory and reads it to the end, so the data
+/// is authenticated. Returns the number of unpacked files.
+fn unpack_in<R: Read>(archive: R, directory: &str) -> Result<usize, String> {
+    let mut archive = tar::Archive::new(GzDecoder::new(archive));
+    let boxed_entries = archive.entries();
+    if boxed_entries.is_err() {
+        let message = format!("unable to read backup archive: {}", boxed_entries.err().unwrap());
+        return Err(message)
+    }
+
+    let mut restored_files = 0;
+    for boxed_entry in boxed_entries.unwrap() {
+        if boxed_entry.is_err() {
+            let message = format!("unable to read backup archive: {}", boxed_entry.err().unwrap());
+            return Err(message)
+        }
+        let mut entry = boxed_entry.unwrap();
+        let is_file = entry.header().entry_type().is_file();
+
+        // entries pointing outside of the directory are refused
+        let boxed_unpack = entry.unpack_in(directory);
+        if boxed_unpack.is_err() {
+            let message = format!("unable to restore backup: {}", boxed_unpack.err().unwrap());
+            return Err(message)
+        }
+        if !boxed_unpack.unwrap() {
+            return Err("backup archive has entries outside of the cache directory".to_string());
+        }
+        if is_file {
+            restored_files += 1;
+        }
+    }
+
+    // tag of the envelope follows the archive, it is checked once the data is read to the end
+    let boxed_drain = io::copy(&mut archive.into_inner().into_inner(), &mut io::sink());
+    if boxed_drain.is_err() {
+        let message = format!("unable to decrypt backup: {}", boxed_drain.err().unwrap());
+        return Err(message)
+    }
+    Ok(restored_files)
+}
+
+/// Moves the files of the source directory over the same paths in the target directory.
+fn move_into(source: &Path, target: &Path) -> Result<(), String> {
+    let boxed_entries = fs::read_dir(source);
+    if boxed_entries.is_err() {
+        let message = format!("unable to read {}: {}", source.display(), boxed_entries.err().unwrap());
+        return Err(message)
+    }
+    for boxed_entry in boxed_entries.unwrap() {
+        if boxed_entry.is_err() {
+            let message = format!("unable to read {}: {}", source.display(), boxed_entry.err().unwrap());
+            return Err(message)
+        }
+        let entry = boxed_entry.unwrap();
+        let source_path = entry.path();
+        let target_path = target.join(entry.file_name());
+        if entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false) {
+            let boxed_create = fs::create_dir_all(&target_path);
+            if boxed_create.is_err() {
+                let message = format!("unable to create {}: {}", target_path.display(), boxed_create.err().unwrap());
+                return Err(message)
+            }
+            let boxed_move = move_into(source_path.as_path(), target_path.as_path());
+            if boxed_move.is_err() {
+                return Err(boxed_move.err().unwrap());
+            }
+            continue;
+        }
+        let boxed_rename = fs::rename(&source_path, &target_path);
+        if boxed_rename.is_err() {
+            let message = format!("unable to restore {}: {}", target_path.display(), boxed_rename.err().unwrap());
+            return Err(message)
+        }
+    }
+    Ok(())
+}
+
+/// Passes written data to the inner writer and hashes it on the way.
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let boxed_length = self.inner.write(data);
+        if boxed_length.is_err() {
+            return Err(boxed_length.err().unwrap());
+        }
+        let length = boxed_length.unwrap();
+        self.hasher.update(&data[..length]);
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn create_backup_directory(backup_directory: &Path) -> Result<(), String> {
+    let boxed_create = fs::create_dir_all(backup_directory);
+    if boxed_create.is_err() {
+        let message = format!("unable to create backup directory {}: {}", backup_directory.display(), boxed_create.err().unwrap());
+        return Err(message)
+    }
+    let boxed_restrict = restrict_permissions(backup_directory, DIRECTORY_MODE);
+    if boxed_restrict.is_err() {
+        return Err(boxed_restrict.err().unwrap().to_string());
+    }
+    Ok(())
+}
+
+fn create_private_file(path: &Path) -> Result<File, String> {
+    let boxed_file = File::create(path);
+    if boxed_file.is_err() {
+        let message = format!("unable to create {}: {}", path.display(), boxed_file.err().unwrap());
+        return Err(message)
+    }
+    let boxed_restrict = restrict_permissions(path, FILE_MODE);
+    if boxed_restrict.is_err() {
+        return Err(boxed_restrict.err().unwrap().to_string());
+    }
+    Ok(boxed_file.unwrap())
+}
+
+/// Backup is written next to the previous one and renamed over it,
+/// an interrupted backup leaves the previous one intact.
+fn write_backup_file(backup_directory: &Path, header: &BackupHeader, sealed_path: &Path) -> Result<(), String> {
+    let boxed_serialized = serde_json::to_string(header);
+    if boxed_serialized.is_err() {
+        let message = format!("unable to serialize backup header: {}", boxed_serialized.err().unwrap());
+        return Err(message)
+    }
+
+    let boxed_sealed = File::open(sealed_path);
+    if boxed_sealed.is_err() {
+        let message = format!("unable to read {}: {}", sealed_path.display(), boxed_sealed.err().unwrap());
+        return Err(message)
+    }
+    let mut sealed = boxed_sealed.unwrap();
+
+    let path = backup_directory.join(BACKUP_FILENAME);
+    let temporary_path = backup_directory.join([BACKUP_FILENAME, PENDING_SUFFIX].join(""));
+    let boxed_file = create_private_file(temporary_path.as_path());
+    if boxed_file.is_err() {
+        return Err(boxed_file.err().unwrap());
+    }
+    let mut file = boxed_file.unwrap();
+
+    let boxed_write = file.write_all([boxed_serialized.unwrap().as_str(), "\n"].join("").as_bytes())
+        .and_then(|_| io::copy(&mut sealed, &mut file))
+        .and_then(|_| file.sync_all());
+    if boxed_write.is_err() {
+        let message = format!("unable to write {}: {}", temporary_path.display(), boxed_write.err().unwrap());
+        return Err(message)
+    }
+
+    let boxed_rename = fs::rename(&temporary_path, &path);
+    if boxed_rename.is_err() {
+        let message = format!("unable to replace {}: {}", path.display(), boxed_rename.err().unwrap());
+        return Err(message)
+    }
+    Ok(())
+}
+
+fn get_signed_data(version: u8, created_at: u64, sealed_digest: &str) -> String {
+    [version.to_string(), created_at.to_string(), sealed_digest.to_string()].join(":")
+}
@@ -0,0 +1,126 @@
+use std::fs;
+use std::path::Path;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use crate::backup::{BACKUP_FILENAME, BackupHeader, create_backup, get_signed_data, restore_backup, verify_backup};
+use crate::crypto_ext::{envelope, KeyOptions};
+use crate::crypto_ext::algorithm::KeyAlgorithm;
+use crate::crypto_ext::key_provider::MemoryKeyProvider;
+use crate::crypto_ext::keyring::Keyring;
+
+const TEST_SECRET: &str = "backup test secret";
+
+fn get_entries() -> Vec<String> {
+    vec!["processed_app_id_list.json".to_string(), "ISteamApps-GetAppList-v2.json".to_string(), "steampowered".to_string()]
+}
+
+fn setup(name: &str) -> (String, String, Keyring) {
+    // target directory is ignored by git, cache and backup are recreated on every run
+    let directory = ["target/backup_tests/", name].join("");
+    let _ = fs::remove_dir_all(&directory);
+    let cache_directory = [directory.as_str(), "/cache"].join("");
+    let backup_directory = [directory.as_str(), "/backups"].join("");
+    fs::create_dir_all([cache_directory.as_str(), "/steampowered/appdetails/0/10"].join("")).unwrap();
+    fs::write([cache_directory.as_str(), "/processed_app_id_list.json"].join(""), "[10,20]").unwrap();
+    fs::write([cache_directory.as_str(), "/ISteamApps-GetAppList-v2.json"].join(""), "{}").unwrap();
+    fs::write([cache_directory.as_str(), "/steampowered/appdetails/0/10/10.json"].join(""), "{\"name\":\"Counter-Strike\"}").unwrap();
+    fs::write([cache_directory.as_str(), "/not_backed_up.json"].join(""), "{}").unwrap();
+
+    // X25519 key encrypts the data key, Ed25519 key signs the backup
+    let key_provider = MemoryKeyProvider::new();
+    let mut keyring = Keyring::open_with_options(&key_provider, TEST_SECRET, &KeyOptions::new(KeyAlgorithm::Ed25519)).unwrap();
+    keyring.rotate(&KeyOptions::new(KeyAlgorithm::X25519)).unwrap();
+
+    (cache_directory, backup_directory, keyring)
+}
+
+#[test]
+fn round_trip() {
+    let (cache_directory, backup_directory, keyring) = setup("round_trip");
+    create_backup(cache_directory.as_str(), &get_entries(), Path::new(backup_directory.as_str()), &keyring).unwrap();
+
+    let backup_path = Path::new(backup_directory.as_str()).join(BACKUP_FILENAME);
+    let content = fs::read(&backup_path).unwrap();
+    assert!(!String::from_utf8_lossy(content.as_slice()).contains("Counter-Strike"));
+
+    fs::remove_dir_all(&cache_directory).unwrap();
+    assert_eq!(3, restore_backup(backup_path.as_path(), cache_directory.as_str(), &keyring).unwrap());
+    assert_eq!("[10,20]", fs::read_to_string([cache_directory.as_str(), "/processed_app_id_list.json"].join("")).unwrap());
+    assert_eq!("{\"name\":\"Counter-Strike\"}", fs::read_to_string([cache_directory.as_str(), "/steampowered/appdetails/0/10/10.json"].join("")).unwrap());
+    assert!(!Path::new([cache_directory.as_str(), "/not_backed_up.json"].join("").as_str()).exists());
+}
+
+#[test]
+fn tampered() {
+    let (cache_directory, backup_directory, keyring) = setup("tampered");
+    create_backup(cache_directory.as_str(), &get_entries(), Path::new(backup_directory.as_str()), &keyring).unwrap();
+    let backup_path = Path::new(backup_directory.as_str()).join(BACKUP_FILENAME);
+    let content = fs::read(&backup_path).unwrap();
+
+    // changed archive is refused before anything is written to the cache
+    let mut tampered_content = content.to_vec();
+    let last = tampered_content.len() - 1;
+    tampered_content[last] ^= 1;
+    fs::write(&backup_path, tampered_content).unwrap();
+    fs::write([cache_directory.as_str(), "/processed_app_id_list.json"].join(""), "[10,20,30]").unwrap();
+    let error = restore_backup(backup_path.as_path(), cache_directory.as_str(), &keyring).err().unwrap();
+    assert_eq!("backup signature does not match", error);
+    assert_eq!("[10,20,30]", fs::read_to_string([cache_directory.as_str(), "/processed_app_id_list.json"].join("")).unwrap());
+
+    // backup signed by someone else
+    let (foreign_cache_directory, foreign_backup_directory, foreign_keyring) = setup("tampered_foreign");
+    create_backup(foreign_cache_directory.as_str(), &get_entries(), Path::new(foreign_backup_directory.as_str()), &foreign_keyring).unwrap();
+    let foreign_backup_path = Path::new(foreign_backup_directory.as_str()).join(BACKUP_FILENAME);
+    assert!(verify_backup(foreign_backup_path.as_path(), &keyring).err().unwrap().starts_with("backup is signed with a foreign key"));
+
+    fs::write(&backup_path, "").unwrap();
+    assert!(verify_backup(backup_path.as_path(), &keyring).is_err());
+}
+
+#[test]
+fn undecryptable() {
+    let (cache_directory, backup_directory, keyring) = setup("undecryptable");
+    create_backup(cache_directory.as_str(), &get_entries(), Path::new(backup_directory.as_str()), &keyring).unwrap();
+    let backup_path = Path::new(backup_directory.as_str()).join(BACKUP_FILENAME);
+    let content = fs::read(&backup_path).unwrap();
+
+    // signed backup whose tag does not match, the archive is unpacked before the tag is read
+    let header_length = content.iter().position(|byte| *byte == b'\n').unwrap() + 1;
+    let mut sealed = content[header_length..].to_vec();
+    let last = sealed.len() - 1;
+    sealed[last] ^= 1;
+    let signed_data = get_signed_data(2, 1700000000, sha256::digest(sealed.as_slice()).as_str());
+    let header = BackupHeader { version: 2, created_at: 1700000000, signature: keyring.sign(signed_data.as_bytes()).unwrap() };
+    let mut content = [serde_json::to_string(&header).unwrap().as_str(), "\n"].join("").into_bytes();
+    content.extend_from_slice(sealed.as_slice());
+    fs::write(&backup_path, content).unwrap();
+
+    // cache is left as it was, nothing unpacked is moved into it
+    fs::write([cache_directory.as_str(), "/processed_app_id_list.json"].join(""), "[10,20,30]").unwrap();
+    let error = restore_backup(backup_path.as_path(), cache_directory.as_str(), &keyring).err().unwrap();
+    assert!(error.starts_with("unable to decrypt backup"));
+    assert_eq!("[10,20,30]", fs::read_to_string([cache_directory.as_str(), "/processed_app_id_list.json"].join("")).unwrap());
+    assert!(!Path::new([cache_directory.as_str(), ".tmp"].join("").as_str()).exists());
+}
+
+#[test]
+fn version_1() {
+    let (cache_directory, backup_directory, keyring) = setup("version_1");
+
+    // archive sealed in memory by earlier versions is still restored
+    let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+    builder.append_path_with_name([cache_directory.as_str(), "/processed_app_id_list.json"].join(""), "processed_app_id_list.json").unwrap();
+    let archive = builder.into_inner().unwrap().finish().unwrap();
+    let sealed = envelope::seal(&keyring, archive.as_slice()).unwrap();
+    let signed_data = get_signed_data(1, 1700000000, sha256::digest(sealed.as_slice()).as_str());
+    let header = BackupHeader { version: 1, created_at: 1700000000, signature: keyring.sign(signed_data.as_bytes()).unwrap() };
+    let mut content = [serde_json::to_string(&header).unwrap().as_str(), "\n"].join("").into_bytes();
+    content.extend_from_slice(sealed.as_slice());
+    fs::create_dir_all(&backup_directory).unwrap();
+    let backup_path = Path::new(backup_directory.as_str()).join(BACKUP_FILENAME);
+    fs::write(&backup_path, content).unwrap();
+
+    fs::remove_dir_all(&cache_directory).unwrap();
+    assert_eq!(1, restore_backup(backup_path.as_path(), cache_directory.as_str(), &keyring).unwrap());
+    assert_eq!("[10,20]", fs::read_to_string([cache_directory.as_str(), "/processed_app_id_list.json"].join("")).unwrap());
+}
@@ -0,0 +1,140 @@
+use std::net::TcpListener;
+use serde_json::{json, Value};
+use crate::catalog::{AppQuery, Catalog, Health, DEFAULT_SEARCH_LIMIT, MAX_SEARCH_LIMIT};
+use crate::catalog::get_details;
+use crate::coordinator::http::{read_request, write_json_response, Request, IO_TIMEOUT};
+use crate::logging;
+
+/// Splits a request target into its path and its decoded query parameters.
+pub fn parse_target(target: &str) -> (String, Vec<(String, String)>) {
+    let (path, query) = match target.find('?') {
+        Some(position) => (&target[..position], &target[position + 1..]),
+        None => (target, ""),
+    };
+    let parameters = query.split('&')
+        .filter(|parameter| !parameter.is_empty())
+        .map(|parameter| match parameter.find('=') {
+            Some(position) => (decode_component(&parameter[..position]), decode_component(&parameter[position + 1..])),
+            None => (decode_component(parameter), "".to_string()),
+        })
+        .collect();
+    (path.to_string(), parameters)
+}
+
+/// Percent-decodes a query component, `+` is a space. Malformed escapes are kept as they are.
+pub fn decode_component(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        let is_escape = byte == b'%' && index + 2 < bytes.len()
+            && bytes[index + 1].is_ascii_hexdigit() && bytes[index + 2].is_ascii_hexdigit();
+        if is_escape {
+            let hex = String::from_utf8_lossy(&bytes[index + 1..index + 3]).to_string();
+            decoded.push(u8::from_str_radix(hex.as_str(), 16).unwrap_or(b'%'));
+            index += 3;
+            continue;
+        }
+        decoded.push(if byte == b'+' { b' ' } else { byte });
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn error(status: u16, message: &str) -> (u16, Value) {
+    (status, json!({ "error": message }))
+}
+
+/// Status and JSON body of the response to `request`, see `serve`.
+pub fn route<D, H>(catalog: &Catalog, request: &Request, token: Option<&str>, read_document: D, get_health: H) -> (u16, Value)
+    where D: Fn(i64) -> Option<String>, H: Fn(&Catalog) -> Health {
+    let (path, parameters) = parse_target(request.path.as_str());
+    if request.method != "GET" {
+        return error(405, "only GET is served");
+    }
+    if path == "/health" {
+        let health = get_health(catalog);
+        let status = if health.is_ok() { 200 } else { 503 };
+        return (status, serde_json::to_value(health).unwrap_or_default());
+    }
+    if !request.is_authorized(token) {
+        return error(401, "missing or invalid bearer token");
+    }
+
+    if path == "/apps" {
+        let boxed_query = AppQuery::parse(&parameters);
+        if boxed_query.is_err() {
+            return error(400, boxed_query.err().unwrap().as_str());
+        }
+        return (200, serde_json::to_value(catalog.list(&boxed_query.unwrap())).unwrap_or_default());
+    }
+
+    if let Some(app_path) = path.strip_prefix("/apps/") {
+        let boxed_app_id = app_path.parse::<i64>();
+        if boxed_app_id.is_err() {
+            return error(400, "app id must be a number");
+        }
+        let app_id = boxed_app_id.unwrap();
+        let boxed_details = read_document(app_id).and_then(|document| get_details(app_id, document.as_str()).ok().flatten());
+        if boxed_details.is_none() {
+            return error(404, format!("no details stored for app {}", app_id).as_str());
+        }
+        return (200, boxed_details.unwrap());
+    }
+
+    if path == "/search" {
+        let mut name = "".to_string();
+        let mut limit = DEFAULT_SEARCH_LIMIT;
+        for (parameter, value) in parameters.iter() {
+            match parameter.as_str() {
+                "q" => name = value.to_string(),
+                "limit" => {
+                    let boxed_limit = value.parse::<usize>();
+                    if boxed_limit.is_err() || *boxed_limit.as_ref().unwrap() == 0 || *boxed_limit.as_ref().unwrap() > MAX_SEARCH_LIMIT {
+                        return error(400, format!("limit must be between 1 and {}", MAX_SEARCH_LIMIT).as_str());
+                    }
+                    limit = boxed_limit.unwrap();
+                }
+                _ => return error(400, format!("unknown parameter {}", parameter).as_str()),
+            }
+        }
+        if name.trim().is_empty() {
+            return error(400, "q is required");
+        }
+        return (200, serde_json::to_value(catalog.search_names(name.as_str(), limit)).unwrap_or_default());
+    }
+
+    error(404, "not found, GET /apps, /apps/<app id>, /search or /health")
+}
+
+/// Serves the catalog until the process is stopped, one connection at a time. `refresh`
+/// runs before every request and adds the apps the crawl stored since.
+///
+/// `GET /apps/<app id>` → stored details of the app, `GET /apps` → `AppPage` filtered by
+/// `type`, `is_free`, `platform`, `genre` and `year`, paged by `page` and `per_page`,
+/// `GET /search?q=<name>&limit=<n>` → `NameMatch` list, `GET /health` → `Health`, 503
+/// when the crawl is stale. With a token, requests other than `/health` need
+/// `Authorization: Bearer <token>`.
+pub fn serve<R, D, H>(listener: &TcpListener, catalog: &mut Catalog, token: Option<&str>, mut refresh: R, read_document: D, get_health: H)
+    where R: FnMut(&mut Catalog), D: Fn(i64) -> Option<String>, H: Fn(&Catalog) -> Health {
+    for boxed_stream in listener.incoming() {
+        if boxed_stream.is_err() {
+            logging::warn("unable to accept connection", &[("error", json!(boxed_stream.err().unwrap().to_string()))]);
+            continue;
+        }
+        let stream = boxed_stream.unwrap();
+        let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+
+        let boxed_request = read_request(&stream);
+        if boxed_request.is_err() {
+            let (status, body) = error(400, boxed_request.err().unwrap().as_str());
+            let _ = write_json_response(&stream, status, &body);
+            continue;
+        }
+        refresh(catalog);
+        let (status, body) = route(catalog, &boxed_request.unwrap(), token, &read_document, &get_health);
+        let _ = write_json_response(&stream, status, &body);
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::{BTreeMap, BTreeSet};
+use serde::Serialize;
+use serde_json::Value;
+use crate::estimator::Estimate;
+
+pub mod http;
+
+#[cfg(test)]
+mod tests;
+
+pub const DEFAULT_PER_PAGE: usize = 50;
+pub const MAX_PER_PAGE: usize = 500;
+pub const DEFAULT_SEARCH_LIMIT: usize = 20;
+pub const MAX_SEARCH_LIMIT: usize = 100;
+pub const PLATFORMS: [&str; 3] = ["windows", "mac", "linux"];
+
+/// Fields of stored app details the list is filtered on.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct AppSummary {
+    pub app_id: i64,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub app_type: String,
+    pub is_free: bool,
+    pub platforms: Vec<String>,
+    pub genres: Vec<String>,
+    pub release_date: String,
+    /// Unknown for dates such as "Coming soon".
+    pub release_year: Option<u32>,
+    pub coming_soon: bool,
+    pub short_description: String,
+}
+
+impl AppSummary {
+    /// Parses the appdetails response stored by the SDK, apps the store did not
+    /// return details for are `None`.
+    pub fn from_document(app_id: i64, document: &str) -> Result<Option<AppSummary>, String> {
+        let boxed_data = get_details(app_id, document);
+        if boxed_data.is_err() {
+            return Err(boxed_data.err().unwrap());
+        }
+        let boxed_data = boxed_data.unwrap();
+        if boxed_data.is_none() {
+            return Ok(None);
+        }
+        let data = boxed_data.unwrap();
+
+        let get_string = |value: &Value| value.as_str().unwrap_or_default().to_string();
+        let platforms = PLATFORMS.iter()
+            .filter(|platform| data["platforms"][**platform].as_bool().unwrap_or(false))
+            .map(|platform| platform.to_string())
+            .collect();
+        let genres = data["genres"].as_array()
+            .map(|genres| genres.iter().map(|genre| get_string(&genre["description"])).filter(|genre| !genre.is_empty()).collect())
+            .unwrap_or_default();
+        let release_date = get_string(&data["release_date"]["date"]);
+        Ok(Some(AppSummary {
+            app_id,
+            name: get_string(&data["name"]),
+            app_type: get_string(&data["type"]),
+            is_free: data["is_free"].as_bool().unwrap_or(false),
+            platforms,
+            genres,
+            release_year: get_release_year(release_date.as_str()),
+            release_date,
+            coming_soon: data["release_date"]["coming_soon"].as_bool().unwrap_or(false),
+            short_description: get_string(&data["short_description"]),
+        }))
+    }
+}
+
+/// `data` of the stored appdetails response, `None` if the store reported no success.
+pub fn get_details(app_id: i64, document: &str) -> Result<Option<Value>, String> {
+    let boxed_document = serde_json::from_str::<Value>(document);
+    if boxed_document.is_err() {
+        return Err(format!("unable to parse app details of {}: {}", app_id, boxed_document.err().unwrap()));
+    }
+    let mut document = boxed_document.unwrap();
+    let response = document[app_id.to_string()].take();
+    if !response["success"].as_bool().unwrap_or(false) || !response["data"].is_object() {
+        return Ok(None);
+    }
+    Ok(Some(response["data"].clone()))
+}
+
+/// Last four digit number of a store date such as "21 Aug, 2012" or "Q4 2024".
+pub fn get_release_year(date: &str) -> Option<u32> {
+    date.rsplit(|character: char| !character.is_ascii_digit())
+        .filter(|token| token.len() == 4)
+        .filter_map(|token| token.parse::<u32>().ok())
+        .find(|year| (1970..=2100).contains(year))
+}
+
+/// Filters and page of `GET /apps`, absent filters match every app.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppQuery {
+    pub app_type: Option<String>,
+    pub is_free: Option<bool>,
+    pub platform: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    /// Starts at 1.
+    pub page: usize,
+    pub per_page: usize,
+}
+
+impl Default for AppQuery {
+    fn default() -> Self {
+        AppQuery { app_type: None, is_free: None, platform: None, genre: None, year: None, page: 1, per_page: DEFAULT_PER_PAGE }
+    }
+}
+
+impl AppQuery {
+    /// Unknown parameters are rejected so a misspelled filter does not go unnoticed.
+    pub fn parse(parameters: &[(String, String)]) -> Result<AppQuery, String> {
+        let mut query = AppQuery::default();
+        for (name, value) in parameters {
+            match name.as_str() {
+                "type" => query.app_type = Some(value.to_lowercase()),
+                "platform" => {
+                    let platform = value.to_lowercase();
+                    if !PLATFORMS.contains(&platform.as_str()) {
+                        return Err(format!("platform must be one of {}", PLATFORMS.join(", ")));
+                    }
+                    query.platform = Some(platform);
+                }
+                "genre" => query.genre = Some(value.to_lowercase()),
+                "is_free" => {
+                    let boxed_is_free = value.parse::<bool>();
+                    if boxed_is_free.is_err() {
+                        return Err("is_free must be true or false".to_string());
+                    }
+                    query.is_free = Some(boxed_is_free.unwrap());
+                }
+                "year" | "page" | "per_page" => {
+                    let boxed_number = value.parse::<usize>();
+                    if boxed_number.is_err() {
+                        return Err(format!("{} must be a number", name));
+                    }
+                    let number = boxed_number.unwrap();
+                    match name.as_str() {
+                        "year" => query.year = Some(number.min(u32::MAX as usize) as u32),
+                        "page" => query.page = number,
+                        _ => query.per_page = number,
+                    }
+                }
+                _ => return Err(format!("unknown parameter {}", name)),
+            }
+        }
+        if query.page == 0 {
+            return Err("page starts at 1".to_string());
+        }
+        if query.per_page == 0 || query.per_page > MAX_PER_PAGE {
+            return Err(format!("per_page must be between 1 and {}", MAX_PER_PAGE));
+        }
+        Ok(query)
+    }
+
+    pub fn matches(&self, app: &AppSummary) -> bool {
+        let is_type = self.app_type.as_ref().map(|app_type| app.app_type.eq_ignore_ascii_case(app_type)).unwrap_or(true);
+        let is_free = self.is_free.map(|is_free| app.is_free == is_free).unwrap_or(true);
+        let is_platform = self.platform.as_ref().map(|platform| app.platforms.contains(platform)).unwrap_or(true);
+        let is_genre = self.genre.as_ref().map(|genre| app.genres.iter().any(|app_genre| app_genre.to_lowercase() == *genre)).unwrap_or(true);
+        let is_year = self.year.map(|year| app.release_year == Some(year)).unwrap_or(true);
+        is_type && is_free && is_platform && is_genre && is_year
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct AppPage {
+    /// Apps matching the filters on every page.
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+    pub apps: Vec<AppSummary>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct NameMatch {
+    pub app_id: i64,
+    pub name: String,
+    /// Whether the crawl stored details of the app.
+    pub has_details: bool,
+}
+
+/// Summaries of the stored app details and the names of the app list, kept in memory
+/// and extended as the crawl stores more apps.
+#[derive(Clone, Debug, Default)]
+pub struct Catalog {
+    apps: BTreeMap<i64, AppSummary>,
+    /// Apps read, with or without details, so they are not read again.
+    loaded: BTreeSet<i64>,
+    names: Vec<(i64, String)>,
+}
+
+impl Catalog {
+    pub fn new(names: Vec<(i64, String)>) -> Catalog {
+        Catalog { apps: BTreeMap::new(), loaded: BTreeSet::new(), names }
+    }
+
+    pub fn is_loaded(&self, app_id: i64) -> bool {
+        self.loaded.contains(&app_id)
+    }
+
+    /// Stores the summary of the app details document, returns whether it had details.
+    pub fn insert_document(&mut self, app_id: i64, document: &str) -> Result<bool, String> {
+        self.loaded.insert(app_id);
+        let boxed_summary = AppSummary::from_document(app_id, document);
+        if boxed_summary.is_err() {
+            return Err(boxed_summary.err().unwrap());
+        }
+        let boxed_summary = boxed_summary.unwrap();
+        if boxed_summary.is_none() {
+            return Ok(false);
+        }
+        self.apps.insert(app_id, boxed_summary.unwrap());
+        Ok(true)
+    }
+
+    /// App processed without a stored document, not read again.
+    pub fn insert_missing(&mut self, app_id: i64) {
+        self.loaded.insert(app_id);
+    }
+
+    pub fn get_app_count(&self) -> usize {
+        self.apps.len()
+    }
+
+    /// Matching apps ordered by app id.
+    pub fn list(&self, query: &AppQuery) -> AppPage {
+        let matching: Vec<&AppSummary> = self.apps.values().filter(|app| query.matches(app)).collect();
+        let apps = matching.iter()
+            .skip((query.page - 1).saturating_mul(query.per_page))
+            .take(query.per_page)
+            .map(|app| (*app).clone())
+            .collect();
+        AppPage { total: matching.len(), page: query.page, per_page: query.per_page, apps }
+    }
+
+    /// Case-insensitive search of the app list names. Exact names rank first, then names
+    /// starting with the query, then names with a word starting with it, then any other
+    /// name containing it. Shorter names rank first within each.
+    pub fn search_names(&self, query: &str, limit: usize) -> Vec<NameMatch> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return vec![];
+        }
+        let mut matches: Vec<(usize, usize, i64, &str)> = vec![];
+        for (app_id, name) in self.names.iter() {
+            let lowercase_name = name.to_lowercase();
+            let boxed_position = lowercase_name.find(query.as_str());
+            if boxed_position.is_none() {
+                continue;
+            }
+            let position = boxed_position.unwrap();
+            let rank = if lowercase_name == query {
+                0
+            } else if position == 0 {
+                1
+            } else if lowercase_name.split_whitespace().any(|word| word.starts_with(query.as_str())) {
+                2
+            } else {
+                3
+            };
+            matches.push((rank, name.chars().count(), *app_id, name.as_str()));
+        }
+        matches.sort();
+        matches.into_iter()
+            .take(limit)
+            .map(|(_, _, app_id, name)| NameMatch { app_id, name: name.to_string(), has_details: self.apps.contains_key(&app_id) })
+            .collect()
+    }
+}
+
+/// Freshness of the crawl behind the served data.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Health {
+    /// "ok", or "stale" if the crawl did not process an app for `stale_after_seconds`.
+    pub status: String,
+    pub crawler_running: bool,
+    pub updated_at: Option<u64>,
+    pub seconds_since_update: Option<u64>,
+    pub stale_after_seconds: u64,
+    pub app_list_size: usize,
+    pub processed_total: usize,
+    pub percentage: f64,
+    pub apps_with_details: usize,
+}
+
+impl Health {
+    /// `estimate` is the last crawl status, absent before the first app is processed.
+    pub fn new(estimate: Option<&Estimate>, is_crawler_running: bool, apps_with_details: usize, stale_after_seconds: u64, now: u64) -> Health {
+        let seconds_since_update = estimate.map(|estimate| now.saturating_sub(estimate.updated_at));
+        let is_fresh = seconds_since_update.map(|seconds| seconds <= stale_after_seconds).unwrap_or(false);
+        Health {
+            status: if is_fresh { "ok" } else { "stale" }.to_string(),
+            crawler_running: is_crawler_running,
+            updated_at: estimate.map(|estimate| estimate.updated_at),
+            seconds_since_update,
+            stale_after_seconds,
+            app_list_size: estimate.map(|estimate| estimate.app_list_size).unwrap_or(0),
+            processed_total: estimate.map(|estimate| estimate.processed_total).unwrap_or(0),
+            percentage: estimate.map(|estimate| estimate.percentage).unwrap_or(0.0),
+            apps_with_details,
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status == "ok"
+    }
+}
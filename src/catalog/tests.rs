@@ -0,0 +1,116 @@
+use crate::catalog::{get_release_year, AppQuery, AppSummary, Catalog, Health};
+use crate::catalog::http::{decode_component, parse_target, route};
+use crate::coordinator::http::Request;
+use crate::estimator::Estimate;
+
+fn document(app_id: i64, name: &str, app_type: &str, is_free: bool, linux: bool, genre: &str, date: &str) -> String {
+    format!(r#"{{"{}":{{"success":true,"data":{{"type":"{}","name":"{}","steam_appid":{},"is_free":{},
+        "short_description":"About {}","platforms":{{"windows":true,"mac":false,"linux":{}}},
+        "genres":[{{"id":"1","description":"{}"}}],"release_date":{{"coming_soon":false,"date":"{}"}}}}}}}}"#,
+            app_id, app_type, name, app_id, is_free, name, linux, genre, date)
+}
+
+fn get_catalog() -> Catalog {
+    let names = vec![
+        (10, "Counter-Strike".to_string()), (20, "Team Fortress Classic".to_string()),
+        (30, "Portal".to_string()), (40, "Portal 2".to_string()), (50, "Aperture Portal Tales".to_string()),
+    ];
+    let mut catalog = Catalog::new(names);
+    catalog.insert_document(10, document(10, "Counter-Strike", "game", false, true, "Action", "1 Nov, 2000").as_str()).unwrap();
+    catalog.insert_document(30, document(30, "Portal", "game", false, true, "Puzzle", "10 Oct, 2007").as_str()).unwrap();
+    catalog.insert_document(40, document(40, "Portal 2", "game", false, false, "Action", "Apr 18, 2011").as_str()).unwrap();
+    catalog.insert_document(60, document(60, "Portal 2 Soundtrack", "dlc", true, true, "Action", "Coming soon").as_str()).unwrap();
+    assert!(!catalog.insert_document(20, r#"{"20":{"success":false}}"#).unwrap());
+    assert!(catalog.insert_document(70, "<html>").is_err());
+    catalog
+}
+
+fn get(path: &str, authorization: Option<&str>) -> Request {
+    let headers = authorization.map(|value| vec![("Authorization".to_string(), value.to_string())]).unwrap_or_default();
+    Request { method: "GET".to_string(), path: path.to_string(), headers, body: vec![] }
+}
+
+#[test]
+fn summaries_and_filters() {
+    let catalog = get_catalog();
+    assert_eq!(4, catalog.get_app_count());
+    assert!(catalog.is_loaded(20) && catalog.is_loaded(70) && !catalog.is_loaded(50));
+
+    let portal = catalog.list(&AppQuery { year: Some(2007), ..AppQuery::default() }).apps.remove(0);
+    assert_eq!(AppSummary {
+        app_id: 30, name: "Portal".to_string(), app_type: "game".to_string(), is_free: false,
+        platforms: vec!["windows".to_string(), "linux".to_string()], genres: vec!["Puzzle".to_string()],
+        release_date: "10 Oct, 2007".to_string(), release_year: Some(2007), coming_soon: false,
+        short_description: "About Portal".to_string(),
+    }, portal);
+    assert_eq!(Some(2024), get_release_year("Q4 2024"));
+    assert_eq!(None, get_release_year("Coming soon"));
+
+    let app_ids = |query: AppQuery| catalog.list(&query).apps.iter().map(|app| app.app_id).collect::<Vec<i64>>();
+    assert_eq!(vec![10, 30, 40, 60], app_ids(AppQuery::default()));
+    assert_eq!(vec![10, 40], app_ids(AppQuery { app_type: Some("game".to_string()), genre: Some("action".to_string()), ..AppQuery::default() }));
+    assert_eq!(vec![60], app_ids(AppQuery { is_free: Some(true), ..AppQuery::default() }));
+    assert_eq!(vec![10, 30], app_ids(AppQuery { platform: Some("linux".to_string()), is_free: Some(false), ..AppQuery::default() }));
+    assert_eq!(vec![40], app_ids(AppQuery { year: Some(2011), ..AppQuery::default() }));
+
+    let page = catalog.list(&AppQuery { page: 2, per_page: 3, ..AppQuery::default() });
+    assert_eq!((4, 2, 3), (page.total, page.page, page.per_page));
+    assert_eq!(vec![60], page.apps.iter().map(|app| app.app_id).collect::<Vec<i64>>());
+    assert!(catalog.list(&AppQuery { page: 3, per_page: 3, ..AppQuery::default() }).apps.is_empty());
+}
+
+#[test]
+fn name_search() {
+    let catalog = get_catalog();
+    let results = catalog.search_names("portal", 10);
+    let ranked: Vec<(i64, bool)> = results.iter().map(|result| (result.app_id, result.has_details)).collect();
+    assert_eq!(vec![(30, true), (40, true), (50, false)], ranked);
+    assert_eq!(1, catalog.search_names("PORTAL", 1).len());
+    assert_eq!(vec![20], catalog.search_names("ortress", 10).iter().map(|result| result.app_id).collect::<Vec<i64>>());
+    assert!(catalog.search_names("  ", 10).is_empty());
+}
+
+#[test]
+fn http_routes() {
+    assert_eq!("Portal 2 & co/é", decode_component("Portal+2%20%26%20co%2F%C3%A9"));
+    assert_eq!("100%", decode_component("100%"));
+    assert_eq!(("/apps".to_string(), vec![("type".to_string(), "game".to_string()), ("page".to_string(), "".to_string())]), parse_target("/apps?type=game&page"));
+
+    let catalog = get_catalog();
+    let estimate = Estimate {
+        app_list_size: 100, processed_total: 40, percentage: 40.0, apps_to_fetch: 70, processed: 10, run_percentage: 14.3,
+        apps_per_minute: 2.0, eta_seconds: Some(1_800), retries: 0, backoff_seconds: 0, started_at: 1_000, updated_at: 2_000,
+    };
+    let read_document = |app_id: i64| if app_id == 30 { Some(document(30, "Portal", "game", false, true, "Puzzle", "10 Oct, 2007")) } else { None };
+    let respond = |request: &Request, now: u64| route(&catalog, request, Some("secret"), read_document,
+        |catalog: &Catalog| Health::new(Some(&estimate), true, catalog.get_app_count(), 600, now));
+    let authorized = Some("Bearer secret");
+
+    let (status, body) = respond(&get("/health", None), 2_300);
+    assert_eq!((200, "ok", 300, 4), (status, body["status"].as_str().unwrap(), body["seconds_since_update"].as_u64().unwrap(), body["apps_with_details"].as_u64().unwrap()));
+    let (status, body) = respond(&get("/health", None), 2_601);
+    assert_eq!((503, "stale"), (status, body["status"].as_str().unwrap()));
+    assert_eq!("stale", Health::new(None, false, 0, 600, 0).status);
+
+    assert_eq!(401, respond(&get("/apps", None), 0).0);
+    assert_eq!(401, respond(&get("/apps", Some("Bearer guess")), 0).0);
+    let (status, body) = respond(&get("/apps?platform=linux&per_page=1&page=2", authorized), 0);
+    assert_eq!((200, 3, 30), (status, body["total"].as_u64().unwrap(), body["apps"][0]["app_id"].as_i64().unwrap()));
+    assert_eq!("game", body["apps"][0]["type"].as_str().unwrap());
+    assert_eq!(400, respond(&get("/apps?platform=amiga", authorized), 0).0);
+    assert_eq!(400, respond(&get("/apps?genere=action", authorized), 0).0);
+    assert_eq!(400, respond(&get("/apps?per_page=501", authorized), 0).0);
+
+    let (status, body) = respond(&get("/apps/30", authorized), 0);
+    assert_eq!((200, 30), (status, body["steam_appid"].as_i64().unwrap()));
+    assert_eq!(404, respond(&get("/apps/20", authorized), 0).0);
+    assert_eq!(400, respond(&get("/apps/portal", authorized), 0).0);
+
+    let (status, body) = respond(&get("/search?q=Portal+2&limit=5", authorized), 0);
+    assert_eq!((200, 40, true), (status, body[0]["app_id"].as_i64().unwrap(), body[0]["has_details"].as_bool().unwrap()));
+    assert_eq!(400, respond(&get("/search?limit=5", authorized), 0).0);
+    assert_eq!(400, respond(&get("/search?q=portal&limit=0", authorized), 0).0);
+    assert_eq!(404, respond(&get("/", authorized), 0).0);
+    let post = Request { method: "POST".to_string(), ..get("/apps", authorized) };
+    assert_eq!(405, respond(&post, 0).0);
+}
@@ -0,0 +1,413 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::backup::BACKUP_DIRECTORY_ENVIRONMENT_VARIABLE;
+use crate::crypto_ext::algorithm::{KeyAlgorithm, KeyUsage};
+use crate::crypto_ext::key_store::KEY_STORE_ENVIRONMENT_VARIABLE;
+use crate::logging::{Level, LogFormat};
+
+#[cfg(test)]
+mod tests;
+
+pub const CONFIG_FILE_ENVIRONMENT_VARIABLE: &str = "CRAWLER_CONFIG";
+/// Read from the working directory if `CRAWLER_CONFIG` is not set and the file exists.
+pub const DEFAULT_CONFIG_FILENAME: &str = "crawler.toml";
+/// Printed in place of a configured token.
+const REDACTED_TOKEN: &str = "<set>";
+
+/// Crawler settings. Every field has a default, so the file only lists what differs.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub cache: CacheConfig,
+    pub retry: RetryConfig,
+    pub crawl: CrawlConfig,
+    pub export: ExportConfig,
+    pub encryption: EncryptionConfig,
+    pub shard: ShardConfig,
+    pub coordinator: CoordinatorConfig,
+    pub metrics: MetricsConfig,
+    pub logging: LoggingConfig,
+    pub serve: ServeConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Directory the `steam-webapi-cache` directory lives in, the working directory if absent.
+    /// Crawler changes into it on start, relative paths of the commands are resolved from it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_directory: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    pub delay_seconds: u64,
+    /// Attempts per app including the first one, 0 retries until the app is fetched.
+    pub max_attempts: u32,
+    /// Errors of the SDK not worth retrying, the app is skipped.
+    pub non_retryable_errors: Vec<String>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            delay_seconds: 60,
+            max_attempts: 0,
+            non_retryable_errors: vec![
+                "steampowered api returned failed response".to_string(),
+                "invalid utf-8 sequence".to_string(),
+            ],
+        }
+    }
+}
+
+impl RetryConfig {
+    pub fn is_retryable(&self, error: &str) -> bool {
+        !self.non_retryable_errors.iter().any(|non_retryable_error| non_retryable_error == error)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrawlConfig {
+    /// Number of apps fetched in parallel.
+    pub concurrency: usize,
+    /// Requests to the Steam API per minute shared by all workers, 0 is unlimited.
+    pub requests_per_minute: u32,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        CrawlConfig { concurrency: 1, requests_per_minute: 0 }
+    }
+}
+
+/// Defaults of `export-records` arguments.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipients_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_file: Option<String>,
+}
+
+/// The secret protecting the keys is never read from the file, only from `CRYPTO_EXT_SECRET`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// Key store directory, `$XDG_CONFIG_HOME` based default if absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_store_directory: Option<String>,
+    /// Algorithm of the first key of a new keyring and the default of `rotate-key`.
+    pub key_algorithm: KeyAlgorithm,
+    /// Backup directory, `$XDG_DATA_HOME` based default if absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup_directory: Option<String>,
+}
+
+/// Distributed crawling, workers sharing the work directory split the app list between them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShardConfig {
+    /// Directory shared by the workers, crawling is not sharded if absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_directory: Option<String>,
+    /// Apps per shard of a new generation.
+    pub shard_size: usize,
+    /// Lease duration, shard of a worker which stopped renewing it is reclaimed after it.
+    pub lease_seconds: u64,
+    /// Name of the worker in leases, hostname and PID if absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worker_id: Option<String>,
+}
+
+impl Default for ShardConfig {
+    fn default() -> Self {
+        ShardConfig { work_directory: None, shard_size: 1000, lease_seconds: 600, worker_id: None }
+    }
+}
+
+/// Coordinator serving the app list to worker processes over HTTP.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoordinatorConfig {
+    /// Address the coordinator listens on and the workers connect to.
+    pub address: String,
+    /// Apps leased to a worker at once.
+    pub batch_size: usize,
+    /// Lease duration, apps of a worker which stopped reporting are leased again after it.
+    pub lease_seconds: u64,
+    /// Bearer token the workers have to send, requests are not authenticated if absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl Default for CoordinatorConfig {
+    fn default() -> Self {
+        CoordinatorConfig { address: "127.0.0.1:8470".to_string(), batch_size: 50, lease_seconds: 300, token: None }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Address Prometheus metrics are served on at `/metrics`, not served if absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Least severe events written, error, warn, info or debug.
+    pub level: Level,
+    /// Text lines or one JSON object per line.
+    pub format: LogFormat,
+    /// Log file rotated by size, stdout and stderr if absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Size the log file is rotated at.
+    pub max_file_bytes: u64,
+    /// Rotated files kept next to the log file.
+    pub max_files: u32,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig { level: Level::Info, format: LogFormat::Text, file: None, max_file_bytes: 10 * 1024 * 1024, max_files: 5 }
+    }
+}
+
+/// Read API over the stored app details, run with the `serve` command.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServeConfig {
+    pub address: String,
+    /// Bearer token the clients have to send, requests are not authenticated if absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// `/health` reports the crawl stale once it processed no app for this long.
+    pub stale_after_seconds: u64,
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        ServeConfig { address: "127.0.0.1:8480".to_string(), token: None, stale_after_seconds: 3600 }
+    }
+}
+
+/// Environment variable and the setting it overrides. Key store and backup
+/// directories keep the variables they were configured with before the file existed.
+pub const ENVIRONMENT_OVERRIDES: [(&str, &str); 28] = [
+    ("CRAWLER_CACHE_BASE_DIRECTORY", "cache.base_directory"),
+    ("CRAWLER_RETRY_DELAY_SECONDS", "retry.delay_seconds"),
+    ("CRAWLER_RETRY_MAX_ATTEMPTS", "retry.max_attempts"),
+    ("CRAWLER_RETRY_NON_RETRYABLE_ERRORS", "retry.non_retryable_errors"),
+    ("CRAWLER_CRAWL_CONCURRENCY", "crawl.concurrency"),
+    ("CRAWLER_CRAWL_REQUESTS_PER_MINUTE", "crawl.requests_per_minute"),
+    ("CRAWLER_EXPORT_RECIPIENTS_FILE", "export.recipients_file"),
+    ("CRAWLER_EXPORT_OUTPUT_FILE", "export.output_file"),
+    (KEY_STORE_ENVIRONMENT_VARIABLE, "encryption.key_store_directory"),
+    ("CRAWLER_ENCRYPTION_KEY_ALGORITHM", "encryption.key_algorithm"),
+    (BACKUP_DIRECTORY_ENVIRONMENT_VARIABLE, "encryption.backup_directory"),
+    ("CRAWLER_SHARD_WORK_DIRECTORY", "shard.work_directory"),
+    ("CRAWLER_SHARD_SHARD_SIZE", "shard.shard_size"),
+    ("CRAWLER_SHARD_LEASE_SECONDS", "shard.lease_seconds"),
+    ("CRAWLER_SHARD_WORKER_ID", "shard.worker_id"),
+    ("CRAWLER_COORDINATOR_ADDRESS", "coordinator.address"),
+    ("CRAWLER_COORDINATOR_BATCH_SIZE", "coordinator.batch_size"),
+    ("CRAWLER_COORDINATOR_LEASE_SECONDS", "coordinator.lease_seconds"),
+    ("CRAWLER_COORDINATOR_TOKEN", "coordinator.token"),
+    ("CRAWLER_METRICS_ADDRESS", "metrics.address"),
+    ("CRAWLER_LOGGING_LEVEL", "logging.level"),
+    ("CRAWLER_LOGGING_FORMAT", "logging.format"),
+    ("CRAWLER_LOGGING_FILE", "logging.file"),
+    ("CRAWLER_LOGGING_MAX_FILE_BYTES", "logging.max_file_bytes"),
+    ("CRAWLER_LOGGING_MAX_FILES", "logging.max_files"),
+    ("CRAWLER_SERVE_ADDRESS", "serve.address"),
+    ("CRAWLER_SERVE_TOKEN", "serve.token"),
+    ("CRAWLER_SERVE_STALE_AFTER_SECONDS", "serve.stale_after_seconds"),
+];
+
+impl Config {
+    /// Reads the file named by `CRAWLER_CONFIG`, or `crawler.toml` if present, and
+    /// applies the environment overrides. Returns the configuration and its file.
+    pub fn from_environment() -> Result<(Config, Option<PathBuf>), String> {
+        let boxed_path = env::var(CONFIG_FILE_ENVIRONMENT_VARIABLE).ok().filter(|path| !path.is_empty());
+        let path = if let Some(path) = boxed_path {
+            Some(PathBuf::from(path))
+        } else if Path::new(DEFAULT_CONFIG_FILENAME).is_file() {
+            Some(PathBuf::from(DEFAULT_CONFIG_FILENAME))
+        } else {
+            None
+        };
+
+        let mut config = Config::default();
+        if let Some(path) = path.as_ref() {
+            let boxed_config = Config::read(path);
+            if boxed_config.is_err() {
+                return Err(boxed_config.err().unwrap());
+            }
+            config = boxed_config.unwrap();
+        }
+
+        let boxed_override = config.apply_overrides(|name| env::var(name).ok());
+        if boxed_override.is_err() {
+            return Err(boxed_override.err().unwrap());
+        }
+        let boxed_validate = config.validate();
+        if boxed_validate.is_err() {
+            return Err(boxed_validate.err().unwrap());
+        }
+
+        Ok((config, path))
+    }
+
+    pub fn read(path: &Path) -> Result<Config, String> {
+        let boxed_content = fs::read_to_string(path);
+        if boxed_content.is_err() {
+            let message = format!("unable to read configuration {}: {}", path.display(), boxed_content.err().unwrap());
+            return Err(message)
+        }
+        Config::parse(boxed_content.unwrap().as_str())
+    }
+
+    pub fn parse(content: &str) -> Result<Config, String> {
+        let boxed_config = toml::from_str(content);
+        if boxed_config.is_err() {
+            let message = format!("unable to parse configuration: {}", boxed_config.err().unwrap());
+            return Err(message)
+        }
+        Ok(boxed_config.unwrap())
+    }
+
+    /// Overrides the settings with the variables set, see `ENVIRONMENT_OVERRIDES`.
+    /// Lists are comma separated, empty variables are ignored.
+    pub fn apply_overrides<F: Fn(&str) -> Option<String>>(&mut self, get_variable: F) -> Result<(), String> {
+        for (name, setting) in ENVIRONMENT_OVERRIDES {
+            let boxed_value = get_variable(name);
+            if boxed_value.is_none() || boxed_value.as_ref().unwrap().trim().is_empty() {
+                continue;
+            }
+            let value = boxed_value.unwrap().trim().to_string();
+
+            let boxed_apply = self.set(setting, value.as_str());
+            if boxed_apply.is_err() {
+                let message = format!("invalid {} environment variable: {}", name, boxed_apply.err().unwrap());
+                return Err(message)
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.crawl.concurrency == 0 {
+            return Err("crawl.concurrency must be at least 1".to_string());
+        }
+        if self.shard.shard_size == 0 {
+            return Err("shard.shard_size must be at least 1".to_string());
+        }
+        if self.shard.lease_seconds == 0 {
+            return Err("shard.lease_seconds must be at least 1".to_string());
+        }
+        if self.coordinator.batch_size == 0 {
+            return Err("coordinator.batch_size must be at least 1".to_string());
+        }
+        if self.coordinator.lease_seconds == 0 {
+            return Err("coordinator.lease_seconds must be at least 1".to_string());
+        }
+        if self.logging.max_file_bytes == 0 {
+            return Err("logging.max_file_bytes must be at least 1".to_string());
+        }
+        // the first key signs the cache integrity manifest
+        if !self.encryption.key_algorithm.supports(KeyUsage::Signing) {
+            let message = format!("encryption.key_algorithm {} can not sign, use rsa4096 or ed25519", self.encryption.key_algorithm);
+            return Err(message)
+        }
+        Ok(())
+    }
+
+    /// Tokens are printed as `<set>`, the output of `config check` ends up in logs and tickets.
+    pub fn to_toml(&self) -> Result<String, String> {
+        let mut config = self.clone();
+        config.coordinator.token = config.coordinator.token.map(|_| REDACTED_TOKEN.to_string());
+        config.serve.token = config.serve.token.map(|_| REDACTED_TOKEN.to_string());
+        let boxed_serialized = toml::to_string(&config);
+        if boxed_serialized.is_err() {
+            let message = format!("unable to serialize configuration: {}", boxed_serialized.err().unwrap());
+            return Err(message)
+        }
+        Ok(boxed_serialized.unwrap())
+    }
+
+    fn set(&mut self, setting: &str, value: &str) -> Result<(), String> {
+        match setting {
+            "cache.base_directory" => self.cache.base_directory = Some(value.to_string()),
+            "retry.non_retryable_errors" => {
+                self.retry.non_retryable_errors = value.split(',').map(|error| error.trim().to_string()).filter(|error| !error.is_empty()).collect();
+            }
+            "export.recipients_file" => self.export.recipients_file = Some(value.to_string()),
+            "export.output_file" => self.export.output_file = Some(value.to_string()),
+            "encryption.key_store_directory" => self.encryption.key_store_directory = Some(value.to_string()),
+            "encryption.backup_directory" => self.encryption.backup_directory = Some(value.to_string()),
+            "shard.work_directory" => self.shard.work_directory = Some(value.to_string()),
+            "shard.worker_id" => self.shard.worker_id = Some(value.to_string()),
+            "coordinator.address" => self.coordinator.address = value.to_string(),
+            "coordinator.token" => self.coordinator.token = Some(value.to_string()),
+            "metrics.address" => self.metrics.address = Some(value.to_string()),
+            "serve.address" => self.serve.address = value.to_string(),
+            "serve.token" => self.serve.token = Some(value.to_string()),
+            "logging.file" => self.logging.file = Some(value.to_string()),
+            "logging.level" => {
+                let boxed_level = value.parse::<Level>();
+                if boxed_level.is_err() {
+                    return Err(boxed_level.err().unwrap());
+                }
+                self.logging.level = boxed_level.unwrap();
+            }
+            "logging.format" => {
+                let boxed_format = value.parse::<LogFormat>();
+                if boxed_format.is_err() {
+                    return Err(boxed_format.err().unwrap());
+                }
+                self.logging.format = boxed_format.unwrap();
+            }
+            "encryption.key_algorithm" => {
+                let boxed_algorithm = value.parse::<KeyAlgorithm>();
+                if boxed_algorithm.is_err() {
+                    return Err(boxed_algorithm.err().unwrap());
+                }
+                self.encryption.key_algorithm = boxed_algorithm.unwrap();
+            }
+            _ => {
+                let boxed_number = value.parse::<u64>();
+                if boxed_number.is_err() {
+                    return Err(format!("{} is not a valid number", value));
+                }
+                let number = boxed_number.unwrap();
+                let is_too_large = match setting {
+                    "retry.delay_seconds" => { self.retry.delay_seconds = number; false }
+                    "retry.max_attempts" => { self.retry.max_attempts = number as u32; number > u32::MAX as u64 }
+                    "crawl.concurrency" => { self.crawl.concurrency = number as usize; false }
+                    "crawl.requests_per_minute" => { self.crawl.requests_per_minute = number as u32; number > u32::MAX as u64 }
+                    "shard.shard_size" => { self.shard.shard_size = number as usize; false }
+                    "shard.lease_seconds" => { self.shard.lease_seconds = number; false }
+                    "coordinator.batch_size" => { self.coordinator.batch_size = number as usize; false }
+                    "coordinator.lease_seconds" => { self.coordinator.lease_seconds = number; false }
+                    "logging.max_file_bytes" => { self.logging.max_file_bytes = number; false }
+                    "logging.max_files" => { self.logging.max_files = number as u32; number > u32::MAX as u64 }
+                    "serve.stale_after_seconds" => { self.serve.stale_after_seconds = number; false }
+                    _ => return Err(format!("unknown setting {}", setting)),
+                };
+                if is_too_large {
+                    return Err(format!("{} is too large", value));
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use crate::config::{Config, CrawlConfig};
+use crate::crypto_ext::algorithm::KeyAlgorithm;
+
+#[test]
+fn defaults() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(Config::default(), config);
+    assert_eq!(60, config.retry.delay_seconds);
+    assert_eq!(CrawlConfig { concurrency: 1, requests_per_minute: 0 }, config.crawl);
+    assert!(!config.retry.is_retryable("invalid utf-8 sequence"));
+    assert!(config.retry.is_retryable("no response from API"));
+    assert!(config.validate().is_ok());
+
+    // effective configuration printed by `config check` parses back
+    assert_eq!(config, Config::parse(config.to_toml().unwrap().as_str()).unwrap());
+}
+
+#[test]
+fn file_and_overrides() {
+    let mut config = Config::parse(r#"
+        [retry]
+        delay_seconds = 5
+        non_retryable_errors = ["invalid utf-8 sequence"]
+
+        [crawl]
+        concurrency = 4
+
+        [encryption]
+        key_algorithm = "ed25519"
+    "#).unwrap();
+    assert_eq!(5, config.retry.delay_seconds);
+    assert_eq!(KeyAlgorithm::Ed25519, config.encryption.key_algorithm);
+    assert!(config.retry.is_retryable("steampowered api returned failed response"));
+
+    let variables: HashMap<&str, &str> = [
+        ("CRAWLER_CRAWL_CONCURRENCY", "8"),
+        ("CRAWLER_RETRY_NON_RETRYABLE_ERRORS", "a, b,"),
+        ("CRAWLER_EXPORT_OUTPUT_FILE", "export.jsonl"),
+        ("CRYPTO_EXT_KEY_STORE", "/var/lib/crawler/keys"),
+        ("CRAWLER_RETRY_DELAY_SECONDS", ""),
+        ("CRAWLER_SERVE_STALE_AFTER_SECONDS", "120"),
+        ("CRAWLER_COORDINATOR_TOKEN", "coordinator-secret"),
+        ("CRAWLER_SERVE_TOKEN", "serve-secret"),
+    ].into_iter().collect();
+    config.apply_overrides(|name| variables.get(name).map(|value| value.to_string())).unwrap();
+    assert_eq!(8, config.crawl.concurrency);
+    assert_eq!(5, config.retry.delay_seconds);
+    assert_eq!(vec!["a".to_string(), "b".to_string()], config.retry.non_retryable_errors);
+    assert_eq!(Some("export.jsonl".to_string()), config.export.output_file);
+    assert_eq!(Some("/var/lib/crawler/keys".to_string()), config.encryption.key_store_directory);
+    assert_eq!(120, config.serve.stale_after_seconds);
+
+    // tokens are not printed by `config check`
+    let toml = config.to_toml().unwrap();
+    assert_eq!(Some("coordinator-secret".to_string()), config.coordinator.token);
+    assert!(!toml.contains("coordinator-secret"));
+    assert!(!toml.contains("serve-secret"));
+    assert_eq!(2, toml.matches("token = \"<set>\"").count());
+}
+
+#[test]
+fn invalid_configuration() {
+    assert!(Config::parse("[crawl]\nthreads = 4").is_err());
+    assert!(Config::parse("[retry]\ndelay_seconds = \"soon\"").is_err());
+
+    let mut config = Config::default();
+    let error = config.apply_overrides(|name| if name == "CRAWLER_CRAWL_CONCURRENCY" { Some("many".to_string()) } else { None }).err().unwrap();
+    assert_eq!("invalid CRAWLER_CRAWL_CONCURRENCY environment variable: many is not a valid number", error);
+
+    config.crawl.concurrency = 0;
+    assert!(config.validate().is_err());
+    config.crawl.concurrency = 1;
+    config.encryption.key_algorithm = KeyAlgorithm::X25519;
+    assert!(config.validate().is_err());
+}
@@ -0,0 +1,348 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use openssl::memcmp;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::coordinator::{Coordinator, FailureReport, HeartbeatRequest, HeartbeatResponse, LeaseRequest, LeaseResponse, SuccessReport};
+use crate::crawler::{FetchEvent, IDLE_INTERVAL};
+use crate::crypto_ext::keyring::get_unix_timestamp;
+use crate::logging;
+
+/// Request bodies carry app details documents, a few megabytes at most.
+pub const MAX_BODY_LENGTH: usize = 16 * 1024 * 1024;
+/// Request line and headers are short, longer lines are rejected before they are buffered.
+pub const MAX_HEAD_LINE_LENGTH: usize = 8 * 1024;
+pub const MAX_HEADER_COUNT: usize = 100;
+/// Connection silent for longer is dropped, the coordinator serves one at a time.
+pub const IO_TIMEOUT: Duration = Duration::from_secs(30);
+/// Listener is polled, a blocked accept would not notice the crawl being stopped.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Header names are compared case-insensitively.
+    pub fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Requests are let through without a token configured.
+    pub fn is_authorized(&self, token: Option<&str>) -> bool {
+        if token.is_none() {
+            return true;
+        }
+        let expected = ["Bearer ", token.unwrap()].join("");
+        let boxed_authorization = self.get_header("Authorization");
+        boxed_authorization.is_some()
+            && boxed_authorization.unwrap().len() == expected.len()
+            && memcmp::eq(boxed_authorization.unwrap().as_bytes(), expected.as_bytes())
+    }
+}
+
+/// Reads a HTTP/1.1 request with its body, chunked bodies are not supported.
+pub fn read_request(stream: &TcpStream) -> Result<Request, String> {
+    let mut reader = BufReader::new(stream);
+    let boxed_request_line = read_head_line(&mut reader);
+    if boxed_request_line.is_err() {
+        return Err(boxed_request_line.err().unwrap());
+    }
+    let request_line = boxed_request_line.unwrap();
+    let parts: Vec<&str> = request_line.split_whitespace().collect();
+    if parts.len() != 3 || !parts[2].starts_with("HTTP/1.") {
+        return Err(format!("malformed request line: {}", request_line.trim()));
+    }
+
+    let mut headers: Vec<(String, String)> = vec![];
+    loop {
+        let boxed_line = read_head_line(&mut reader);
+        if boxed_line.is_err() {
+            return Err(boxed_line.err().unwrap());
+        }
+        let line = boxed_line.unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADER_COUNT {
+            return Err(format!("request with more than {} headers is too large", MAX_HEADER_COUNT));
+        }
+        let boxed_separator = line.find(':');
+        if boxed_separator.is_none() {
+            return Err(format!("malformed header: {}", line));
+        }
+        let (name, value) = line.split_at(boxed_separator.unwrap());
+        headers.push((name.trim().to_string(), value[1..].trim().to_string()));
+    }
+
+    let mut request = Request {
+        method: parts[0].to_string(),
+        path: parts[1].to_string(),
+        headers,
+        body: vec![],
+    };
+
+    let content_length = request.get_header("Content-Length").unwrap_or("0").parse::<usize>().unwrap_or(0);
+    if content_length > MAX_BODY_LENGTH {
+        return Err(format!("request body of {} bytes is too large", content_length));
+    }
+    request.body = vec![0; content_length];
+    let boxed_read = reader.read_exact(&mut request.body);
+    if boxed_read.is_err() {
+        return Err(format!("unable to read request body: {}", boxed_read.err().unwrap()));
+    }
+    Ok(request)
+}
+
+fn read_head_line<R: BufRead>(reader: &mut R) -> Result<String, String> {
+    let mut line = String::new();
+    let boxed_read = reader.take(MAX_HEAD_LINE_LENGTH as u64 + 1).read_line(&mut line);
+    if boxed_read.is_err() {
+        return Err(format!("unable to read request: {}", boxed_read.err().unwrap()));
+    }
+    if line.len() > MAX_HEAD_LINE_LENGTH {
+        return Err(format!("request line or header of more than {} bytes is too large", MAX_HEAD_LINE_LENGTH));
+    }
+    Ok(line)
+}
+
+pub fn write_response(mut stream: &TcpStream, status: u16, content_type: &str, body: &[u8]) -> Result<(), String> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let head = format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, reason, content_type, body.len());
+    let boxed_write = stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(body)).and_then(|_| stream.flush());
+    if boxed_write.is_err() {
+        return Err(format!("unable to write response: {}", boxed_write.err().unwrap()));
+    }
+    Ok(())
+}
+
+pub fn write_json_response<T: Serialize>(stream: &TcpStream, status: u16, value: &T) -> Result<(), String> {
+    let body = serde_json::to_string(value).unwrap_or_else(|_| "{}".to_string());
+    write_response(stream, status, "application/json", body.as_bytes())
+}
+
+/// Serves the work queue until every app was reported or `on_event` returns false.
+/// Reports become events of the crawl passed to `on_event`, `FetchEvent::Idle` is passed
+/// while no worker connects. Documents of fetched apps are passed to `on_document`.
+///
+/// `POST /lease` `LeaseRequest` → `LeaseResponse`, `POST /success` `SuccessReport`,
+/// `POST /failure` `FailureReport`, `POST /heartbeat` `HeartbeatRequest` → `HeartbeatResponse`,
+/// `GET /status` → `CoordinatorStatus`. With a token, requests need `Authorization: Bearer <token>`.
+pub fn serve<E, D>(listener: &TcpListener, coordinator: &mut Coordinator, token: Option<&str>, mut on_event: E, mut on_document: D) -> Result<(), String>
+    where E: FnMut(FetchEvent) -> bool, D: FnMut(i64, &str) -> Result<(), String> {
+    let boxed_nonblocking = listener.set_nonblocking(true);
+    if boxed_nonblocking.is_err() {
+        return Err(format!("unable to poll the listener: {}", boxed_nonblocking.err().unwrap()));
+    }
+
+    let mut is_running = true;
+    let mut idle_since = Instant::now();
+    while is_running && !coordinator.is_finished() {
+        let boxed_stream = listener.accept();
+        if boxed_stream.is_err() {
+            let error = boxed_stream.err().unwrap();
+            if error.kind() != ErrorKind::WouldBlock {
+                logging::warn("unable to accept connection", &[("error", serde_json::json!(error.to_string()))]);
+            }
+            if idle_since.elapsed() >= IDLE_INTERVAL {
+                is_running = on_event(FetchEvent::Idle);
+                idle_since = Instant::now();
+            }
+            thread::sleep(ACCEPT_INTERVAL);
+            continue;
+        }
+        let (stream, _) = boxed_stream.unwrap();
+        let _ = stream.set_nonblocking(false);
+        let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+
+        let boxed_request = read_request(&stream);
+        if boxed_request.is_err() {
+            let error = boxed_request.err().unwrap();
+            let status = if error.ends_with("is too large") { 413 } else { 400 };
+            let _ = write_json_response(&stream, status, &serde_json::json!({ "error": error }));
+            continue;
+        }
+        let request = boxed_request.unwrap();
+        if !request.is_authorized(token) {
+            let _ = write_json_response(&stream, 401, &serde_json::json!({ "error": "missing or wrong token" }));
+            continue;
+        }
+
+        let (status, body) = handle_request(&request, coordinator, get_unix_timestamp(), &mut |event| {
+            is_running = on_event(event) && is_running;
+        }, &mut on_document);
+        idle_since = Instant::now();
+        let boxed_write = write_response(&stream, status, "application/json", body.as_bytes());
+        if boxed_write.is_err() {
+            logging::warn("unable to answer the worker", &[("path", serde_json::json!(request.path)), ("status", serde_json::json!(status)), ("error", serde_json::json!(boxed_write.err().unwrap()))]);
+        }
+    }
+    Ok(())
+}
+
+fn handle_request<E, D>(request: &Request, coordinator: &mut Coordinator, now: u64, on_event: &mut E, on_document: &mut D) -> (u16, String)
+    where E: FnMut(FetchEvent), D: FnMut(i64, &str) -> Result<(), String> {
+    let route = (request.method.as_str(), request.path.as_str());
+    let events = match route {
+        ("GET", "/status") => {
+            return (200, serde_json::to_string(&coordinator.get_status(now)).unwrap_or_default());
+        }
+        ("POST", "/lease") => {
+            let boxed_lease: Result<LeaseRequest, _> = serde_json::from_slice(&request.body);
+            if boxed_lease.is_err() {
+                return get_error_response(400, boxed_lease.err().unwrap().to_string());
+            }
+            let response = coordinator.lease_batch(&boxed_lease.unwrap(), now);
+            return (200, serde_json::to_string(&response).unwrap_or_default());
+        }
+        ("POST", "/heartbeat") => {
+            let boxed_heartbeat: Result<HeartbeatRequest, _> = serde_json::from_slice(&request.body);
+            if boxed_heartbeat.is_err() {
+                return get_error_response(400, boxed_heartbeat.err().unwrap().to_string());
+            }
+            let response = coordinator.heartbeat(&boxed_heartbeat.unwrap(), now);
+            return (200, serde_json::to_string(&response).unwrap_or_default());
+        }
+        ("POST", "/success") => {
+            let boxed_report: Result<SuccessReport, _> = serde_json::from_slice(&request.body);
+            if boxed_report.is_err() {
+                return get_error_response(400, boxed_report.err().unwrap().to_string());
+            }
+            let report = boxed_report.unwrap();
+            // document is stored before the app is marked processed, an app whose
+            // document could not be stored goes back to the queue to be fetched again
+            let boxed_document = report.document.as_ref().filter(|_| coordinator.is_outstanding(report.app_id));
+            if let Some(document) = boxed_document {
+                let boxed_store = on_document(report.app_id, document);
+                if boxed_store.is_err() {
+                    let error = boxed_store.err().unwrap();
+                    logging::error("unable to store reported app details, the app is fetched again", &[
+                        ("app_id", serde_json::json!(report.app_id)), ("worker_id", serde_json::json!(report.worker_id)), ("error", serde_json::json!(error)),
+                    ]);
+                    coordinator.release(report.app_id);
+                    return get_error_response(500, error);
+                }
+            }
+            coordinator.report_success(&report, now)
+        }
+        ("POST", "/failure") => {
+            let boxed_report: Result<FailureReport, _> = serde_json::from_slice(&request.body);
+            if boxed_report.is_err() {
+                return get_error_response(400, boxed_report.err().unwrap().to_string());
+            }
+            coordinator.report_failure(&boxed_report.unwrap(), now)
+        }
+        (_, "/status") | (_, "/lease") | (_, "/heartbeat") | (_, "/success") | (_, "/failure") => {
+            return get_error_response(405, format!("{} is not allowed", request.method));
+        }
+        _ => {
+            return get_error_response(404, format!("{} not found", request.path));
+        }
+    };
+
+    for event in events {
+        on_event(event);
+    }
+    (200, "{}".to_string())
+}
+
+fn get_error_response(status: u16, error: String) -> (u16, String) {
+    (status, serde_json::json!({ "error": error }).to_string())
+}
+
+/// Worker side of the protocol, one connection per request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoordinatorClient {
+    address: String,
+    token: Option<String>,
+}
+
+impl CoordinatorClient {
+    pub fn new(address: &str, token: Option<&str>) -> CoordinatorClient {
+        CoordinatorClient { address: address.to_string(), token: token.map(|token| token.to_string()) }
+    }
+
+    pub fn lease(&self, request: &LeaseRequest) -> Result<LeaseResponse, String> {
+        self.post("/lease", request)
+    }
+
+    pub fn report_success(&self, report: &SuccessReport) -> Result<(), String> {
+        self.post::<SuccessReport, serde_json::Value>("/success", report).map(|_| ())
+    }
+
+    pub fn report_failure(&self, report: &FailureReport) -> Result<(), String> {
+        self.post::<FailureReport, serde_json::Value>("/failure", report).map(|_| ())
+    }
+
+    pub fn heartbeat(&self, request: &HeartbeatRequest) -> Result<HeartbeatResponse, String> {
+        self.post("/heartbeat", request)
+    }
+
+    fn post<T: Serialize, R: DeserializeOwned>(&self, path: &str, body: &T) -> Result<R, String> {
+        let boxed_body = serde_json::to_string(body);
+        if boxed_body.is_err() {
+            return Err(format!("unable to serialize request: {}", boxed_body.err().unwrap()));
+        }
+        let body = boxed_body.unwrap();
+
+        let boxed_stream = TcpStream::connect(self.address.as_str());
+        if boxed_stream.is_err() {
+            return Err(format!("unable to connect to coordinator {}: {}", self.address, boxed_stream.err().unwrap()));
+        }
+        let mut stream = boxed_stream.unwrap();
+        let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+
+        let mut head = format!("POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n", path, self.address, body.len());
+        if let Some(token) = self.token.as_ref() {
+            head = [head.as_str(), "Authorization: Bearer ", token, "\r\n"].join("");
+        }
+        head.push_str("\r\n");
+        let boxed_write = stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(body.as_bytes()));
+        if boxed_write.is_err() {
+            return Err(format!("unable to send request to coordinator: {}", boxed_write.err().unwrap()));
+        }
+
+        let mut response: Vec<u8> = vec![];
+        let boxed_read = stream.read_to_end(&mut response);
+        if boxed_read.is_err() {
+            return Err(format!("unable to read response of coordinator: {}", boxed_read.err().unwrap()));
+        }
+        let response = String::from_utf8_lossy(&response).to_string();
+        let boxed_head_end = response.find("\r\n\r\n");
+        if boxed_head_end.is_none() {
+            return Err("malformed response of coordinator".to_string());
+        }
+        let (response_head, response_body) = response.split_at(boxed_head_end.unwrap());
+        let response_body = &response_body[4..];
+
+        let status = response_head.split_whitespace().nth(1).unwrap_or("").to_string();
+        if status != "200" {
+            return Err(format!("coordinator responded {}: {}", status, response_body));
+        }
+        let boxed_value = serde_json::from_str(response_body);
+        if boxed_value.is_err() {
+            return Err(format!("unable to parse response of coordinator: {}", boxed_value.err().unwrap()));
+        }
+        Ok(boxed_value.unwrap())
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{HashMap, VecDeque};
+use serde::{Deserialize, Serialize};
+use crate::crawler::FetchEvent;
+
+pub mod http;
+#[cfg(test)]
+mod tests;
+
+/// Seconds a worker waits before asking again when every pending app is leased.
+pub const RETRY_AFTER_SECONDS: u64 = 5;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LeaseRequest {
+    pub worker_id: String,
+    pub max_apps: usize,
+}
+
+/// Empty batch with `finished` unset means every app left is leased to other workers.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LeaseResponse {
+    pub app_ids: Vec<i64>,
+    pub lease_seconds: u64,
+    pub retry_after_seconds: u64,
+    pub finished: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SuccessReport {
+    pub worker_id: String,
+    pub app_id: i64,
+    pub name: String,
+    /// App details document cached by the worker, stored in the coordinator cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FailureReport {
+    pub worker_id: String,
+    pub app_id: i64,
+    pub error: String,
+    /// Worker retries the app itself, the app is given up on if unset.
+    pub will_retry: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HeartbeatRequest {
+    pub worker_id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HeartbeatResponse {
+    /// Apps leased to the worker, their leases were extended.
+    pub app_ids: Vec<i64>,
+    pub lease_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoordinatorStatus {
+    pub total: usize,
+    pub pending: usize,
+    pub leased: usize,
+    pub fetched: usize,
+    pub failed: usize,
+    pub workers: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct AppLease {
+    worker_id: String,
+    expires_at: u64,
+}
+
+/// Work queue of the coordinator. Apps are leased to workers in batches, an app
+/// whose lease expired without a report goes back to the queue. Reports are turned
+/// into the events of a local crawl, so the coordinator keeps the progress exactly
+/// like a single crawler does. Time is passed in to keep the state testable.
+#[derive(Debug)]
+pub struct Coordinator {
+    pending: VecDeque<i64>,
+    leases: HashMap<i64, AppLease>,
+    lease_seconds: u64,
+    total: usize,
+    fetched: usize,
+    failed: usize,
+    workers: HashMap<String, u64>,
+}
+
+impl Coordinator {
+    pub fn new(app_ids: Vec<i64>, lease_seconds: u64) -> Coordinator {
+        Coordinator {
+            total: app_ids.len(),
+            pending: app_ids.into_iter().collect(),
+            leases: HashMap::new(),
+            lease_seconds,
+            fetched: 0,
+            failed: 0,
+            workers: HashMap::new(),
+        }
+    }
+
+    /// Every app was reported fetched or given up on.
+    pub fn is_finished(&self) -> bool {
+        self.pending.is_empty() && self.leases.is_empty()
+    }
+
+    pub fn lease_batch(&mut self, request: &LeaseRequest, now: u64) -> LeaseResponse {
+        self.workers.insert(request.worker_id.to_string(), now);
+        self.reclaim_expired(now);
+
+        let mut app_ids: Vec<i64> = vec![];
+        while app_ids.len() < request.max_apps.max(1) {
+            let boxed_app_id = self.pending.pop_front();
+            if boxed_app_id.is_none() {
+                break;
+            }
+            let app_id = boxed_app_id.unwrap();
+            self.leases.insert(app_id, AppLease { worker_id: request.worker_id.to_string(), expires_at: now + self.lease_seconds });
+            app_ids.push(app_id);
+        }
+
+        LeaseResponse {
+            app_ids,
+            lease_seconds: self.lease_seconds,
+            retry_after_seconds: RETRY_AFTER_SECONDS,
+            finished: self.is_finished(),
+        }
+    }
+
+    /// Fetched app is done with whoever holds its lease, a late report of an
+    /// expired lease still counts. Repeated reports are ignored.
+    pub fn report_success(&mut self, report: &SuccessReport, now: u64) -> Vec<FetchEvent> {
+        self.workers.insert(report.worker_id.to_string(), now);
+        if !self.take(report.app_id) {
+            return vec![];
+        }
+        self.fetched += 1;
+        vec![
+            FetchEvent::Fetched { app_id: report.app_id, name: report.name.to_string() },
+            FetchEvent::Finished { app_id: report.app_id },
+        ]
+    }
+
+    pub fn report_failure(&mut self, report: &FailureReport, now: u64) -> Vec<FetchEvent> {
+        self.workers.insert(report.worker_id.to_string(), now);
+        if !self.is_outstanding(report.app_id) {
+            return vec![];
+        }
+
+        let mut events = vec![FetchEvent::Failed { app_id: report.app_id, error: report.error.to_string() }];
+        if report.will_retry {
+            events.push(FetchEvent::Retried { app_id: report.app_id });
+            self.extend_lease(report.app_id, report.worker_id.as_str(), now);
+        } else {
+            self.take(report.app_id);
+            self.failed += 1;
+            events.push(FetchEvent::Finished { app_id: report.app_id });
+        }
+        events
+    }
+
+    /// App is leased or waits in the queue, it was not reported done with yet.
+    pub fn is_outstanding(&self, app_id: i64) -> bool {
+        self.leases.contains_key(&app_id) || self.pending.contains(&app_id)
+    }
+
+    /// Puts a leased app back to the queue, the report of its worker could not be taken.
+    pub fn release(&mut self, app_id: i64) {
+        if self.leases.remove(&app_id).is_some() {
+            self.pending.push_back(app_id);
+        }
+    }
+
+    /// Extends the leases of every app held by the worker.
+    pub fn heartbeat(&mut self, request: &HeartbeatRequest, now: u64) -> HeartbeatResponse {
+        self.workers.insert(request.worker_id.to_string(), now);
+        let mut app_ids: Vec<i64> = self.leases.iter()
+            .filter(|(_, lease)| lease.worker_id == request.worker_id)
+            .map(|(app_id, _)| *app_id)
+            .collect();
+        app_ids.sort();
+        for app_id in app_ids.iter() {
+            self.extend_lease(*app_id, request.worker_id.as_str(), now);
+        }
+        HeartbeatResponse { app_ids, lease_seconds: self.lease_seconds }
+    }
+
+    /// Workers seen within the last two leases count as active.
+    pub fn get_status(&self, now: u64) -> CoordinatorStatus {
+        CoordinatorStatus {
+            total: self.total,
+            pending: self.pending.len(),
+            leased: self.leases.len(),
+            fetched: self.fetched,
+            failed: self.failed,
+            workers: self.workers.values().filter(|last_seen| **last_seen + 2 * self.lease_seconds > now).count(),
+        }
+    }
+
+    fn reclaim_expired(&mut self, now: u64) {
+        let mut expired: Vec<i64> = self.leases.iter()
+            .filter(|(_, lease)| lease.expires_at <= now)
+            .map(|(app_id, _)| *app_id)
+            .collect();
+        expired.sort();
+        for app_id in expired {
+            self.leases.remove(&app_id);
+            self.pending.push_back(app_id);
+        }
+    }
+
+    fn extend_lease(&mut self, app_id: i64, worker_id: &str, now: u64) {
+        let boxed_lease = self.leases.get_mut(&app_id).filter(|lease| lease.worker_id == worker_id);
+        if let Some(lease) = boxed_lease {
+            lease.expires_at = now + self.lease_seconds;
+        }
+    }
+
+    /// Removes the app from the queue, returns false if it was done with already.
+    fn take(&mut self, app_id: i64) -> bool {
+        if self.leases.remove(&app_id).is_some() {
+            return true;
+        }
+        let boxed_position = self.pending.iter().position(|pending_app_id| *pending_app_id == app_id);
+        if boxed_position.is_none() {
+            return false;
+        }
+        self.pending.remove(boxed_position.unwrap());
+        true
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use crate::coordinator::{Coordinator, FailureReport, HeartbeatRequest, LeaseRequest, SuccessReport};
+use crate::coordinator::http::{CoordinatorClient, MAX_HEAD_LINE_LENGTH, serve};
+use crate::crawler::FetchEvent;
+
+fn lease(coordinator: &mut Coordinator, worker_id: &str, max_apps: usize, now: u64) -> Vec<i64> {
+    coordinator.lease_batch(&LeaseRequest { worker_id: worker_id.to_string(), max_apps }, now).app_ids
+}
+
+fn success(worker_id: &str, app_id: i64) -> SuccessReport {
+    SuccessReport { worker_id: worker_id.to_string(), app_id, name: format!("app {}", app_id), document: None }
+}
+
+#[test]
+fn work_queue() {
+    let mut coordinator = Coordinator::new(vec![1, 2, 3, 4, 5], 60);
+    assert_eq!(vec![1, 2], lease(&mut coordinator, "worker-1", 2, 0));
+    assert_eq!(vec![3, 4, 5], lease(&mut coordinator, "worker-2", 10, 0));
+    let response = coordinator.lease_batch(&LeaseRequest { worker_id: "worker-3".to_string(), max_apps: 10 }, 0);
+    assert!(response.app_ids.is_empty());
+    assert!(!response.finished);
+
+    let events = coordinator.report_success(&success("worker-1", 1), 10);
+    assert_eq!(vec![FetchEvent::Fetched { app_id: 1, name: "app 1".to_string() }, FetchEvent::Finished { app_id: 1 }], events);
+    assert!(coordinator.report_success(&success("worker-1", 1), 10).is_empty());
+
+    // retried app stays leased, given up app is done with
+    let failure = FailureReport { worker_id: "worker-1".to_string(), app_id: 2, error: "no response".to_string(), will_retry: true };
+    assert_eq!(2, coordinator.report_failure(&failure, 10).len());
+    let failure = FailureReport { will_retry: false, ..failure };
+    assert_eq!(FetchEvent::Finished { app_id: 2 }, coordinator.report_failure(&failure, 10)[1]);
+    assert!(coordinator.report_failure(&failure, 10).is_empty());
+
+    let status = coordinator.get_status(10);
+    assert_eq!((5, 0, 3, 1, 1, 3), (status.total, status.pending, status.leased, status.fetched, status.failed, status.workers));
+
+    // heartbeat keeps the leases of worker-2, apps of a silent worker go to the next one asking
+    let heartbeat = coordinator.heartbeat(&HeartbeatRequest { worker_id: "worker-2".to_string() }, 50);
+    assert_eq!(vec![3, 4, 5], heartbeat.app_ids);
+    assert!(lease(&mut coordinator, "worker-3", 10, 100).is_empty());
+    assert_eq!(vec![3, 4, 5], lease(&mut coordinator, "worker-3", 10, 110));
+
+    // late report of the expired lease still counts
+    assert_eq!(2, coordinator.report_success(&success("worker-2", 3), 111).len());
+    assert!(coordinator.report_success(&success("worker-3", 3), 112).is_empty());
+    coordinator.report_success(&success("worker-3", 4), 112);
+    assert!(!coordinator.is_finished());
+    coordinator.report_success(&success("worker-3", 5), 112);
+    assert!(coordinator.is_finished());
+    assert!(coordinator.lease_batch(&LeaseRequest { worker_id: "worker-3".to_string(), max_apps: 10 }, 112).finished);
+}
+
+#[test]
+fn http_protocol() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = thread::spawn(move || {
+        let mut coordinator = Coordinator::new(vec![10, 20], 60);
+        let mut events: Vec<FetchEvent> = vec![];
+        let mut documents: Vec<(i64, String)> = vec![];
+        serve(&listener, &mut coordinator, Some("secret"), |event| {
+            events.push(event);
+            true
+        }, |app_id, document| {
+            if document.is_empty() {
+                return Err(format!("unable to store app details of {}: disk is full", app_id));
+            }
+            documents.push((app_id, document.to_string()));
+            Ok(())
+        }).unwrap();
+        (events, documents)
+    });
+
+    // overlong header is rejected before it is read to the end
+    let mut stream = TcpStream::connect(address.as_str()).unwrap();
+    let head = ["POST /lease HTTP/1.1\r\nX-Padding: ", "a".repeat(MAX_HEAD_LINE_LENGTH).as_str(), "\r\n\r\n"].join("");
+    stream.write_all(head.as_bytes()).unwrap();
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    assert!(response.starts_with("HTTP/1.1 413"));
+
+    let intruder = CoordinatorClient::new(address.as_str(), Some("guess"));
+    let error = intruder.lease(&LeaseRequest { worker_id: "intruder".to_string(), max_apps: 10 }).err().unwrap();
+    assert!(error.starts_with("coordinator responded 401"));
+
+    let client = CoordinatorClient::new(address.as_str(), Some("secret"));
+    let response = client.lease(&LeaseRequest { worker_id: "worker".to_string(), max_apps: 10 }).unwrap();
+    assert_eq!(vec![10, 20], response.app_ids);
+    assert_eq!(vec![10, 20], client.heartbeat(&HeartbeatRequest { worker_id: "worker".to_string() }).unwrap().app_ids);
+
+    // app whose document could not be stored is leased again
+    let report = SuccessReport { document: Some("".to_string()), ..success("worker", 10) };
+    let error = client.report_success(&report).err().unwrap();
+    assert!(error.starts_with("coordinator responded 500"));
+    assert_eq!(vec![10], client.lease(&LeaseRequest { worker_id: "worker".to_string(), max_apps: 10 }).unwrap().app_ids);
+
+    let report = SuccessReport { document: Some("{\"name\":\"app 10\"}".to_string()), ..success("worker", 10) };
+    client.report_success(&report).unwrap();
+    let failure = FailureReport { worker_id: "worker".to_string(), app_id: 20, error: "gone".to_string(), will_retry: false };
+    client.report_failure(&failure).unwrap();
+
+    let (events, documents) = server.join().unwrap();
+    assert_eq!(vec![(10, "{\"name\":\"app 10\"}".to_string())], documents);
+    assert_eq!(4, events.len());
+    assert_eq!(FetchEvent::Finished { app_id: 20 }, events[3]);
+}
+
+#[test]
+fn stop_request() {
+    // serving stops once the caller returns false, also while no worker connects
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut coordinator = Coordinator::new(vec![30], 60);
+    let mut idle_count = 0;
+    serve(&listener, &mut coordinator, None, |event| {
+        assert_eq!(FetchEvent::Idle, event);
+        idle_count += 1;
+        false
+    }, |_, _| Ok(())).unwrap();
+    assert_eq!(1, idle_count);
+    assert!(!coordinator.is_finished());
+}
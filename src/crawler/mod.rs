@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::json;
+use crate::config::{CrawlConfig, RetryConfig};
+use crate::logging;
+use crate::metrics::get_error_class;
+
+#[cfg(test)]
+mod tests;
+
+/// Progress of the workers, handled on the thread which started the crawl.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FetchEvent {
+    Fetched { app_id: i64, name: String },
+    Failed { app_id: i64, error: String },
+    Retried { app_id: i64 },
+    /// App is done with, fetched or given up on, and can be marked as processed.
+    Finished { app_id: i64 },
+    /// No event for `IDLE_INTERVAL`, lets the caller act while the workers are busy.
+    Idle,
+}
+
+pub const IDLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Workers waiting for a retry notice a stop within this interval.
+pub const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Spaces requests of all workers evenly to stay under the limit.
+pub struct RateLimiter {
+    interval: Option<Duration>,
+    next_request: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// 0 requests per minute is unlimited.
+    pub fn new(requests_per_minute: u32) -> RateLimiter {
+        let interval = if requests_per_minute == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(60) / requests_per_minute)
+        };
+        RateLimiter { interval, next_request: Mutex::new(Instant::now()) }
+    }
+
+    /// Blocks until the caller may send the next request.
+    pub fn wait(&self) {
+        if self.interval.is_none() {
+            return;
+        }
+
+        let wait_until = {
+            let mut next_request = self.next_request.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let now = Instant::now();
+            let wait_until = if *next_request > now { *next_request } else { now };
+            *next_request = wait_until + self.interval.unwrap();
+            wait_until
+        };
+
+        let now = Instant::now();
+        if wait_until > now {
+            thread::sleep(wait_until - now);
+        }
+    }
+}
+
+/// Fetches the apps with `crawl.concurrency` workers following the retry policy.
+/// `fetch` returns the app name or the error message of the SDK. Events are passed
+/// to `on_event` on the calling thread, which returns when every app is finished.
+/// Returning false from `on_event` stops the workers once their current apps are done.
+pub fn fetch_apps<F, E>(app_ids: Vec<i64>, crawl: &CrawlConfig, retry: &RetryConfig, fetch: F, mut on_event: E)
+    where F: Fn(i64) -> Result<String, String> + Sync, E: FnMut(FetchEvent) -> bool {
+    let queue = Mutex::new(app_ids.into_iter().collect::<VecDeque<i64>>());
+    let is_stopped = AtomicBool::new(false);
+    let rate_limiter = RateLimiter::new(crawl.requests_per_minute);
+    let (sender, receiver) = mpsc::channel::<FetchEvent>();
+
+    thread::scope(|scope| {
+        for _ in 0..crawl.concurrency.max(1) {
+            let sender = sender.clone();
+            let queue = &queue;
+            let rate_limiter = &rate_limiter;
+            let fetch = &fetch;
+            let is_stopped = &is_stopped;
+            scope.spawn(move || {
+                loop {
+                    if is_stopped.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    let boxed_app_id = queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).pop_front();
+                    if boxed_app_id.is_none() {
+                        return;
+                    }
+                    fetch_app(boxed_app_id.unwrap(), retry, rate_limiter, fetch, &sender, is_stopped);
+                }
+            });
+        }
+        // receiver ends once every worker dropped its sender
+        drop(sender);
+
+        loop {
+            let boxed_event = receiver.recv_timeout(IDLE_INTERVAL);
+            let event = match boxed_event {
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => FetchEvent::Idle,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            if !on_event(event) {
+                is_stopped.store(true, Ordering::SeqCst);
+            }
+        }
+    });
+}
+
+fn fetch_app<F>(app_id: i64, retry: &RetryConfig, rate_limiter: &RateLimiter, fetch: &F, sender: &mpsc::Sender<FetchEvent>, is_stopped: &AtomicBool)
+    where F: Fn(i64) -> Result<String, String> {
+    let mut attempt: u32 = 1;
+    loop {
+        rate_limiter.wait();
+        let started_at = Instant::now();
+        let boxed_name = fetch(app_id);
+        let duration_ms = started_at.elapsed().as_millis() as u64;
+        if let Ok(name) = boxed_name {
+            logging::info("app details fetched", &[
+                ("app_id", json!(app_id)), ("name", json!(name)), ("attempt", json!(attempt)), ("duration_ms", json!(duration_ms)),
+            ]);
+            let _ = sender.send(FetchEvent::Fetched { app_id, name });
+            break;
+        }
+
+        let error = boxed_name.err().unwrap();
+        let is_retryable = retry.is_retryable(error.as_str());
+        let has_attempts_left = retry.max_attempts == 0 || attempt < retry.max_attempts;
+        let will_retry = is_retryable && has_attempts_left;
+        logging::warn("app details fetch failed", &[
+            ("app_id", json!(app_id)), ("attempt", json!(attempt)), ("duration_ms", json!(duration_ms)),
+            ("error", json!(error)), ("error_class", json!(get_error_class(error.as_str()))),
+            ("will_retry", json!(will_retry)), ("retry_in_seconds", json!(if will_retry { retry.delay_seconds } else { 0 })),
+        ]);
+        let _ = sender.send(FetchEvent::Failed { app_id, error });
+        if !will_retry {
+            break;
+        }
+
+        // app given up on for the stop is not finished, the next crawl fetches it again
+        if !wait_for_retry(Duration::from_secs(retry.delay_seconds), is_stopped) {
+            return;
+        }
+        let _ = sender.send(FetchEvent::Retried { app_id });
+        attempt += 1;
+    }
+    let _ = sender.send(FetchEvent::Finished { app_id });
+}
+
+/// Sleeps for the retry delay, returns false as soon as the crawl is stopped.
+fn wait_for_retry(delay: Duration, is_stopped: &AtomicBool) -> bool {
+    let retry_at = Instant::now() + delay;
+    loop {
+        if is_stopped.load(Ordering::SeqCst) {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= retry_at {
+            return true;
+        }
+        thread::sleep((retry_at - now).min(STOP_CHECK_INTERVAL));
+    }
+}
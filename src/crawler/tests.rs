@@ -0,0 +1,95 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::config::{CrawlConfig, RetryConfig};
+use crate::crawler::{fetch_apps, FetchEvent, RateLimiter};
+
+fn no_delay_retry(max_attempts: u32) -> RetryConfig {
+    RetryConfig { delay_seconds: 0, max_attempts, ..RetryConfig::default() }
+}
+
+#[test]
+fn retry_policy() {
+    let calls = Mutex::new(0);
+    let fetch = |app_id: i64| {
+        *calls.lock().unwrap() += 1;
+        match app_id {
+            10 => Ok("Counter-Strike".to_string()),
+            20 => Err("steampowered api returned failed response".to_string()),
+            _ => Err("no response from API".to_string()),
+        }
+    };
+
+    let mut events: Vec<FetchEvent> = vec![];
+    fetch_apps(vec![10, 20, 30], &CrawlConfig::default(), &no_delay_retry(3), fetch, |event| { events.push(event); true });
+
+    // non-retryable error is given up on right away, others after the attempts run out
+    assert_eq!(FetchEvent::Fetched { app_id: 10, name: "Counter-Strike".to_string() }, events[0]);
+    assert_eq!(FetchEvent::Finished { app_id: 20 }, events[3]);
+    assert_eq!(3, events.iter().filter(|event| matches!(event, FetchEvent::Failed { app_id: 30, .. })).count());
+    assert_eq!(2, events.iter().filter(|event| matches!(event, FetchEvent::Retried { app_id: 30 })).count());
+    assert_eq!(FetchEvent::Finished { app_id: 30 }, *events.last().unwrap());
+    assert_eq!(5, *calls.lock().unwrap());
+}
+
+#[test]
+fn stop_while_waiting_for_retry() {
+    let retry = RetryConfig { delay_seconds: 3600, max_attempts: 0, ..RetryConfig::default() };
+    let started_at = Instant::now();
+    let mut events: Vec<FetchEvent> = vec![];
+    fetch_apps(vec![10], &CrawlConfig::default(), &retry, |_| Err("no response from API".to_string()), |event| {
+        events.push(event);
+        !matches!(events.last(), Some(FetchEvent::Failed { .. }))
+    });
+
+    // app is left unfinished for the next crawl
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+    assert_eq!(vec![FetchEvent::Failed { app_id: 10, error: "no response from API".to_string() }], events);
+}
+
+#[test]
+fn concurrency() {
+    let crawl = CrawlConfig { concurrency: 4, requests_per_minute: 0 };
+    let app_ids: Vec<i64> = (0..40).collect();
+    let mut finished: Vec<i64> = vec![];
+    fetch_apps(app_ids.to_vec(), &crawl, &no_delay_retry(1), |app_id| Ok(app_id.to_string()), |event| {
+        if let FetchEvent::Finished { app_id } = event {
+            finished.push(app_id);
+        }
+        true
+    });
+
+    finished.sort();
+    assert_eq!(app_ids, finished);
+
+    // stopped workers finish the apps at hand and take no new ones
+    let slow_fetch = |app_id: i64| {
+        std::thread::sleep(Duration::from_millis(20));
+        Ok(app_id.to_string())
+    };
+    let mut finished: Vec<i64> = vec![];
+    fetch_apps(app_ids.to_vec(), &crawl, &no_delay_retry(1), slow_fetch, |event| {
+        if let FetchEvent::Finished { app_id } = event {
+            finished.push(app_id);
+        }
+        finished.len() < 5
+    });
+    assert!(finished.len() >= 5 && finished.len() < 5 + 4 * 2);
+}
+
+#[test]
+fn rate_limit() {
+    // 1200 requests per minute is one request every 50 ms, the first one goes right away
+    let rate_limiter = RateLimiter::new(1200);
+    let started_at = Instant::now();
+    for _ in 0..5 {
+        rate_limiter.wait();
+    }
+    assert!(started_at.elapsed() >= Duration::from_millis(200));
+
+    let unlimited = RateLimiter::new(0);
+    let started_at = Instant::now();
+    for _ in 0..1000 {
+        unlimited.wait();
+    }
+    assert!(started_at.elapsed() < Duration::from_millis(200));
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+use std::str::FromStr;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::rsa::Padding;
+use serde::{Deserialize, Serialize};
+use crate::crypto_ext::error::CryptoError;
+use crate::crypto_ext::RSA_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyUsage {
+    Encryption,
+    Signing,
+}
+
+/// Algorithm of the keypair, recorded in the keyring next to the key files.
+/// RSA keys are used both for encryption and signing, Ed25519 keys only sign
+/// and X25519 keys only wrap data via ECDH key agreement.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyAlgorithm {
+    #[default]
+    Rsa4096,
+    Ed25519,
+    X25519,
+}
+
+impl KeyAlgorithm {
+    pub fn supports(&self, usage: KeyUsage) -> bool {
+        match self {
+            KeyAlgorithm::Rsa4096 => true,
+            KeyAlgorithm::Ed25519 => usage == KeyUsage::Signing,
+            KeyAlgorithm::X25519 => usage == KeyUsage::Encryption,
+        }
+    }
+
+    pub fn usages(&self) -> Vec<KeyUsage> {
+        vec![KeyUsage::Encryption, KeyUsage::Signing]
+            .into_iter()
+            .filter(|usage| self.supports(*usage))
+            .collect()
+    }
+
+    pub fn generate(&self) -> Result<PKey<Private>, CryptoError> {
+        let boxed_pkey = match self {
+            KeyAlgorithm::Rsa4096 => openssl::rsa::Rsa::generate(RSA_SIZE).and_then(PKey::from_rsa),
+            KeyAlgorithm::Ed25519 => PKey::generate_ed25519(),
+            KeyAlgorithm::X25519 => PKey::generate_x25519(),
+        };
+        if boxed_pkey.is_err() {
+            let message = format!("unable to generate {} key: {}", self, boxed_pkey.err().unwrap());
+            return Err(CryptoError::Crypto(message))
+        }
+        Ok(boxed_pkey.unwrap())
+    }
+
+    pub fn from_id(id: Id) -> Result<KeyAlgorithm, CryptoError> {
+        if id == Id::RSA {
+            return Ok(KeyAlgorithm::Rsa4096);
+        }
+        if id == Id::ED25519 {
+            return Ok(KeyAlgorithm::Ed25519);
+        }
+        if id == Id::X25519 {
+            return Ok(KeyAlgorithm::X25519);
+        }
+        let message = format!("unsupported key type: {:?}", id);
+        Err(CryptoError::Unsupported(message))
+    }
+}
+
+impl fmt::Display for KeyAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            KeyAlgorithm::Rsa4096 => "rsa4096",
+            KeyAlgorithm::Ed25519 => "ed25519",
+            KeyAlgorithm::X25519 => "x25519",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for KeyAlgorithm {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "rsa" | "rsa4096" => Ok(KeyAlgorithm::Rsa4096),
+            "ed25519" => Ok(KeyAlgorithm::Ed25519),
+            "x25519" => Ok(KeyAlgorithm::X25519),
+            _ => Err(format!("unknown key algorithm: {}", name)),
+        }
+    }
+}
+
+/// Padding used for RSA encryption. Recorded in the keyring per key, so data
+/// encrypted before the padding of newer keys was changed stays decryptable.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RsaPadding {
+    #[default]
+    Pkcs1,
+    OaepSha1,
+    OaepSha256,
+}
+
+impl RsaPadding {
+    pub fn get_padding(&self) -> Padding {
+        match self {
+            RsaPadding::Pkcs1 => Padding::PKCS1,
+            RsaPadding::OaepSha1 | RsaPadding::OaepSha256 => Padding::PKCS1_OAEP,
+        }
+    }
+
+    /// Bytes taken by the padding in a single RSA block.
+    pub fn get_overhead(&self) -> usize {
+        match self {
+            RsaPadding::Pkcs1 => 11,
+            RsaPadding::OaepSha1 => 2 * 20 + 2,
+            RsaPadding::OaepSha256 => 2 * 32 + 2,
+        }
+    }
+
+    pub fn get_oaep_digest(&self) -> Option<MessageDigest> {
+        match self {
+            RsaPadding::Pkcs1 => None,
+            RsaPadding::OaepSha1 => Some(MessageDigest::sha1()),
+            RsaPadding::OaepSha256 => Some(MessageDigest::sha256()),
+        }
+    }
+}
+
+impl fmt::Display for RsaPadding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RsaPadding::Pkcs1 => "PKCS1",
+            RsaPadding::OaepSha1 => "OAEP-SHA1",
+            RsaPadding::OaepSha256 => "OAEP-SHA256",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for RsaPadding {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().replace('-', "_").as_str() {
+            "pkcs1" => Ok(RsaPadding::Pkcs1),
+            "oaep_sha1" => Ok(RsaPadding::OaepSha1),
+            "oaep_sha256" => Ok(RsaPadding::OaepSha256),
+            _ => Err(format!("unknown RSA padding: {}", name)),
+        }
+    }
+}
@@ -0,0 +1,487 @@
+use std::io::{self, Read, Write};
+use openssl::pkey::PKey;
+use openssl::rand::rand_bytes;
+use openssl::symm::{Cipher, Crypter, decrypt_aead, encrypt_aead, Mode};
+use zeroize::Zeroizing;
+use crate::crypto_ext::algorithm::RsaPadding;
+use crate::crypto_ext::error::CryptoError;
+use crate::crypto_ext::keyring::Keyring;
+use crate::crypto_ext::{decrypt, encrypt, get_key_id, get_public_key_pem, KEY_ID_LENGTH};
+
+pub const ENVELOPE_VERSION: u8 = 1;
+pub const MULTI_RECIPIENT_ENVELOPE_VERSION: u8 = 2;
+pub const STREAM_ENVELOPE_VERSION: u8 = 3;
+
+/// Data keys are encrypted to RSA recipients with OAEP, whatever padding their own keyring uses.
+pub const RECIPIENT_RSA_PADDING: RsaPadding = RsaPadding::OaepSha256;
+
+const DATA_KEY_LENGTH: usize = 32;
+const IV_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+const STREAM_CHUNK_LENGTH: usize = 64 * 1024;
+
+/// Key id and wrapped data key of a recipient.
+type RecipientEntry = (String, Vec<u8>);
+
+/// Data key, iv and envelope header with the wrapped data key.
+type DataKey = (Zeroizing<[u8; DATA_KEY_LENGTH]>, [u8; IV_LENGTH], Vec<u8>);
+
+/// Encrypts data of any size. Random AES-256-GCM data key encrypts the data and
+/// is itself encrypted with the active keyring key. Output layout: version,
+/// length of the wrapped data key (2 bytes, big endian), wrapped data key, iv,
+/// tag, ciphertext. Version and wrapped data key are authenticated with the data.
+/// Backups are sealed with `seal_stream` now, version 1 is only opened in them.
+#[cfg(test)]
+pub fn seal(keyring: &Keyring, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let boxed_data_key = create_data_key(keyring, ENVELOPE_VERSION);
+    if boxed_data_key.is_err() {
+        return Err(boxed_data_key.err().unwrap());
+    }
+    let (data_key, iv, mut sealed) = boxed_data_key.unwrap();
+
+    let mut tag = [0; TAG_LENGTH];
+    let boxed_ciphertext = encrypt_aead(Cipher::aes_256_gcm(), data_key.as_ref(), Some(&iv), sealed.as_slice(), data, &mut tag);
+    if boxed_ciphertext.is_err() {
+        let message = format!("unable to encrypt: {}", boxed_ciphertext.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    sealed.extend_from_slice(&iv);
+    sealed.extend_from_slice(&tag);
+    sealed.append(&mut boxed_ciphertext.unwrap());
+    Ok(sealed)
+}
+
+/// Decrypts data produced by `seal` with the keyring key the data key was encrypted with.
+pub fn open(keyring: &Keyring, sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if sealed.len() < 3 {
+        return Err(CryptoError::Crypto("envelope is too short".to_string()));
+    }
+    if sealed[0] != ENVELOPE_VERSION {
+        let message = format!("unsupported envelope version: {}", sealed[0]);
+        return Err(CryptoError::Unsupported(message))
+    }
+
+    let wrapped_key_length = u16::from_be_bytes([sealed[1], sealed[2]]) as usize;
+    let header_length = 3 + wrapped_key_length;
+    if sealed.len() < header_length + IV_LENGTH + TAG_LENGTH {
+        return Err(CryptoError::Crypto("envelope is too short".to_string()));
+    }
+    let (header, rest) = sealed.split_at(header_length);
+    let (iv, rest) = rest.split_at(IV_LENGTH);
+    let (tag, ciphertext) = rest.split_at(TAG_LENGTH);
+
+    let boxed_data_key = keyring.decrypt(&header[3..]);
+    if boxed_data_key.is_err() {
+        return Err(boxed_data_key.err().unwrap());
+    }
+    let data_key = Zeroizing::new(boxed_data_key.unwrap());
+    if data_key.len() != DATA_KEY_LENGTH {
+        return Err(CryptoError::Crypto("unable to decrypt: wrong key or corrupted data".to_string()));
+    }
+
+    let boxed_plaintext = decrypt_aead(Cipher::aes_256_gcm(), data_key.as_slice(), Some(iv), header, ciphertext, tag);
+    if boxed_plaintext.is_err() {
+        return Err(CryptoError::Crypto("unable to decrypt: wrong key or corrupted data".to_string()));
+    }
+    Ok(boxed_plaintext.unwrap())
+}
+
+/// Encrypts data written to it into the inner writer, created by `seal_stream`.
+pub struct SealingWriter<W: Write> {
+    inner: W,
+    crypter: Crypter,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Write for SealingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.resize(data.len() + Cipher::aes_256_gcm().block_size(), 0);
+        let boxed_length = self.crypter.update(data, self.buffer.as_mut_slice());
+        if boxed_length.is_err() {
+            let message = format!("unable to encrypt: {}", boxed_length.err().unwrap());
+            return Err(io::Error::new(io::ErrorKind::Other, message))
+        }
+
+        let boxed_write = self.inner.write_all(&self.buffer[..boxed_length.unwrap()]);
+        if boxed_write.is_err() {
+            return Err(boxed_write.err().unwrap());
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> SealingWriter<W> {
+    /// Writes the tag after the ciphertext and returns the inner writer.
+    /// Data written without calling `finish` can not be decrypted.
+    pub fn finish(mut self) -> Result<W, CryptoError> {
+        let mut rest = [0; TAG_LENGTH];
+        let mut tag = [0; TAG_LENGTH];
+        let boxed_finalize = self.crypter.finalize(&mut rest).and_then(|length| self.crypter.get_tag(&mut tag).map(|_| length));
+        if boxed_finalize.is_err() {
+            let message = format!("unable to encrypt: {}", boxed_finalize.err().unwrap());
+            return Err(CryptoError::Crypto(message))
+        }
+
+        let length = boxed_finalize.unwrap();
+        let boxed_write = self.inner.write_all(&rest[..length])
+            .and_then(|_| self.inner.write_all(&tag))
+            .and_then(|_| self.inner.flush());
+        if boxed_write.is_err() {
+            let message = format!("unable to write envelope: {}", boxed_write.err().unwrap());
+            return Err(CryptoError::Io(message))
+        }
+        Ok(self.inner)
+    }
+}
+
+/// Encrypts data of any size as it is written, without holding it in memory.
+/// Data key is created and wrapped the same way as by `seal`. Output layout:
+/// version, length of the wrapped data key (2 bytes, big endian), wrapped data
+/// key, iv, ciphertext, tag. The tag is known only at the end, so it follows
+/// the ciphertext and is written by `SealingWriter::finish`.
+pub fn seal_stream<W: Write>(keyring: &Keyring, mut inner: W) -> Result<SealingWriter<W>, CryptoError> {
+    let boxed_data_key = create_data_key(keyring, STREAM_ENVELOPE_VERSION);
+    if boxed_data_key.is_err() {
+        return Err(boxed_data_key.err().unwrap());
+    }
+    let (data_key, iv, header) = boxed_data_key.unwrap();
+
+    let boxed_crypter = Crypter::new(Cipher::aes_256_gcm(), Mode::Encrypt, data_key.as_ref(), Some(&iv))
+        .and_then(|mut crypter| crypter.aad_update(header.as_slice()).map(|_| crypter));
+    if boxed_crypter.is_err() {
+        let message = format!("unable to encrypt: {}", boxed_crypter.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    let boxed_write = inner.write_all(header.as_slice()).and_then(|_| inner.write_all(&iv));
+    if boxed_write.is_err() {
+        let message = format!("unable to write envelope: {}", boxed_write.err().unwrap());
+        return Err(CryptoError::Io(message))
+    }
+
+    Ok(SealingWriter {
+        inner,
+        crypter: boxed_crypter.unwrap(),
+        buffer: vec![],
+    })
+}
+
+/// Decrypts data read from the inner reader, created by `open_stream`.
+/// The last bytes of the input are held back as the tag, which is checked
+/// when the input ends. Reading fails with `InvalidData` if it does not match,
+/// data read before that must not be trusted until the reader returns 0.
+pub struct OpeningReader<R: Read> {
+    inner: R,
+    crypter: Crypter,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    position: usize,
+    is_finished: bool,
+}
+
+impl<R: Read> Read for OpeningReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.output.len() {
+            if self.is_finished {
+                return Ok(0);
+            }
+            let boxed_fill = self.fill();
+            if boxed_fill.is_err() {
+                return Err(boxed_fill.err().unwrap());
+            }
+        }
+
+        let length = buffer.len().min(self.output.len() - self.position);
+        buffer[..length].copy_from_slice(&self.output[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+impl<R: Read> OpeningReader<R> {
+    fn fill(&mut self) -> io::Result<()> {
+        let mut chunk = vec![0; STREAM_CHUNK_LENGTH];
+        let boxed_read = self.inner.read(chunk.as_mut_slice());
+        if boxed_read.is_err() {
+            return Err(boxed_read.err().unwrap());
+        }
+        let read_length = boxed_read.unwrap();
+        self.output.clear();
+        self.position = 0;
+
+        if read_length == 0 {
+            if self.input.len() != TAG_LENGTH {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "envelope is too short"));
+            }
+            let mut rest = [0; TAG_LENGTH];
+            let boxed_finalize = self.crypter.set_tag(self.input.as_slice()).and_then(|_| self.crypter.finalize(&mut rest));
+            if boxed_finalize.is_err() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "unable to decrypt: wrong key or corrupted data"));
+            }
+            self.output.extend_from_slice(&rest[..boxed_finalize.unwrap()]);
+            self.is_finished = true;
+            return Ok(());
+        }
+
+        self.input.extend_from_slice(&chunk[..read_length]);
+        if self.input.len() <= TAG_LENGTH {
+            return Ok(());
+        }
+        let length = self.input.len() - TAG_LENGTH;
+        self.output.resize(length + Cipher::aes_256_gcm().block_size(), 0);
+        let boxed_length = self.crypter.update(&self.input[..length], self.output.as_mut_slice());
+        if boxed_length.is_err() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unable to decrypt: wrong key or corrupted data"));
+        }
+        self.output.truncate(boxed_length.unwrap());
+        self.input.drain(..length);
+        Ok(())
+    }
+}
+
+/// Decrypts data produced by `seal_stream` as it is read. Header is read and
+/// the data key decrypted with the keyring before the reader is returned.
+pub fn open_stream<R: Read>(keyring: &Keyring, mut inner: R) -> Result<OpeningReader<R>, CryptoError> {
+    let mut header = vec![0; 3];
+    let boxed_read = inner.read_exact(header.as_mut_slice());
+    if boxed_read.is_err() {
+        return Err(CryptoError::Crypto("envelope is too short".to_string()));
+    }
+    if header[0] != STREAM_ENVELOPE_VERSION {
+        let message = format!("unsupported envelope version: {}", header[0]);
+        return Err(CryptoError::Unsupported(message))
+    }
+
+    let wrapped_key_length = u16::from_be_bytes([header[1], header[2]]) as usize;
+    header.resize(3 + wrapped_key_length, 0);
+    let mut iv = [0; IV_LENGTH];
+    let boxed_read = inner.read_exact(&mut header[3..]).and_then(|_| inner.read_exact(&mut iv));
+    if boxed_read.is_err() {
+        return Err(CryptoError::Crypto("envelope is too short".to_string()));
+    }
+
+    let boxed_data_key = keyring.decrypt(&header[3..]);
+    if boxed_data_key.is_err() {
+        return Err(boxed_data_key.err().unwrap());
+    }
+    let data_key = Zeroizing::new(boxed_data_key.unwrap());
+    if data_key.len() != DATA_KEY_LENGTH {
+        return Err(CryptoError::Crypto("unable to decrypt: wrong key or corrupted data".to_string()));
+    }
+
+    let boxed_crypter = Crypter::new(Cipher::aes_256_gcm(), Mode::Decrypt, data_key.as_slice(), Some(&iv))
+        .and_then(|mut crypter| crypter.aad_update(header.as_slice()).map(|_| crypter));
+    if boxed_crypter.is_err() {
+        let message = format!("unable to decrypt: {}", boxed_crypter.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    Ok(OpeningReader {
+        inner,
+        crypter: boxed_crypter.unwrap(),
+        input: vec![],
+        output: vec![],
+        position: 0,
+        is_finished: false,
+    })
+}
+
+/// Encrypts data once for several recipients, each of them can decrypt it with
+/// their own private key only. Random AES-256-GCM data key encrypts the data and
+/// is encrypted to every RSA or X25519 public key. Output layout: version, number
+/// of recipients (2 bytes, big endian), then key id, length of the wrapped data key
+/// (2 bytes, big endian) and wrapped data key for each recipient, iv, tag, ciphertext.
+/// Everything before the iv is authenticated together with the associated data,
+/// which has to be supplied again on decryption.
+pub fn seal_for(public_keys: &[&str], associated_data: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if public_keys.is_empty() {
+        return Err(CryptoError::Configuration("envelope needs at least one recipient".to_string()));
+    }
+    if public_keys.len() > u16::MAX as usize {
+        return Err(CryptoError::Configuration("envelope has too many recipients".to_string()));
+    }
+
+    let mut data_key = Zeroizing::new([0; DATA_KEY_LENGTH]);
+    let mut iv = [0; IV_LENGTH];
+    let boxed_random = rand_bytes(data_key.as_mut()).and_then(|_| rand_bytes(&mut iv));
+    if boxed_random.is_err() {
+        let message = format!("unable to generate data key: {}", boxed_random.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    let mut key_id_list: Vec<String> = vec![];
+    let mut recipients: Vec<u8> = vec![];
+    for public_key in public_keys {
+        let boxed_key_id = get_key_id(public_key);
+        if boxed_key_id.is_err() {
+            return Err(boxed_key_id.err().unwrap());
+        }
+        let key_id = boxed_key_id.unwrap();
+        if key_id_list.contains(&key_id) {
+            continue;
+        }
+
+        let boxed_wrapped_key = encrypt(public_key, RECIPIENT_RSA_PADDING, data_key.as_ref());
+        if boxed_wrapped_key.is_err() {
+            return Err(boxed_wrapped_key.err().unwrap());
+        }
+        let wrapped_key = boxed_wrapped_key.unwrap();
+        if wrapped_key.len() > u16::MAX as usize {
+            return Err(CryptoError::Crypto("wrapped data key is too long".to_string()));
+        }
+
+        recipients.extend_from_slice(key_id.as_bytes());
+        recipients.extend_from_slice(&(wrapped_key.len() as u16).to_be_bytes());
+        recipients.extend_from_slice(wrapped_key.as_slice());
+        key_id_list.push(key_id);
+    }
+
+    let mut sealed: Vec<u8> = vec![MULTI_RECIPIENT_ENVELOPE_VERSION];
+    sealed.extend_from_slice(&(key_id_list.len() as u16).to_be_bytes());
+    sealed.append(&mut recipients);
+
+    let mut aad = sealed.to_vec();
+    aad.extend_from_slice(associated_data);
+
+    let mut tag = [0; TAG_LENGTH];
+    let boxed_ciphertext = encrypt_aead(Cipher::aes_256_gcm(), data_key.as_ref(), Some(&iv), aad.as_slice(), data, &mut tag);
+    if boxed_ciphertext.is_err() {
+        let message = format!("unable to encrypt: {}", boxed_ciphertext.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    sealed.extend_from_slice(&iv);
+    sealed.extend_from_slice(&tag);
+    sealed.append(&mut boxed_ciphertext.unwrap());
+    Ok(sealed)
+}
+
+/// Decrypts data produced by `seal_for` with the PEM encoded private key of one
+/// of the recipients. Private key of anyone else yields `UnknownKey`.
+pub fn open_with(private_key: &str, associated_data: &[u8], sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let boxed_parsed = parse_recipients(sealed);
+    if boxed_parsed.is_err() {
+        return Err(boxed_parsed.err().unwrap());
+    }
+    let (recipients, header_length) = boxed_parsed.unwrap();
+
+    let boxed_pkey = PKey::private_key_from_pem(private_key.as_bytes());
+    if boxed_pkey.is_err() {
+        let message = format!("unable to parse private key: {}", boxed_pkey.err().unwrap());
+        return Err(CryptoError::MalformedKey(message))
+    }
+    let boxed_public_key = get_public_key_pem(&boxed_pkey.unwrap());
+    if boxed_public_key.is_err() {
+        return Err(boxed_public_key.err().unwrap());
+    }
+    let boxed_key_id = get_key_id(boxed_public_key.unwrap().as_str());
+    if boxed_key_id.is_err() {
+        return Err(boxed_key_id.err().unwrap());
+    }
+    let key_id = boxed_key_id.unwrap();
+
+    let boxed_recipient = recipients.iter().find(|(recipient_key_id, _)| recipient_key_id == &key_id);
+    if boxed_recipient.is_none() {
+        return Err(CryptoError::UnknownKey(key_id))
+    }
+    let (_, wrapped_key) = boxed_recipient.unwrap();
+
+    let boxed_data_key = decrypt(private_key, RECIPIENT_RSA_PADDING, wrapped_key);
+    if boxed_data_key.is_err() {
+        return Err(boxed_data_key.err().unwrap());
+    }
+    let data_key = Zeroizing::new(boxed_data_key.unwrap());
+    if data_key.len() != DATA_KEY_LENGTH {
+        return Err(CryptoError::Crypto("unable to decrypt: wrong key or corrupted data".to_string()));
+    }
+
+    let (header, rest) = sealed.split_at(header_length);
+    let (iv, rest) = rest.split_at(IV_LENGTH);
+    let (tag, ciphertext) = rest.split_at(TAG_LENGTH);
+    let mut aad = header.to_vec();
+    aad.extend_from_slice(associated_data);
+
+    let boxed_plaintext = decrypt_aead(Cipher::aes_256_gcm(), data_key.as_slice(), Some(iv), aad.as_slice(), ciphertext, tag);
+    if boxed_plaintext.is_err() {
+        return Err(CryptoError::Crypto("unable to decrypt: wrong key or corrupted data".to_string()));
+    }
+    Ok(boxed_plaintext.unwrap())
+}
+
+/// Key ids of the recipients of an envelope produced by `seal_for`.
+pub fn get_recipients(sealed: &[u8]) -> Result<Vec<String>, CryptoError> {
+    let boxed_parsed = parse_recipients(sealed);
+    if boxed_parsed.is_err() {
+        return Err(boxed_parsed.err().unwrap());
+    }
+    let (recipients, _) = boxed_parsed.unwrap();
+    Ok(recipients.into_iter().map(|(key_id, _)| key_id).collect())
+}
+
+/// Returns key id and wrapped data key of every recipient and the length of the
+/// header preceding the iv. Checks there is room for the iv and tag after it.
+fn parse_recipients(sealed: &[u8]) -> Result<(Vec<RecipientEntry>, usize), CryptoError> {
+    if sealed.len() < 3 {
+        return Err(CryptoError::Crypto("envelope is too short".to_string()));
+    }
+    if sealed[0] != MULTI_RECIPIENT_ENVELOPE_VERSION {
+        let message = format!("unsupported envelope version: {}", sealed[0]);
+        return Err(CryptoError::Unsupported(message))
+    }
+
+    let recipient_count = u16::from_be_bytes([sealed[1], sealed[2]]) as usize;
+    let mut recipients: Vec<RecipientEntry> = vec![];
+    let mut offset = 3;
+    for _ in 0..recipient_count {
+        if sealed.len() < offset + KEY_ID_LENGTH + 2 {
+            return Err(CryptoError::Crypto("envelope is too short".to_string()));
+        }
+        let key_id = String::from_utf8_lossy(&sealed[offset..offset + KEY_ID_LENGTH]).to_string();
+        offset += KEY_ID_LENGTH;
+        let wrapped_key_length = u16::from_be_bytes([sealed[offset], sealed[offset + 1]]) as usize;
+        offset += 2;
+
+        if sealed.len() < offset + wrapped_key_length {
+            return Err(CryptoError::Crypto("envelope is too short".to_string()));
+        }
+        recipients.push((key_id, sealed[offset..offset + wrapped_key_length].to_vec()));
+        offset += wrapped_key_length;
+    }
+
+    if sealed.len() < offset + IV_LENGTH + TAG_LENGTH {
+        return Err(CryptoError::Crypto("envelope is too short".to_string()));
+    }
+    Ok((recipients, offset))
+}
+
+/// Creates random data key and iv and encrypts the data key with the active
+/// keyring key. Returns them with the envelope header: version, length of the
+/// wrapped data key (2 bytes, big endian) and wrapped data key.
+fn create_data_key(keyring: &Keyring, version: u8) -> Result<DataKey, CryptoError> {
+    let mut data_key = Zeroizing::new([0; DATA_KEY_LENGTH]);
+    let mut iv = [0; IV_LENGTH];
+    let boxed_random = rand_bytes(data_key.as_mut()).and_then(|_| rand_bytes(&mut iv));
+    if boxed_random.is_err() {
+        let message = format!("unable to generate data key: {}", boxed_random.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    let boxed_wrapped_key = keyring.encrypt(data_key.as_ref());
+    if boxed_wrapped_key.is_err() {
+        return Err(boxed_wrapped_key.err().unwrap());
+    }
+    let wrapped_key = boxed_wrapped_key.unwrap();
+    if wrapped_key.len() > u16::MAX as usize {
+        return Err(CryptoError::Crypto("wrapped data key is too long".to_string()));
+    }
+
+    let mut header: Vec<u8> = vec![version];
+    header.extend_from_slice(&(wrapped_key.len() as u16).to_be_bytes());
+    header.extend_from_slice(wrapped_key.as_slice());
+    Ok((data_key, iv, header))
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+
+/// Error returned by crypto_ext functions. The kind lets callers decide whether
+/// key material can be restored from a backup or the process has to stop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CryptoError {
+    /// Secret (or legacy passphrase) does not decrypt the private key.
+    BadPassphrase,
+    /// Key material can not be parsed or does not match what the keyring recorded.
+    MalformedKey(String),
+    /// Data does not fit into a single block of the key.
+    DataTooLarge { size: usize, max_size: usize },
+    /// Reading or writing key material failed.
+    Io(String),
+    /// Ciphertext or signature refers to a key which is not in the keyring.
+    UnknownKey(String),
+    /// Key algorithm does not support the requested operation.
+    Unsupported(String),
+    /// Secret is missing or key options are invalid.
+    Configuration(String),
+    /// Any other failure reported by openssl.
+    Crypto(String),
+}
+
+impl CryptoError {
+    /// Damaged or missing key material can be fixed by restoring keys from a backup,
+    /// any other error needs the operator.
+    pub fn is_restorable_from_backup(&self) -> bool {
+        matches!(self, CryptoError::MalformedKey(_) | CryptoError::Io(_))
+    }
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::BadPassphrase => write!(f, "unable to decrypt private key: wrong secret or corrupted key file"),
+            CryptoError::MalformedKey(message) => write!(f, "malformed key: {}", message),
+            CryptoError::DataTooLarge { size, max_size } => write!(f, "data is too large: {} bytes, at most {} bytes can be encrypted", size, max_size),
+            CryptoError::Io(message) => write!(f, "{}", message),
+            CryptoError::UnknownKey(key_id) => write!(f, "key {} is not present in the keyring", key_id),
+            CryptoError::Unsupported(message) => write!(f, "unsupported operation: {}", message),
+            CryptoError::Configuration(message) => write!(f, "{}", message),
+            CryptoError::Crypto(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
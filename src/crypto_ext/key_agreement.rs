@@ -0,0 +1,120 @@
+use openssl::derive::Deriver;
+use openssl::pkey::{HasPrivate, HasPublic, PKey, PKeyRef};
+use openssl::rand::rand_bytes;
+use openssl::sha::Sha256;
+use openssl::symm::{Cipher, decrypt_aead, encrypt_aead};
+use zeroize::Zeroizing;
+use crate::crypto_ext::error::CryptoError;
+
+const X25519_PUBLIC_KEY_LENGTH: usize = 32;
+const IV_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+/// Encrypts data to the X25519 public key. Ephemeral keypair is generated for
+/// every call, shared secret derived via ECDH is hashed together with both public
+/// keys into AES-256-GCM key. Output layout: ephemeral public key, iv, tag, ciphertext.
+pub fn wrap<T: HasPublic>(recipient: &PKeyRef<T>, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let boxed_ephemeral = PKey::generate_x25519();
+    if boxed_ephemeral.is_err() {
+        let message = format!("unable to generate ephemeral key: {}", boxed_ephemeral.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+    let ephemeral = boxed_ephemeral.unwrap();
+
+    let boxed_ephemeral_public_key = ephemeral.raw_public_key();
+    let boxed_recipient_public_key = recipient.raw_public_key();
+    if boxed_ephemeral_public_key.is_err() || boxed_recipient_public_key.is_err() {
+        return Err(CryptoError::MalformedKey("unable to encode X25519 public key".to_string()));
+    }
+    let ephemeral_public_key = boxed_ephemeral_public_key.unwrap();
+    let recipient_public_key = boxed_recipient_public_key.unwrap();
+
+    let boxed_key = derive_key(&ephemeral, recipient, &ephemeral_public_key, &recipient_public_key);
+    if boxed_key.is_err() {
+        return Err(boxed_key.err().unwrap());
+    }
+    let key = boxed_key.unwrap();
+
+    let mut iv = [0; IV_LENGTH];
+    let boxed_random = rand_bytes(&mut iv);
+    if boxed_random.is_err() {
+        let message = format!("unable to generate iv: {}", boxed_random.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    let mut tag = [0; TAG_LENGTH];
+    let boxed_ciphertext = encrypt_aead(Cipher::aes_256_gcm(), key.as_ref(), Some(&iv), &ephemeral_public_key, data, &mut tag);
+    if boxed_ciphertext.is_err() {
+        let message = format!("unable to encrypt: {}", boxed_ciphertext.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    let mut wrapped = ephemeral_public_key;
+    wrapped.extend_from_slice(&iv);
+    wrapped.extend_from_slice(&tag);
+    wrapped.append(&mut boxed_ciphertext.unwrap());
+    Ok(wrapped)
+}
+
+/// Decrypts data produced by `wrap` with the X25519 private key of the recipient.
+pub fn unwrap<T: HasPrivate>(recipient: &PKeyRef<T>, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if data.len() < X25519_PUBLIC_KEY_LENGTH + IV_LENGTH + TAG_LENGTH {
+        return Err(CryptoError::Crypto("wrapped data is too short".to_string()));
+    }
+    let (ephemeral_public_key, rest) = data.split_at(X25519_PUBLIC_KEY_LENGTH);
+    let (iv, rest) = rest.split_at(IV_LENGTH);
+    let (tag, ciphertext) = rest.split_at(TAG_LENGTH);
+
+    let boxed_ephemeral = PKey::public_key_from_raw_bytes(ephemeral_public_key, openssl::pkey::Id::X25519);
+    if boxed_ephemeral.is_err() {
+        let message = format!("malformed ephemeral public key: {}", boxed_ephemeral.err().unwrap());
+        return Err(CryptoError::MalformedKey(message))
+    }
+    let ephemeral = boxed_ephemeral.unwrap();
+
+    let boxed_recipient_public_key = recipient.raw_public_key();
+    if boxed_recipient_public_key.is_err() {
+        return Err(CryptoError::MalformedKey("unable to encode X25519 public key".to_string()));
+    }
+    let recipient_public_key = boxed_recipient_public_key.unwrap();
+
+    let boxed_key = derive_key(recipient, &ephemeral, ephemeral_public_key, &recipient_public_key);
+    if boxed_key.is_err() {
+        return Err(boxed_key.err().unwrap());
+    }
+    let key = boxed_key.unwrap();
+
+    let boxed_plaintext = decrypt_aead(Cipher::aes_256_gcm(), key.as_ref(), Some(iv), ephemeral_public_key, ciphertext, tag);
+    if boxed_plaintext.is_err() {
+        return Err(CryptoError::Crypto("unable to decrypt: wrong key or corrupted data".to_string()));
+    }
+    Ok(boxed_plaintext.unwrap())
+}
+
+fn derive_key<T: HasPrivate, U: HasPublic>(private: &PKeyRef<T>, peer: &PKeyRef<U>, ephemeral_public_key: &[u8], recipient_public_key: &[u8]) -> Result<Zeroizing<[u8; 32]>, CryptoError> {
+    let boxed_deriver = Deriver::new(private);
+    if boxed_deriver.is_err() {
+        let message = format!("unable to derive shared secret: {}", boxed_deriver.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+    let mut deriver = boxed_deriver.unwrap();
+
+    let boxed_peer = deriver.set_peer(peer);
+    if boxed_peer.is_err() {
+        let message = format!("unable to derive shared secret: {}", boxed_peer.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    let boxed_shared_secret = deriver.derive_to_vec();
+    if boxed_shared_secret.is_err() {
+        let message = format!("unable to derive shared secret: {}", boxed_shared_secret.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+    let shared_secret = Zeroizing::new(boxed_shared_secret.unwrap());
+
+    let mut hasher = Sha256::new();
+    hasher.update(&shared_secret);
+    hasher.update(ephemeral_public_key);
+    hasher.update(recipient_public_key);
+    Ok(Zeroizing::new(hasher.finish()))
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::str::FromStr;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::sha::sha256;
+use crate::crypto_ext::error::CryptoError;
+use crate::crypto_ext::get_key_id;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PublicKeyFormat {
+    #[default]
+    Pem,
+    OpenSsh,
+    Jwk,
+}
+
+impl fmt::Display for PublicKeyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PublicKeyFormat::Pem => "pem",
+            PublicKeyFormat::OpenSsh => "openssh",
+            PublicKeyFormat::Jwk => "jwk",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for PublicKeyFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "pem" => Ok(PublicKeyFormat::Pem),
+            "openssh" | "ssh" => Ok(PublicKeyFormat::OpenSsh),
+            "jwk" => Ok(PublicKeyFormat::Jwk),
+            _ => Err(format!("unknown public key format: {}", name)),
+        }
+    }
+}
+
+/// Converts PEM encoded public key into the given format. Key id is used as
+/// the comment of the OpenSSH key and as `kid` of the JWK.
+/// X25519 keys have no OpenSSH representation.
+pub fn export_public_key(public_key: &str, format: PublicKeyFormat) -> Result<String, CryptoError> {
+    let boxed_pkey = PKey::public_key_from_pem(public_key.as_bytes());
+    if boxed_pkey.is_err() {
+        let message = format!("unable to parse public key: {}", boxed_pkey.err().unwrap());
+        return Err(CryptoError::MalformedKey(message))
+    }
+    let pkey = boxed_pkey.unwrap();
+
+    let boxed_key_id = get_key_id(public_key);
+    if boxed_key_id.is_err() {
+        return Err(boxed_key_id.err().unwrap());
+    }
+    let key_id = boxed_key_id.unwrap();
+
+    match format {
+        PublicKeyFormat::Pem => {
+            let boxed_pem = pkey.public_key_to_pem();
+            if boxed_pem.is_err() {
+                let message = format!("unable to encode public key: {}", boxed_pem.err().unwrap());
+                return Err(CryptoError::Crypto(message))
+            }
+            Ok(String::from_utf8_lossy(boxed_pem.unwrap().as_slice()).to_string())
+        }
+        PublicKeyFormat::OpenSsh => export_openssh(&pkey, key_id.as_str()),
+        PublicKeyFormat::Jwk => export_jwk(&pkey, key_id.as_str()),
+    }
+}
+
+/// SHA-256 of the DER encoded public key as colon separated hex, same as
+/// `openssl pkey -pubin -outform DER | openssl dgst -sha256 -c` prints.
+/// Key id is the beginning of the fingerprint without colons.
+pub fn get_fingerprint(public_key: &str) -> Result<String, CryptoError> {
+    let boxed_pkey = PKey::public_key_from_pem(public_key.as_bytes());
+    if boxed_pkey.is_err() {
+        let message = format!("unable to parse public key: {}", boxed_pkey.err().unwrap());
+        return Err(CryptoError::MalformedKey(message))
+    }
+
+    let boxed_der = boxed_pkey.unwrap().public_key_to_der();
+    if boxed_der.is_err() {
+        let message = format!("unable to encode public key: {}", boxed_der.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    let hash = sha256(boxed_der.unwrap().as_slice());
+    let hex_list: Vec<String> = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(["SHA256:", hex_list.join(":").as_str()].join(""))
+}
+
+fn export_openssh(pkey: &PKey<Public>, key_id: &str) -> Result<String, CryptoError> {
+    let mut blob: Vec<u8> = vec![];
+    let key_type = if pkey.id() == Id::RSA {
+        let boxed_rsa = pkey.rsa();
+        if boxed_rsa.is_err() {
+            let message = format!("unable to read RSA key: {}", boxed_rsa.err().unwrap());
+            return Err(CryptoError::MalformedKey(message))
+        }
+        let rsa = boxed_rsa.unwrap();
+
+        write_ssh_string(&mut blob, "ssh-rsa".as_bytes());
+        write_ssh_mpint(&mut blob, rsa.e().to_vec().as_slice());
+        write_ssh_mpint(&mut blob, rsa.n().to_vec().as_slice());
+        "ssh-rsa"
+    } else if pkey.id() == Id::ED25519 {
+        let boxed_raw = pkey.raw_public_key();
+        if boxed_raw.is_err() {
+            let message = format!("unable to read Ed25519 key: {}", boxed_raw.err().unwrap());
+            return Err(CryptoError::MalformedKey(message))
+        }
+
+        write_ssh_string(&mut blob, "ssh-ed25519".as_bytes());
+        write_ssh_string(&mut blob, boxed_raw.unwrap().as_slice());
+        "ssh-ed25519"
+    } else {
+        let message = format!("{:?} key has no OpenSSH format", pkey.id());
+        return Err(CryptoError::Unsupported(message))
+    };
+
+    Ok([key_type, " ", base64::encode(blob).as_str(), " ", key_id, "\n"].join(""))
+}
+
+fn export_jwk(pkey: &PKey<Public>, key_id: &str) -> Result<String, CryptoError> {
+    let jwk = if pkey.id() == Id::RSA {
+        let boxed_rsa = pkey.rsa();
+        if boxed_rsa.is_err() {
+            let message = format!("unable to read RSA key: {}", boxed_rsa.err().unwrap());
+            return Err(CryptoError::MalformedKey(message))
+        }
+        let rsa = boxed_rsa.unwrap();
+
+        serde_json::json!({
+            "kty": "RSA",
+            "kid": key_id,
+            "n": base64::encode_config(rsa.n().to_vec(), base64::URL_SAFE_NO_PAD),
+            "e": base64::encode_config(rsa.e().to_vec(), base64::URL_SAFE_NO_PAD),
+        })
+    } else if pkey.id() == Id::ED25519 || pkey.id() == Id::X25519 {
+        let curve = if pkey.id() == Id::ED25519 { "Ed25519" } else { "X25519" };
+        let boxed_raw = pkey.raw_public_key();
+        if boxed_raw.is_err() {
+            let message = format!("unable to read {} key: {}", curve, boxed_raw.err().unwrap());
+            return Err(CryptoError::MalformedKey(message))
+        }
+
+        serde_json::json!({
+            "kty": "OKP",
+            "kid": key_id,
+            "crv": curve,
+            "x": base64::encode_config(boxed_raw.unwrap(), base64::URL_SAFE_NO_PAD),
+        })
+    } else {
+        let message = format!("{:?} key has no JWK format", pkey.id());
+        return Err(CryptoError::Unsupported(message))
+    };
+
+    let boxed_serialized = serde_json::to_string_pretty(&jwk);
+    if boxed_serialized.is_err() {
+        let message = format!("unable to serialize JWK: {}", boxed_serialized.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+    Ok([boxed_serialized.unwrap().as_str(), "\n"].join(""))
+}
+
+fn write_ssh_string(blob: &mut Vec<u8>, data: &[u8]) {
+    blob.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    blob.extend_from_slice(data);
+}
+
+/// Positive integer, prefixed with zero byte if the highest bit is set.
+fn write_ssh_mpint(blob: &mut Vec<u8>, data: &[u8]) {
+    if data.first().map(|byte| byte & 0x80 != 0).unwrap_or(false) {
+        let mut padded = vec![0];
+        padded.extend_from_slice(data);
+        write_ssh_string(blob, padded.as_slice());
+        return;
+    }
+    write_ssh_string(blob, data);
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use crate::crypto_ext::error::CryptoError;
+use crate::crypto_ext::key_protection::{is_protected_private_key, Kdf, KeyProtectionCipher, protect_private_key};
+use crate::crypto_ext::key_store::KeyStore;
+use crate::crypto_ext::secret::SecretString;
+use crate::crypto_ext::{create_file, does_file_exist, overwrite_file, read_file};
+
+pub const PRIVATE_KEY_ENVIRONMENT_VARIABLE: &str = "CRYPTO_EXT_PRIVATE_KEY";
+pub const PUBLIC_KEY_ENVIRONMENT_VARIABLE: &str = "CRYPTO_EXT_PUBLIC_KEY";
+
+pub const PRIVATE_KEY_NAME: &str = ".private_key";
+pub const PUBLIC_KEY_NAME: &str = ".public_key";
+
+/// Storage of the keypairs and the keyring index. Items are addressed by name,
+/// the same names the key store uses as file names.
+pub trait KeyProvider: Send + Sync {
+    /// Prepares the storage, called once when a keyring is opened.
+    fn open(&self) -> Result<(), CryptoError> {
+        Ok(())
+    }
+
+    /// Returns `None` if there is no item with the name.
+    fn read(&self, name: &str) -> Result<Option<String>, CryptoError>;
+
+    /// Creates the item or replaces its content.
+    fn write(&self, name: &str, content: &str) -> Result<(), CryptoError>;
+
+    /// Removing an absent item is not an error.
+    fn remove(&self, name: &str) -> Result<(), CryptoError>;
+
+    fn box_clone(&self) -> Box<dyn KeyProvider>;
+}
+
+/// Items are files in the key store directory.
+impl KeyProvider for KeyStore {
+    fn open(&self) -> Result<(), CryptoError> {
+        KeyStore::open(self)
+    }
+
+    fn read(&self, name: &str) -> Result<Option<String>, CryptoError> {
+        let boxed_path = self.get_filepath(name);
+        if boxed_path.is_err() {
+            return Err(boxed_path.err().unwrap());
+        }
+        let path = boxed_path.unwrap();
+
+        if !does_file_exist(path.as_str()) {
+            return Ok(None);
+        }
+        let boxed_read = read_file(path.as_str());
+        if boxed_read.is_err() {
+            return Err(boxed_read.err().unwrap());
+        }
+        Ok(Some(boxed_read.unwrap()))
+    }
+
+    fn write(&self, name: &str, content: &str) -> Result<(), CryptoError> {
+        let boxed_path = self.get_filepath(name);
+        if boxed_path.is_err() {
+            return Err(boxed_path.err().unwrap());
+        }
+        let path = boxed_path.unwrap();
+
+        if !does_file_exist(path.as_str()) {
+            let boxed_create = create_file(path.as_str());
+            if boxed_create.is_err() {
+                return Err(boxed_create.err().unwrap());
+            }
+        }
+        overwrite_file(path.as_str(), content.as_bytes())
+    }
+
+    fn remove(&self, name: &str) -> Result<(), CryptoError> {
+        let boxed_path = self.get_filepath(name);
+        if boxed_path.is_err() {
+            return Err(boxed_path.err().unwrap());
+        }
+        let path = boxed_path.unwrap();
+
+        if !does_file_exist(path.as_str()) {
+            return Ok(());
+        }
+        let boxed_remove = fs::remove_file(path.as_str());
+        if boxed_remove.is_err() {
+            let message = format!("unable to remove {}: {}", path, boxed_remove.err().unwrap());
+            return Err(CryptoError::Io(message))
+        }
+        Ok(())
+    }
+
+    fn box_clone(&self) -> Box<dyn KeyProvider> {
+        Box::new(self.clone())
+    }
+}
+
+/// Items live in memory and are gone with the process. Clones share the items,
+/// so a keyring reopened from a clone sees keys added by the previous one.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryKeyProvider {
+    items: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl MemoryKeyProvider {
+    pub fn new() -> MemoryKeyProvider {
+        MemoryKeyProvider::default()
+    }
+}
+
+impl KeyProvider for MemoryKeyProvider {
+    fn read(&self, name: &str) -> Result<Option<String>, CryptoError> {
+        let boxed_items = self.items.lock();
+        if boxed_items.is_err() {
+            return Err(CryptoError::Io("in-memory key provider is poisoned".to_string()));
+        }
+        Ok(boxed_items.unwrap().get(name).cloned())
+    }
+
+    fn write(&self, name: &str, content: &str) -> Result<(), CryptoError> {
+        let boxed_items = self.items.lock();
+        if boxed_items.is_err() {
+            return Err(CryptoError::Io("in-memory key provider is poisoned".to_string()));
+        }
+        boxed_items.unwrap().insert(name.to_string(), content.to_string());
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<(), CryptoError> {
+        let boxed_items = self.items.lock();
+        if boxed_items.is_err() {
+            return Err(CryptoError::Io("in-memory key provider is poisoned".to_string()));
+        }
+        boxed_items.unwrap().remove(name);
+        Ok(())
+    }
+
+    fn box_clone(&self) -> Box<dyn KeyProvider> {
+        Box::new(self.clone())
+    }
+}
+
+/// Keypair supplied by the environment, e.g. by a container runtime. Private key
+/// is read from `CRYPTO_EXT_PRIVATE_KEY` and public key, if set, from `CRYPTO_EXT_PUBLIC_KEY`.
+/// Keyring index and the derived public key are kept in memory. Private keys can
+/// not be written, so the keyring can neither generate nor rotate keys.
+#[derive(Clone, Debug)]
+pub struct EnvironmentKeyProvider {
+    items: MemoryKeyProvider,
+}
+
+impl EnvironmentKeyProvider {
+    /// Private key may be plain PEM, it is protected with the secret before
+    /// it is handed to the keyring.
+    pub fn from_environment(secret: &str) -> Result<EnvironmentKeyProvider, CryptoError> {
+        EnvironmentKeyProvider::from_variables(secret, |name| env::var(name).ok())
+    }
+
+    /// Same as `from_environment`, with the variables looked up by the given function.
+    pub fn from_variables<F: Fn(&str) -> Option<String>>(secret: &str, get_variable: F) -> Result<EnvironmentKeyProvider, CryptoError> {
+        let boxed_private_key = get_variable(PRIVATE_KEY_ENVIRONMENT_VARIABLE);
+        if boxed_private_key.is_none() || boxed_private_key.as_ref().unwrap().trim().is_empty() {
+            let message = format!("{} environment variable is not set", PRIVATE_KEY_ENVIRONMENT_VARIABLE);
+            return Err(CryptoError::Configuration(message))
+        }
+        let private_key = SecretString::new(boxed_private_key.unwrap());
+
+        let protected_private_key = if is_protected_private_key(private_key.expose()) {
+            private_key.expose().to_string()
+        } else {
+            let boxed_protected = protect_private_key(private_key.expose(), secret, &Kdf::default(), KeyProtectionCipher::default());
+            if boxed_protected.is_err() {
+                return Err(boxed_protected.err().unwrap());
+            }
+            boxed_protected.unwrap()
+        };
+
+        let items = MemoryKeyProvider::new();
+        let boxed_write = items.write(PRIVATE_KEY_NAME, protected_private_key.as_str());
+        if boxed_write.is_err() {
+            return Err(boxed_write.err().unwrap());
+        }
+
+        let boxed_public_key = get_variable(PUBLIC_KEY_ENVIRONMENT_VARIABLE).filter(|public_key| !public_key.trim().is_empty());
+        if let Some(public_key) = boxed_public_key {
+            let boxed_write = items.write(PUBLIC_KEY_NAME, public_key.as_str());
+            if boxed_write.is_err() {
+                return Err(boxed_write.err().unwrap());
+            }
+        }
+
+        Ok(EnvironmentKeyProvider { items })
+    }
+
+    pub fn is_configured() -> bool {
+        env::var_os(PRIVATE_KEY_ENVIRONMENT_VARIABLE).is_some()
+    }
+}
+
+impl KeyProvider for EnvironmentKeyProvider {
+    fn read(&self, name: &str) -> Result<Option<String>, CryptoError> {
+        self.items.read(name)
+    }
+
+    fn write(&self, name: &str, content: &str) -> Result<(), CryptoError> {
+        if name.ends_with(PRIVATE_KEY_NAME) {
+            let message = format!("private keys supplied by {} are read only", PRIVATE_KEY_ENVIRONMENT_VARIABLE);
+            return Err(CryptoError::Unsupported(message))
+        }
+        self.items.write(name, content)
+    }
+
+    fn remove(&self, name: &str) -> Result<(), CryptoError> {
+        if name.ends_with(PRIVATE_KEY_NAME) {
+            let message = format!("private keys supplied by {} are read only", PRIVATE_KEY_ENVIRONMENT_VARIABLE);
+            return Err(CryptoError::Unsupported(message))
+        }
+        self.items.remove(name)
+    }
+
+    fn box_clone(&self) -> Box<dyn KeyProvider> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,192 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::crypto_ext::error::CryptoError;
+
+pub const KEY_STORE_ENVIRONMENT_VARIABLE: &str = "CRYPTO_EXT_KEY_STORE";
+pub const KEY_STORE_DIRECTORY_NAME: &str = "retrieve-all-steam-apps-details-demo-app";
+
+pub const DIRECTORY_MODE: u32 = 0o700;
+pub const FILE_MODE: u32 = 0o600;
+
+/// Directory keeping the keypairs and the keyring index. Path is always absolute,
+/// relative paths are resolved against the working directory once, on creation.
+/// Directory is accessible by the owner only, so are the files in it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyStore {
+    directory: PathBuf,
+}
+
+impl KeyStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<KeyStore, CryptoError> {
+        let path = path.as_ref();
+        if path.as_os_str().is_empty() {
+            return Err(CryptoError::Configuration("key store path is empty".to_string()));
+        }
+        if path.is_absolute() {
+            return Ok(KeyStore { directory: path.to_path_buf() });
+        }
+
+        let boxed_working_directory = env::current_dir();
+        if boxed_working_directory.is_err() {
+            let message = format!("unable to resolve key store path {}: {}", path.display(), boxed_working_directory.err().unwrap());
+            return Err(CryptoError::Io(message))
+        }
+        Ok(KeyStore { directory: boxed_working_directory.unwrap().join(path) })
+    }
+
+    /// Key store from `CRYPTO_EXT_KEY_STORE` if set, otherwise `keys` directory
+    /// under `$XDG_CONFIG_HOME` or `$HOME/.config`.
+    pub fn from_environment() -> Result<KeyStore, CryptoError> {
+        KeyStore::from_variables(|name| env::var(name).ok())
+    }
+
+    /// Same as `from_environment`, with the variables looked up by the given function.
+    pub fn from_variables<F: Fn(&str) -> Option<String>>(get_variable: F) -> Result<KeyStore, CryptoError> {
+        let boxed_path = get_variable(KEY_STORE_ENVIRONMENT_VARIABLE).filter(|path| !path.is_empty());
+        if let Some(path) = boxed_path {
+            return KeyStore::new(path);
+        }
+
+        let boxed_config_home = get_variable("XDG_CONFIG_HOME").filter(|config_home| Path::new(config_home).is_absolute());
+        let config_home = if let Some(config_home) = boxed_config_home {
+            PathBuf::from(config_home)
+        } else {
+            let boxed_home = get_variable("HOME").filter(|home| !home.is_empty());
+            if boxed_home.is_none() {
+                let message = format!("neither {}, XDG_CONFIG_HOME nor HOME environment variable is set", KEY_STORE_ENVIRONMENT_VARIABLE);
+                return Err(CryptoError::Configuration(message))
+            }
+            PathBuf::from(boxed_home.unwrap()).join(".config")
+        };
+
+        KeyStore::new(config_home.join(KEY_STORE_DIRECTORY_NAME).join("keys"))
+    }
+
+    pub fn get_directory(&self) -> &Path {
+        self.directory.as_path()
+    }
+
+    /// Path of the file directly in the key store, file name can not point elsewhere.
+    pub fn get_filepath(&self, filename: &str) -> Result<String, CryptoError> {
+        let is_plain_filename = !filename.is_empty()
+            && filename != "."
+            && filename != ".."
+            && !filename.contains('/')
+            && !filename.contains('\\');
+        if !is_plain_filename {
+            let message = format!("{} is not a file name in the key store", filename);
+            return Err(CryptoError::Configuration(message))
+        }
+
+        let path = self.directory.join(filename);
+        let boxed_path = path.to_str();
+        if boxed_path.is_none() {
+            let message = format!("key store path {} is not valid unicode", path.display());
+            return Err(CryptoError::Io(message))
+        }
+        Ok(boxed_path.unwrap().to_string())
+    }
+
+    /// Creates the directory if absent with permissions restricted to the owner.
+    /// Fails if the existing directory or a file in it is accessible by group or others.
+    pub fn open(&self) -> Result<(), CryptoError> {
+        let is_created = !self.directory.is_dir();
+        let boxed_create = fs::create_dir_all(&self.directory);
+        if boxed_create.is_err() {
+            let message = format!("unable to create key store {}: {}", self.directory.display(), boxed_create.err().unwrap());
+            return Err(CryptoError::Io(message))
+        }
+
+        let boxed_restrict = if is_created {
+            restrict_permissions(self.directory.as_path(), DIRECTORY_MODE)
+        } else {
+            check_permissions(self.directory.as_path(), DIRECTORY_MODE)
+        };
+        if boxed_restrict.is_err() {
+            return Err(boxed_restrict.err().unwrap());
+        }
+
+        let boxed_entries = fs::read_dir(&self.directory);
+        if boxed_entries.is_err() {
+            let message = format!("unable to read key store {}: {}", self.directory.display(), boxed_entries.err().unwrap());
+            return Err(CryptoError::Io(message))
+        }
+        for boxed_entry in boxed_entries.unwrap() {
+            if boxed_entry.is_err() {
+                let message = format!("unable to read key store {}: {}", self.directory.display(), boxed_entry.err().unwrap());
+                return Err(CryptoError::Io(message))
+            }
+            let path = boxed_entry.unwrap().path();
+            if !path.is_file() {
+                continue;
+            }
+
+            let boxed_check = check_permissions(path.as_path(), FILE_MODE);
+            if boxed_check.is_err() {
+                return Err(boxed_check.err().unwrap());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Sets the permissions of a path just created, which got the ones of the umask.
+#[cfg(unix)]
+pub fn restrict_permissions(path: &Path, mode: u32) -> Result<(), CryptoError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let boxed_mode = get_mode(path);
+    if boxed_mode.is_err() {
+        return Err(boxed_mode.err().unwrap());
+    }
+    if boxed_mode.unwrap() == mode {
+        return Ok(());
+    }
+
+    let boxed_set = fs::set_permissions(path, fs::Permissions::from_mode(mode));
+    if boxed_set.is_err() {
+        let message = format!("unable to restrict permissions of {} to {:o}: {}", path.display(), mode, boxed_set.err().unwrap());
+        return Err(CryptoError::Configuration(message))
+    }
+    Ok(())
+}
+
+/// Fails if group or others can access the path. Key material may have leaked already,
+/// the operator restricts the permissions after deciding whether to rotate the keys.
+#[cfg(unix)]
+pub fn check_permissions(path: &Path, mode: u32) -> Result<(), CryptoError> {
+    let boxed_mode = get_mode(path);
+    if boxed_mode.is_err() {
+        return Err(boxed_mode.err().unwrap());
+    }
+    let current_mode = boxed_mode.unwrap();
+    if current_mode & 0o077 != 0 {
+        let message = format!("{} is accessible by group or others ({:o}), restrict it to {:o}", path.display(), current_mode, mode);
+        return Err(CryptoError::Configuration(message))
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn get_mode(path: &Path) -> Result<u32, CryptoError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let boxed_metadata = fs::metadata(path);
+    if boxed_metadata.is_err() {
+        let message = format!("unable to read permissions of {}: {}", path.display(), boxed_metadata.err().unwrap());
+        return Err(CryptoError::Io(message))
+    }
+    Ok(boxed_metadata.unwrap().permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+pub fn restrict_permissions(_path: &Path, _mode: u32) -> Result<(), CryptoError> {
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn check_permissions(_path: &Path, _mode: u32) -> Result<(), CryptoError> {
+    Ok(())
+}
@@ -0,0 +1,460 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::pkey::PKey;
+use serde::{Deserialize, Serialize};
+use crate::crypto_ext::error::CryptoError;
+use crate::crypto_ext::algorithm::{KeyAlgorithm, KeyUsage, RsaPadding};
+use crate::crypto_ext::key_protection::{KeyProtectionCipher, protect_private_key};
+use crate::crypto_ext::secret::SecretString;
+use crate::crypto_ext::key_provider::{KeyProvider, PRIVATE_KEY_NAME, PUBLIC_KEY_NAME};
+use crate::crypto_ext::{decrypt, encrypt, EncryptionParameters, generate_private_public_keys, get_key_algorithm, get_key_id, get_private_key_pem, get_public_key_pem, get_or_create_private_public_keys, KEY_ID_LENGTH, KeyOptions, read_or_create_and_write, RSA_SIZE, sign, verify};
+
+pub const KEYRING_INDEX_FILENAME: &str = ".keyring";
+pub const LEGACY_PASSPHRASE_FILENAME: &str = ".passphrase";
+pub const SIGNATURE_KEY_ID_SEPARATOR: &str = ":";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct KeyringEntry {
+    pub id: String,
+    pub created_at: u64,
+    #[serde(default)]
+    pub algorithm: KeyAlgorithm,
+    #[serde(default)]
+    pub padding: Option<RsaPadding>,
+    #[serde(default)]
+    pub cipher: KeyProtectionCipher,
+    pub active: bool,
+    pub private_key_file: String,
+    pub public_key_file: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct KeyringIndex {
+    keys: Vec<KeyringEntry>,
+}
+
+/// Set of keypairs stored by one key provider. The newest active key supporting
+/// the usage is used for new encryption and signing, older keys are retained so
+/// data encrypted or signed before a rotation can still be decrypted and verified.
+/// Ciphertexts and signatures produced by the keyring are tagged with the id of the key.
+pub struct Keyring {
+    pub entries: Vec<KeyringEntry>,
+    key_provider: Box<dyn KeyProvider>,
+    secret: SecretString,
+}
+
+impl Keyring {
+    pub fn open(key_provider: &dyn KeyProvider, secret: &str) -> Result<Keyring, CryptoError> {
+        Keyring::open_with_options(key_provider, secret, &KeyOptions::default())
+    }
+
+    /// Reads the keyring index from the key provider. If there is no index yet,
+    /// keypair stored as `.private_key` and `.public_key` (created with the given
+    /// options if absent) becomes the first and active key of the keyring.
+    pub fn open_with_options(key_provider: &dyn KeyProvider, secret: &str, options: &KeyOptions) -> Result<Keyring, CryptoError> {
+        let boxed_open = key_provider.open();
+        if boxed_open.is_err() {
+            return Err(boxed_open.err().unwrap());
+        }
+
+        let mut keyring = Keyring {
+            entries: vec![],
+            key_provider: key_provider.box_clone(),
+            secret: SecretString::from(secret),
+        };
+
+        let boxed_read = keyring.key_provider.read(KEYRING_INDEX_FILENAME);
+        if boxed_read.is_err() {
+            return Err(boxed_read.err().unwrap());
+        }
+        let boxed_content = boxed_read.unwrap();
+
+        if let Some(content) = boxed_content {
+            let boxed_index: Result<KeyringIndex, _> = serde_json::from_str(content.as_str());
+            if boxed_index.is_err() {
+                let message = format!("unable to parse keyring index: {}", boxed_index.err().unwrap());
+                return Err(CryptoError::Io(message))
+            }
+            keyring.entries = boxed_index.unwrap().keys;
+            return Ok(keyring);
+        }
+
+        let private_key_file = PRIVATE_KEY_NAME.to_string();
+        let public_key_file = PUBLIC_KEY_NAME.to_string();
+        let boxed_keys = keyring.get_or_create_keys(private_key_file.as_str(), public_key_file.as_str(), options);
+        if boxed_keys.is_err() {
+            return Err(boxed_keys.err().unwrap());
+        }
+        let (_, public_key, cipher) = boxed_keys.unwrap();
+
+        let boxed_key_id = get_key_id(public_key.as_str());
+        if boxed_key_id.is_err() {
+            return Err(boxed_key_id.err().unwrap());
+        }
+
+        // keypair may already exist in the provider, the algorithm is taken from the key itself
+        let boxed_algorithm = get_key_algorithm(public_key.as_str());
+        if boxed_algorithm.is_err() {
+            return Err(boxed_algorithm.err().unwrap());
+        }
+        let algorithm = boxed_algorithm.unwrap();
+        let padding = if algorithm == options.algorithm { options.padding } else { KeyOptions::new(algorithm).padding };
+
+        keyring.entries.push(KeyringEntry {
+            id: boxed_key_id.unwrap(),
+            created_at: get_unix_timestamp(),
+            algorithm,
+            padding,
+            cipher,
+            active: true,
+            private_key_file,
+            public_key_file,
+        });
+
+        let boxed_save = keyring.save();
+        if boxed_save.is_err() {
+            return Err(boxed_save.err().unwrap());
+        }
+
+        Ok(keyring)
+    }
+
+    pub fn active_for(&self, usage: KeyUsage) -> Result<&KeyringEntry, CryptoError> {
+        let boxed_active = self.entries
+            .iter()
+            .rev()
+            .find(|entry| entry.active && entry.algorithm.supports(usage));
+        if boxed_active.is_none() {
+            let message = format!("keyring has no active key for {:?}", usage);
+            return Err(CryptoError::Configuration(message))
+        }
+        Ok(boxed_active.unwrap())
+    }
+
+    pub fn find(&self, key_id: &str) -> Option<&KeyringEntry> {
+        self.entries.iter().find(|entry| entry.id == key_id)
+    }
+
+    pub fn get_encryption_parameters(&self, key_id: &str) -> Result<EncryptionParameters, CryptoError> {
+        let boxed_entry = self.find(key_id);
+        if boxed_entry.is_none() {
+            return Err(CryptoError::UnknownKey(key_id.to_string()))
+        }
+        let entry = boxed_entry.unwrap();
+
+        let boxed_private_key = self.key_provider.read(entry.private_key_file.as_str());
+        if boxed_private_key.is_err() {
+            return Err(boxed_private_key.err().unwrap());
+        }
+        if boxed_private_key.unwrap().is_none() {
+            let message = format!("private key file {} for key {} is missing", entry.private_key_file, key_id);
+            return Err(CryptoError::Io(message))
+        }
+
+        let boxed_keys = self.get_or_create_keys(entry.private_key_file.as_str(), entry.public_key_file.as_str(), &KeyOptions::new(entry.algorithm));
+        if boxed_keys.is_err() {
+            return Err(boxed_keys.err().unwrap());
+        }
+        let (private_key, public_key, cipher) = boxed_keys.unwrap();
+
+        let boxed_algorithm = get_key_algorithm(public_key.as_str());
+        if boxed_algorithm.is_err() {
+            return Err(boxed_algorithm.err().unwrap());
+        }
+        let algorithm = boxed_algorithm.unwrap();
+        if algorithm != entry.algorithm {
+            let message = format!("key {} is recorded as {} but is {}", key_id, entry.algorithm, algorithm);
+            return Err(CryptoError::MalformedKey(message))
+        }
+
+        if cipher != entry.cipher {
+            let message = format!("key {} is recorded as protected with {} but is protected with {}", key_id, entry.cipher, cipher);
+            return Err(CryptoError::MalformedKey(message))
+        }
+
+        // keys recorded before the padding was configurable use PKCS1
+        let padding = if algorithm == KeyAlgorithm::Rsa4096 { entry.padding.or(Some(RsaPadding::Pkcs1)) } else { None };
+
+        let params = EncryptionParameters {
+            key_id: entry.id.to_string(),
+            private_key,
+            public_key,
+            padding,
+        };
+
+        Ok(params)
+    }
+
+    /// Generates new keypair with the given options and makes it active for
+    /// the usages its algorithm supports. Previous keys are retained for decryption
+    /// and verification, those no longer used for anything become inactive.
+    /// Returns id of the new key.
+    pub fn rotate(&mut self, options: &KeyOptions) -> Result<String, CryptoError> {
+        let boxed_generated = generate_private_public_keys(self.secret.expose(), options);
+        if boxed_generated.is_err() {
+            return Err(boxed_generated.err().unwrap());
+        }
+        let (protected_private_key, public_key) = boxed_generated.unwrap();
+
+        self.add_key(protected_private_key.as_str(), public_key.as_str(), options)
+    }
+
+    /// Adds keypair generated elsewhere and makes it active the same way as `rotate`.
+    /// Private key is PEM encoded and unencrypted, it is protected with the keyring
+    /// secret using cipher and kdf from the options. If the public key is given,
+    /// it has to match the private key. Returns id of the imported key.
+    pub fn import(&mut self, private_key: &str, boxed_public_key: Option<&str>, options: &KeyOptions) -> Result<String, CryptoError> {
+        let boxed_pkey = PKey::private_key_from_pem(private_key.as_bytes());
+        if boxed_pkey.is_err() {
+            let message = format!("unable to parse imported private key: {}", boxed_pkey.err().unwrap());
+            return Err(CryptoError::MalformedKey(message))
+        }
+        let pkey = boxed_pkey.unwrap();
+
+        let boxed_algorithm = KeyAlgorithm::from_id(pkey.id());
+        if boxed_algorithm.is_err() {
+            return Err(boxed_algorithm.err().unwrap());
+        }
+        let algorithm = boxed_algorithm.unwrap();
+        if algorithm == KeyAlgorithm::Rsa4096 && pkey.bits() != RSA_SIZE {
+            let message = format!("imported rsa key has {} bits, only {} bit keys are supported", pkey.bits(), RSA_SIZE);
+            return Err(CryptoError::Unsupported(message))
+        }
+
+        let boxed_derived_public_key = get_public_key_pem(&pkey);
+        if boxed_derived_public_key.is_err() {
+            return Err(boxed_derived_public_key.err().unwrap());
+        }
+        let public_key = boxed_derived_public_key.unwrap();
+
+        if let Some(imported_public_key) = boxed_public_key {
+            let boxed_imported_public_key = PKey::public_key_from_pem(imported_public_key.as_bytes());
+            if boxed_imported_public_key.is_err() {
+                let message = format!("unable to parse imported public key: {}", boxed_imported_public_key.err().unwrap());
+                return Err(CryptoError::MalformedKey(message))
+            }
+            if !boxed_imported_public_key.unwrap().public_eq(&pkey) {
+                return Err(CryptoError::MalformedKey("imported public key does not match the private key".to_string()));
+            }
+        }
+
+        let boxed_private_key = get_private_key_pem(&pkey);
+        if boxed_private_key.is_err() {
+            return Err(boxed_private_key.err().unwrap());
+        }
+
+        // padding is kept only if the options were meant for the imported algorithm
+        let options = KeyOptions {
+            algorithm,
+            padding: if algorithm == options.algorithm { options.padding } else { KeyOptions::new(algorithm).padding },
+            cipher: options.cipher,
+            kdf: options.kdf.clone(),
+        };
+        let boxed_protected = protect_private_key(boxed_private_key.unwrap().expose(), self.secret.expose(), &options.kdf, options.cipher);
+        if boxed_protected.is_err() {
+            return Err(boxed_protected.err().unwrap());
+        }
+
+        self.add_key(boxed_protected.unwrap().as_str(), public_key.as_str(), &options)
+    }
+
+    /// Reads public key of the entry, private key is not decrypted.
+    pub fn get_public_key(&self, key_id: &str) -> Result<String, CryptoError> {
+        let boxed_entry = self.find(key_id);
+        if boxed_entry.is_none() {
+            return Err(CryptoError::UnknownKey(key_id.to_string()))
+        }
+
+        let public_key_file = boxed_entry.unwrap().public_key_file.as_str();
+        let boxed_public_key = self.key_provider.read(public_key_file);
+        if boxed_public_key.is_err() {
+            return Err(boxed_public_key.err().unwrap());
+        }
+        let boxed_content = boxed_public_key.unwrap();
+        if boxed_content.is_none() {
+            let message = format!("public key file {} for key {} is missing", public_key_file, key_id);
+            return Err(CryptoError::Io(message))
+        }
+        Ok(boxed_content.unwrap())
+    }
+
+    fn add_key(&mut self, protected_private_key: &str, public_key: &str, options: &KeyOptions) -> Result<String, CryptoError> {
+        let boxed_key_id = get_key_id(public_key);
+        if boxed_key_id.is_err() {
+            return Err(boxed_key_id.err().unwrap());
+        }
+        let key_id = boxed_key_id.unwrap();
+        if self.find(key_id.as_str()).is_some() {
+            let message = format!("key {} is already present in the keyring", key_id);
+            return Err(CryptoError::Configuration(message))
+        }
+
+        let private_key_file = [key_id.as_str(), ".private_key"].join("");
+        let public_key_file = [key_id.as_str(), ".public_key"].join("");
+
+        let boxed_write = read_or_create_and_write(self.key_provider.as_ref(), private_key_file.as_str(), protected_private_key);
+        if boxed_write.is_err() {
+            return Err(boxed_write.err().unwrap());
+        }
+
+        let boxed_write = read_or_create_and_write(self.key_provider.as_ref(), public_key_file.as_str(), public_key);
+        if boxed_write.is_err() {
+            return Err(boxed_write.err().unwrap());
+        }
+
+        self.entries.push(KeyringEntry {
+            id: key_id.to_string(),
+            created_at: get_unix_timestamp(),
+            algorithm: options.algorithm,
+            padding: options.padding,
+            cipher: options.cipher,
+            active: true,
+            private_key_file,
+            public_key_file,
+        });
+
+        // walking from the newest key, a key stays active only if it serves
+        // a usage not yet covered by a newer active key
+        let mut covered_usages: Vec<KeyUsage> = vec![];
+        for entry in self.entries.iter_mut().rev() {
+            if !entry.active {
+                continue;
+            }
+            let usages = entry.algorithm.usages();
+            entry.active = usages.iter().any(|usage| !covered_usages.contains(usage));
+            covered_usages.extend(usages);
+        }
+
+        let boxed_save = self.save();
+        if boxed_save.is_err() {
+            return Err(boxed_save.err().unwrap());
+        }
+
+        Ok(key_id)
+    }
+
+    /// Encrypts data with the active key, ciphertext is prefixed with the key id.
+    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let boxed_active = self.active_for(KeyUsage::Encryption);
+        if boxed_active.is_err() {
+            return Err(boxed_active.err().unwrap());
+        }
+
+        let boxed_params = self.get_encryption_parameters(boxed_active.unwrap().id.as_str());
+        if boxed_params.is_err() {
+            return Err(boxed_params.err().unwrap());
+        }
+        let params = boxed_params.unwrap();
+
+        let boxed_encrypted = encrypt(params.public_key.as_str(), params.padding.unwrap_or_default(), data);
+        if boxed_encrypted.is_err() {
+            return Err(boxed_encrypted.err().unwrap());
+        }
+
+        let mut tagged: Vec<u8> = params.key_id.as_bytes().to_vec();
+        tagged.append(&mut boxed_encrypted.unwrap());
+        Ok(tagged)
+    }
+
+    /// Decrypts data produced by `encrypt` with the key it was tagged with.
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if data.len() < KEY_ID_LENGTH {
+            return Err(CryptoError::Crypto("ciphertext is not tagged with key id".to_string()));
+        }
+        let (key_id, ciphertext) = data.split_at(KEY_ID_LENGTH);
+        let key_id = String::from_utf8_lossy(key_id).to_string();
+
+        let boxed_supported = self.check_supports(key_id.as_str(), KeyUsage::Encryption);
+        if boxed_supported.is_err() {
+            return Err(boxed_supported.err().unwrap());
+        }
+
+        let boxed_params = self.get_encryption_parameters(key_id.as_str());
+        if boxed_params.is_err() {
+            return Err(boxed_params.err().unwrap());
+        }
+        let params = boxed_params.unwrap();
+
+        decrypt(params.private_key.expose(), params.padding.unwrap_or_default(), ciphertext)
+    }
+
+    /// Signs data with the active key, signature has the form `key_id:signature`.
+    pub fn sign(&self, data: &[u8]) -> Result<String, CryptoError> {
+        let boxed_active = self.active_for(KeyUsage::Signing);
+        if boxed_active.is_err() {
+            return Err(boxed_active.err().unwrap());
+        }
+
+        let boxed_params = self.get_encryption_parameters(boxed_active.unwrap().id.as_str());
+        if boxed_params.is_err() {
+            return Err(boxed_params.err().unwrap());
+        }
+        let params = boxed_params.unwrap();
+
+        let boxed_signature = sign(params.private_key.expose(), data);
+        if boxed_signature.is_err() {
+            return Err(boxed_signature.err().unwrap());
+        }
+        let signature = boxed_signature.unwrap();
+
+        Ok([params.key_id.as_str(), SIGNATURE_KEY_ID_SEPARATOR, signature.as_str()].join(""))
+    }
+
+    /// Verifies signature produced by `sign` with the key it was tagged with.
+    pub fn verify(&self, data: &[u8], signature: &str) -> Result<bool, CryptoError> {
+        let boxed_split = signature.split_once(SIGNATURE_KEY_ID_SEPARATOR);
+        if boxed_split.is_none() {
+            return Ok(false);
+        }
+        let (key_id, signature) = boxed_split.unwrap();
+
+        let boxed_supported = self.check_supports(key_id, KeyUsage::Signing);
+        if boxed_supported.is_err() {
+            return Err(boxed_supported.err().unwrap());
+        }
+
+        let boxed_params = self.get_encryption_parameters(key_id);
+        if boxed_params.is_err() {
+            return Err(boxed_params.err().unwrap());
+        }
+        let params = boxed_params.unwrap();
+
+        verify(params.public_key.as_str(), data, signature)
+    }
+
+    fn check_supports(&self, key_id: &str, usage: KeyUsage) -> Result<(), CryptoError> {
+        let boxed_entry = self.find(key_id);
+        if boxed_entry.is_none() {
+            return Err(CryptoError::UnknownKey(key_id.to_string()))
+        }
+        let entry = boxed_entry.unwrap();
+
+        if !entry.algorithm.supports(usage) {
+            let message = format!("key {} is {} and does not support {:?}", key_id, entry.algorithm, usage);
+            return Err(CryptoError::Unsupported(message))
+        }
+        Ok(())
+    }
+
+    fn get_or_create_keys(&self, private_key_file: &str, public_key_file: &str, options: &KeyOptions) -> Result<(SecretString, String, KeyProtectionCipher), CryptoError> {
+        get_or_create_private_public_keys(self.secret.expose(), self.key_provider.as_ref(), public_key_file, private_key_file, LEGACY_PASSPHRASE_FILENAME, options)
+    }
+
+    fn save(&self) -> Result<(), CryptoError> {
+        let index = KeyringIndex { keys: self.entries.to_vec() };
+        let boxed_serialized = serde_json::to_string_pretty(&index);
+        if boxed_serialized.is_err() {
+            let message = format!("unable to serialize keyring index: {}", boxed_serialized.err().unwrap());
+            return Err(CryptoError::Io(message))
+        }
+
+        self.key_provider.write(KEYRING_INDEX_FILENAME, boxed_serialized.unwrap().as_str())
+    }
+}
+
+pub fn get_unix_timestamp() -> u64 {
+    let boxed_duration = SystemTime::now().duration_since(UNIX_EPOCH);
+    if boxed_duration.is_err() {
+        return 0;
+    }
+    boxed_duration.unwrap().as_secs()
+}
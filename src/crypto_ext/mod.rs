@@ -1,161 +1,312 @@
+use std::collections::HashMap;
 use std::env;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
-use sha256::digest;
-use openssl::rsa::Padding;
+use std::sync::Mutex;
+use openssl::encrypt::{Decrypter, Encrypter};
 use openssl::rsa::Rsa;
-use openssl::symm::Cipher;
-use openssl::sign::{Signer};
-use openssl::pkey::PKey;
+use openssl::pkey::{HasPrivate, HasPublic, Id, PKey, PKeyRef};
 use openssl::hash::MessageDigest;
-use hex::{self, FromHex};
-
+use openssl::sha::sha256;
+use openssl::sign::{Signer, Verifier};
+use algorithm::{KeyAlgorithm, RsaPadding};
+use error::CryptoError;
+use key_provider::KeyProvider;
+use secret::SecretString;
+use zeroize::Zeroize;
+use key_protection::{Kdf, KeyProtectionCipher, is_protected_private_key, parse_protected_private_key, protect_private_key, unprotect_private_key};
+
+pub mod algorithm;
+pub mod envelope;
+pub mod error;
+pub mod key_agreement;
+pub mod key_export;
+pub mod key_protection;
+pub mod key_provider;
+pub mod key_store;
+pub mod keyring;
+pub mod secret;
 
 #[cfg(test)]
 mod tests;
 
+/// Size of generated RSA keys, imported RSA keys must have the same size.
 pub const RSA_SIZE: u32 = 4096;
+pub const SECRET_ENVIRONMENT_VARIABLE: &str = "CRYPTO_EXT_SECRET";
+pub const KEY_ID_LENGTH: usize = 16;
+
+/// Private key, public key and the cipher the private key is protected with.
+type LoadedKeys = (SecretString, String, KeyProtectionCipher);
+
+/// Keys unprotected so far, keyed by `get_loaded_keys_cache_key`.
+static LOADED_KEYS: Mutex<Option<HashMap<String, LoadedKeys>>> = Mutex::new(None);
 
+/// Private key is zeroed on drop and redacted in `Debug` output.
+#[derive(Debug)]
 pub struct EncryptionParameters {
-    pub passphrase: String,
-    pub private_key: String,
+    pub key_id: String,
+    pub private_key: SecretString,
     pub public_key: String,
-    pub padding: String,
-    pub cipher: String,
+    pub padding: Option<RsaPadding>,
+}
+
+/// Options applied when a keypair is created. Padding is applicable to RSA
+/// keys only and has to be `None` for the other algorithms.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyOptions {
+    pub algorithm: KeyAlgorithm,
+    pub padding: Option<RsaPadding>,
+    pub cipher: KeyProtectionCipher,
+    pub kdf: Kdf,
 }
 
-fn setup_encryption(path_to_encryption_parameters: Option<&str>) -> Result<EncryptionParameters, String> {
-    let relative_path = get_path_relative_to_working_directory(path_to_encryption_parameters, ".passphrase");
-    let boxed_passphrase_path = get_static_filepath(relative_path.as_str());
-    if boxed_passphrase_path.is_err() {
-        return Err(boxed_passphrase_path.err().unwrap());
+impl KeyOptions {
+    pub fn new(algorithm: KeyAlgorithm) -> KeyOptions {
+        let padding = if algorithm == KeyAlgorithm::Rsa4096 { Some(RsaPadding::default()) } else { None };
+        KeyOptions {
+            algorithm,
+            padding,
+            cipher: KeyProtectionCipher::default(),
+            kdf: Kdf::default(),
+        }
     }
-    let passphrase_path = boxed_passphrase_path.unwrap();
 
-    let boxed_passphrase = get_or_create_passphrase(passphrase_path.as_str());
-    if boxed_passphrase.is_err() {
-        return Err(boxed_passphrase.err().unwrap());
+    pub fn validate(&self) -> Result<(), CryptoError> {
+        let is_rsa = self.algorithm == KeyAlgorithm::Rsa4096;
+        if is_rsa && self.padding.is_none() {
+            return Err(CryptoError::Configuration("padding is required for RSA keys".to_string()));
+        }
+        if !is_rsa && self.padding.is_some() {
+            let message = format!("padding is not applicable to {} keys", self.algorithm);
+            return Err(CryptoError::Configuration(message))
+        }
+        Ok(())
     }
-    let passphrase = boxed_passphrase.unwrap();
+}
 
+impl Default for KeyOptions {
+    fn default() -> Self {
+        KeyOptions::new(KeyAlgorithm::default())
+    }
+}
 
-    let relative_path = get_path_relative_to_working_directory(path_to_encryption_parameters, ".public_key");
-    let boxed_public_key_path = get_static_filepath(relative_path.as_str());
-    if boxed_public_key_path.is_err() {
-        return Err(boxed_public_key_path.err().unwrap());
+/// Returns secret used to protect the private key, supplied by the operator
+/// at runtime via the environment.
+pub fn get_secret_from_environment() -> Result<SecretString, CryptoError> {
+    let boxed_secret = env::var(SECRET_ENVIRONMENT_VARIABLE);
+    if boxed_secret.is_err() {
+        let message = format!("{} environment variable is not set", SECRET_ENVIRONMENT_VARIABLE);
+        return Err(CryptoError::Configuration(message))
     }
-    let public_key_path = boxed_public_key_path.unwrap();
 
+    let secret = boxed_secret.unwrap();
+    if secret.is_empty() {
+        let message = format!("{} environment variable is empty", SECRET_ENVIRONMENT_VARIABLE);
+        return Err(CryptoError::Configuration(message))
+    }
 
-    let relative_path = get_path_relative_to_working_directory(path_to_encryption_parameters, ".private_key");
-    let boxed_private_key_path = get_static_filepath(relative_path.as_str());
-    if boxed_private_key_path.is_err() {
-        return Err(boxed_private_key_path.err().unwrap());
+    Ok(SecretString::new(secret))
+}
+
+fn encrypt(public_key: &str, padding: RsaPadding, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let boxed_pkey = PKey::public_key_from_pem(public_key.as_bytes());
+    if boxed_pkey.is_err() {
+        let message = format!("unable to parse public key: {}", boxed_pkey.err().unwrap());
+        return Err(CryptoError::MalformedKey(message))
     }
-    let private_key_path = boxed_private_key_path.unwrap();
+    let pkey = boxed_pkey.unwrap();
 
+    if pkey.id() == Id::X25519 {
+        return key_agreement::wrap(&pkey, data);
+    }
+    if pkey.id() != Id::RSA {
+        let message = format!("{:?} key can not be used for encryption", pkey.id());
+        return Err(CryptoError::Unsupported(message))
+    }
 
-    let boxed_keys = get_or_create_private_public_keys(passphrase.as_str(), public_key_path.as_str(), private_key_path.as_str());
-    if boxed_keys.is_err() {
-        return Err(boxed_keys.err().unwrap());
+    // key smaller than the padding overhead can not encrypt anything
+    let max_size = pkey.size().saturating_sub(padding.get_overhead());
+    if data.len() > max_size {
+        return Err(CryptoError::DataTooLarge { size: data.len(), max_size })
     }
 
-    let (private_key, public_key) = boxed_keys.unwrap();
+    let boxed_encrypter = Encrypter::new(&pkey);
+    if boxed_encrypter.is_err() {
+        let message = format!("unable to encrypt: {}", boxed_encrypter.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+    let mut encrypter = boxed_encrypter.unwrap();
 
-    let padding = "PKCS1".to_string();
-    let cipher = "aes_128_cbc".to_string();
+    let mut boxed_padding = encrypter.set_rsa_padding(padding.get_padding());
+    let boxed_oaep_digest = padding.get_oaep_digest();
+    if let Some(oaep_digest) = boxed_oaep_digest.filter(|_| boxed_padding.is_ok()) {
+        boxed_padding = encrypter.set_rsa_oaep_md(oaep_digest)
+            .and_then(|_| encrypter.set_rsa_mgf1_md(oaep_digest));
+    }
+    if boxed_padding.is_err() {
+        let message = format!("unable to set {} padding: {}", padding, boxed_padding.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
 
-    let params = EncryptionParameters {
-        passphrase,
-        private_key,
-        public_key,
-        padding,
-        cipher,
-    };
+    let boxed_length = encrypter.encrypt_len(data);
+    if boxed_length.is_err() {
+        let message = format!("unable to encrypt: {}", boxed_length.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
 
-    Ok(params)
+    let mut buffer : Vec<u8> = vec![0; boxed_length.unwrap()];
+    let boxed_encrypt = encrypter.encrypt(data, &mut buffer);
+    if boxed_encrypt.is_err() {
+        let message = format!("unable to encrypt: {}", boxed_encrypt.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+    buffer.truncate(boxed_encrypt.unwrap());
+    Ok(buffer)
 }
 
-fn encrypt(public_key: &str, data: &[u8]) -> Vec<u8> {
-    let rsa = Rsa::public_key_from_pem(public_key.as_bytes()).unwrap();
-    let mut buffer : Vec<u8> = vec![0; rsa.size() as usize];
-    let _ = rsa.public_encrypt(data, &mut buffer, Padding::PKCS1).unwrap();
-    buffer
-}
+fn decrypt(private_key: &str, padding: RsaPadding, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let boxed_pkey = PKey::private_key_from_pem(private_key.as_bytes());
+    if boxed_pkey.is_err() {
+        let message = format!("unable to parse private key: {}", boxed_pkey.err().unwrap());
+        return Err(CryptoError::MalformedKey(message))
+    }
+    let pkey = boxed_pkey.unwrap();
 
-fn decrypt(private_key: &str, passphrase: &str, data: &[u8]) -> Vec<u8> {
-    let rsa = Rsa::private_key_from_pem_passphrase(private_key.as_bytes(), passphrase.as_bytes()).unwrap();
-    let mut buffer: Vec<u8> = vec![0; rsa.size() as usize];
-    let _ = rsa.private_decrypt(data, &mut buffer, Padding::PKCS1).unwrap();
-    buffer
-}
+    if pkey.id() == Id::X25519 {
+        return key_agreement::unwrap(&pkey, data);
+    }
+    if pkey.id() != Id::RSA {
+        let message = format!("{:?} key can not be used for decryption", pkey.id());
+        return Err(CryptoError::Unsupported(message))
+    }
 
-fn sign(private_key: &str, passphrase: &str, data: &[u8]) -> String {
-    //TODO:
-    "".to_string()
-}
+    let boxed_decrypter = Decrypter::new(&pkey);
+    if boxed_decrypter.is_err() {
+        let message = format!("unable to decrypt: {}", boxed_decrypter.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+    let mut decrypter = boxed_decrypter.unwrap();
 
-fn verify(public_key: &str, data: &[u8], signature: &str) -> bool {
-    //TODO
-    false
+    let mut boxed_padding = decrypter.set_rsa_padding(padding.get_padding());
+    let boxed_oaep_digest = padding.get_oaep_digest();
+    if let Some(oaep_digest) = boxed_oaep_digest.filter(|_| boxed_padding.is_ok()) {
+        boxed_padding = decrypter.set_rsa_oaep_md(oaep_digest)
+            .and_then(|_| decrypter.set_rsa_mgf1_md(oaep_digest));
+    }
+    if boxed_padding.is_err() {
+        let message = format!("unable to set {} padding: {}", padding, boxed_padding.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    let boxed_length = decrypter.decrypt_len(data);
+    if boxed_length.is_err() {
+        let message = format!("unable to decrypt: {}", boxed_length.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    // buffer is sized to the modulus, plaintext is copied out and the buffer wiped
+    let mut buffer: Vec<u8> = vec![0; boxed_length.unwrap()];
+    let boxed_decrypt = decrypter.decrypt(data, &mut buffer);
+    if boxed_decrypt.is_err() {
+        buffer.zeroize();
+        let message = format!("unable to decrypt: {}", boxed_decrypt.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+    let plaintext = buffer[..boxed_decrypt.unwrap()].to_vec();
+    buffer.zeroize();
+    Ok(plaintext)
 }
 
-fn get_or_create_passphrase(path: &str) -> Result<String, String> {
+fn sign(private_key: &str, data: &[u8]) -> Result<String, CryptoError> {
+    let boxed_pkey = PKey::private_key_from_pem(private_key.as_bytes());
+    if boxed_pkey.is_err() {
+        let message = format!("unable to parse private key: {}", boxed_pkey.err().unwrap());
+        return Err(CryptoError::MalformedKey(message))
+    }
+    let pkey = boxed_pkey.unwrap();
+
+    let boxed_signature = if pkey.id() == Id::ED25519 {
+        Signer::new_without_digest(&pkey)
+            .and_then(|mut signer| signer.sign_oneshot_to_vec(data))
+    } else if pkey.id() == Id::RSA {
+        Signer::new(MessageDigest::sha256(), &pkey)
+            .and_then(|mut signer| signer.update(data).and_then(|_| signer.sign_to_vec()))
+    } else {
+        let message = format!("{:?} key can not be used for signing", pkey.id());
+        return Err(CryptoError::Unsupported(message))
+    };
+    if boxed_signature.is_err() {
+        let message = format!("unable to sign: {}", boxed_signature.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    Ok(hex::encode(boxed_signature.unwrap()))
+}
 
-    let boxed_passphrase = generate_passphrase();
-    if boxed_passphrase.is_err() {
-        let message = boxed_passphrase.err().unwrap();
-        return Err(message)
+/// Returns `Ok(false)` if the signature does not match the data or is not
+/// a signature at all, errors are reserved for unusable public keys.
+pub fn verify(public_key: &str, data: &[u8], signature: &str) -> Result<bool, CryptoError> {
+    let boxed_pkey = PKey::public_key_from_pem(public_key.as_bytes());
+    if boxed_pkey.is_err() {
+        let message = format!("unable to parse public key: {}", boxed_pkey.err().unwrap());
+        return Err(CryptoError::MalformedKey(message))
     }
+    let pkey = boxed_pkey.unwrap();
 
-    let passphrase = boxed_passphrase.unwrap();
+    if pkey.id() != Id::ED25519 && pkey.id() != Id::RSA {
+        let message = format!("{:?} key can not be used for verification", pkey.id());
+        return Err(CryptoError::Unsupported(message))
+    }
 
-    let boxed_passphrase = read_or_create_and_write(path, passphrase.as_str());
-    if boxed_passphrase.is_err() {
-        let message = boxed_passphrase.err().unwrap();
-        return Err(message)
+    let boxed_signature = hex::decode(signature);
+    if boxed_signature.is_err() {
+        return Ok(false);
     }
+    let signature = boxed_signature.unwrap();
+
+    let boxed_verify = if pkey.id() == Id::ED25519 {
+        Verifier::new_without_digest(&pkey)
+            .and_then(|mut verifier| verifier.verify_oneshot(signature.as_ref(), data))
+    } else {
+        Verifier::new(MessageDigest::sha256(), &pkey)
+            .and_then(|mut verifier| verifier.update(data).and_then(|_| verifier.verify(signature.as_ref())))
+    };
 
-    let passphrase = boxed_passphrase.unwrap();
-    Ok(passphrase)
+    Ok(boxed_verify.unwrap_or(false))
 }
 
-fn read_or_create_and_write(path: &str, content: &str) -> Result<String, String> {
-    let does_passphrase_exist = does_file_exist(path);
-    return if does_passphrase_exist {
-        let boxed_read = read_file(path);
-        if boxed_read.is_err() {
-            return Err(boxed_read.err().unwrap());
-        }
-        let passphrase = boxed_read.unwrap();
-        Ok(passphrase)
-    } else {
-        let boxed_create = create_file(path);
-        if boxed_create.is_err() {
-            let message = boxed_create.err().unwrap();
-            return Err(message)
-        }
+fn read_or_create_and_write(key_provider: &dyn KeyProvider, name: &str, content: &str) -> Result<String, CryptoError> {
+    let boxed_read = key_provider.read(name);
+    if boxed_read.is_err() {
+        return Err(boxed_read.err().unwrap());
+    }
+    if let Some(content) = boxed_read.unwrap() {
+        return Ok(content);
+    }
 
-        let boxed_write = write_file(path, content.as_bytes());
-        if boxed_write.is_err() {
-            let message = boxed_write.err().unwrap();
-            return Err(message)
-        }
-        Ok(content.to_string())
+    let boxed_write = key_provider.write(name, content);
+    if boxed_write.is_err() {
+        return Err(boxed_write.err().unwrap());
     }
+    Ok(content.to_string())
 }
 
-fn create_file(path: &str) -> Result<File, String>  {
+fn create_file(path: &str) -> Result<File, CryptoError>  {
     let boxed_file = File::create(path);
 
     if boxed_file.is_err() {
         let message = format!("unable to create file: {}", boxed_file.err().unwrap());
-        return Err(message)
+        return Err(CryptoError::Io(message))
     }
 
     let file = boxed_file.unwrap();
+
+    // permissions are restricted before anything is written to the file
+    let boxed_restrict = key_store::restrict_permissions(Path::new(path), key_store::FILE_MODE);
+    if boxed_restrict.is_err() {
+        return Err(boxed_restrict.err().unwrap());
+    }
     Ok(file)
 }
 
@@ -164,7 +315,7 @@ fn does_file_exist(path: &str) -> bool {
     file_exists
 }
 
-fn read_file(path: &str) -> Result<String, String> {
+fn read_file(path: &str) -> Result<String, CryptoError> {
     let mut file_contents : String = "".to_string();
     let boxed_open = OpenOptions::new()
         .read(true)
@@ -174,7 +325,7 @@ fn read_file(path: &str) -> Result<String, String> {
         .open(path);
     if boxed_open.is_err() {
         let message = format!("unable to read from file: {}", boxed_open.err().unwrap());
-        return Err(message)
+        return Err(CryptoError::Io(message))
     }
 
     let mut file = boxed_open.unwrap();
@@ -182,111 +333,297 @@ fn read_file(path: &str) -> Result<String, String> {
     let boxed_read = file.read_to_string(&mut file_contents);
     if boxed_read.is_err() {
         let message = format!("unable to read from file: {}", boxed_read.err().unwrap());
-        return Err(message)
+        return Err(CryptoError::Io(message))
     }
 
     Ok(file_contents)
 }
 
-fn write_file(path: &str, file_content: &[u8]) -> Result<(), String> {
-    let mut file = OpenOptions::new()
+fn overwrite_file(path: &str, file_content: &[u8]) -> Result<(), CryptoError>{
+    let boxed_open = OpenOptions::new()
         .read(false)
         .write(true)
         .create(false)
-        .truncate(false)
-        .open(path)
-        .unwrap();
-    let boxed_write = file.write_all(file_content);
-    if boxed_write.is_err() {
-        let message = format!("unable to write to file: {}", boxed_write.err().unwrap());
-        return Err(message)
+        .truncate(true)
+        .open(path);
+    if boxed_open.is_err() {
+        let message = format!("unable to overwrite to file: {}", boxed_open.err().unwrap());
+        return Err(CryptoError::Io(message))
     }
-    Ok(())
-}
 
-fn overwrite_file(path: &str, file_content: &[u8]) -> Result<(), String>{
-    let mut file = OpenOptions::new()
-        .read(false)
-        .write(true)
-        .create(false)
-        .truncate(true)
-        .open(path)
-        .unwrap();
+    let mut file = boxed_open.unwrap();
     let boxed_write = file.write_all(file_content);
     if boxed_write.is_err() {
         let message = format!("unable to overwrite to file: {}", boxed_write.err().unwrap());
-        return Err(message)
+        return Err(CryptoError::Io(message))
     }
     Ok(())
 }
 
-fn generate_passphrase() -> Result<String, String> {
-    let now = SystemTime::now();
-    let boxed_time_in_nanos = now.duration_since(UNIX_EPOCH);
-    if boxed_time_in_nanos.is_err() {
-        let message = format!("unable to get system time: {}", boxed_time_in_nanos.err().unwrap());
-        return Err(message)
-    }
-    let time_in_nanos = boxed_time_in_nanos.unwrap().as_nanos();
-    let hex_time_in_millis = format!("{time_in_nanos:X}");
-    let sha_timestamp = digest(hex_time_in_millis);
-    Ok(sha_timestamp)
-}
+/// Reads the keypair, the private key is generated only if absent. Unprotected
+/// keys are cached for the process lifetime, the costly key derivation runs once
+/// per key. Public key is derived from the private key if absent, otherwise it has
+/// to match the private key.
+fn get_or_create_private_public_keys(secret: &str, key_provider: &dyn KeyProvider, public_key_name: &str, private_key_name: &str, legacy_passphrase_name: &str, options: &KeyOptions) -> Result<LoadedKeys, CryptoError> {
+    let boxed_read = key_provider.read(private_key_name);
+    if boxed_read.is_err() {
+        return Err(boxed_read.err().unwrap());
+    }
+    let boxed_content = boxed_read.unwrap();
+
+    let protected_private_key = if let Some(content) = boxed_content {
+        if is_protected_private_key(content.as_str()) {
+            content
+        } else {
+            let boxed_migrate = migrate_legacy_private_key(content.as_str(), secret, key_provider, private_key_name, legacy_passphrase_name);
+            if boxed_migrate.is_err() {
+                return Err(boxed_migrate.err().unwrap());
+            }
+            boxed_migrate.unwrap()
+        }
+    } else {
+        let boxed_generated = generate_private_public_keys(secret, options);
+        if boxed_generated.is_err() {
+            return Err(boxed_generated.err().unwrap());
+        }
+        let (protected_private_key, _) = boxed_generated.unwrap();
 
-fn get_or_create_private_public_keys(passphrase: &str, public_key_path: &str, private_key_path: &str) -> Result<(String, String), String> {
-    let rsa = Rsa::generate(RSA_SIZE).unwrap();
+        let boxed_private_key = read_or_create_and_write(key_provider, private_key_name, protected_private_key.as_str());
+        if boxed_private_key.is_err() {
+            let message = boxed_private_key.err().unwrap();
+            return Err(message)
+        }
+        boxed_private_key.unwrap()
+    };
 
-    let boxed_private_key = rsa.private_key_to_pem_passphrase(Cipher::aes_128_cbc(), passphrase.as_bytes());
-    let private_key  = String::from_utf8(boxed_private_key.unwrap()).unwrap();
+    let boxed_read = key_provider.read(public_key_name);
+    if boxed_read.is_err() {
+        return Err(boxed_read.err().unwrap());
+    }
+    let boxed_stored_public_key = boxed_read.unwrap();
+
+    let cache_key = get_loaded_keys_cache_key(secret, protected_private_key.as_str());
+    if let Some((private_key, public_key, cipher)) = get_loaded_keys(cache_key.as_str()) {
+        // public key replaced since the keys were loaded is validated again below
+        if boxed_stored_public_key.is_none() || boxed_stored_public_key.as_ref().unwrap() == &public_key {
+            let boxed_public_key = read_or_create_and_write(key_provider, public_key_name, public_key.as_str());
+            if boxed_public_key.is_err() {
+                return Err(boxed_public_key.err().unwrap());
+            }
+            return Ok((private_key, public_key, cipher));
+        }
+    }
 
-    let boxed_private_key = read_or_create_and_write(private_key_path, private_key.as_str());
+    let boxed_parsed = parse_protected_private_key(protected_private_key.as_str());
+    if boxed_parsed.is_err() {
+        return Err(boxed_parsed.err().unwrap());
+    }
+    let (header, _) = boxed_parsed.unwrap();
+
+    let boxed_private_key = unprotect_private_key(protected_private_key.as_str(), secret);
     if boxed_private_key.is_err() {
-        let message = boxed_private_key.err().unwrap();
-        return Err(message)
+        return Err(boxed_private_key.err().unwrap());
     }
     let private_key = boxed_private_key.unwrap();
 
+    let boxed_pkey = PKey::private_key_from_pem(private_key.expose().as_bytes());
+    if boxed_pkey.is_err() {
+        let message = format!("unable to parse private key: {}", boxed_pkey.err().unwrap());
+        return Err(CryptoError::MalformedKey(message))
+    }
+    let pkey = boxed_pkey.unwrap();
+
+    let public_key = if let Some(stored_public_key) = boxed_stored_public_key {
+        let boxed_stored_pkey = PKey::public_key_from_pem(stored_public_key.as_bytes());
+        if boxed_stored_pkey.is_err() {
+            let message = format!("unable to parse public key {}: {}", public_key_name, boxed_stored_pkey.err().unwrap());
+            return Err(CryptoError::MalformedKey(message))
+        }
+        if !boxed_stored_pkey.unwrap().public_eq(&pkey) {
+            let message = format!("public key {} does not match private key {}", public_key_name, private_key_name);
+            return Err(CryptoError::MalformedKey(message))
+        }
+        stored_public_key
+    } else {
+        let boxed_public_key = get_public_key_pem(&pkey);
+        if boxed_public_key.is_err() {
+            return Err(boxed_public_key.err().unwrap());
+        }
+        let public_key = boxed_public_key.unwrap();
+
+        let boxed_write = key_provider.write(public_key_name, public_key.as_str());
+        if boxed_write.is_err() {
+            return Err(boxed_write.err().unwrap());
+        }
+        public_key
+    };
+
+    insert_loaded_keys(cache_key, (private_key.clone(), public_key.to_string(), header.cipher));
+    Ok((private_key, public_key, header.cipher))
+}
+
+/// Cache key covers the secret as well, a key loaded once is not handed out for a wrong secret.
+fn get_loaded_keys_cache_key(secret: &str, protected_private_key: &str) -> String {
+    hex::encode(sha256([secret, protected_private_key].join("\n").as_bytes()))
+}
+
+fn get_loaded_keys(cache_key: &str) -> Option<LoadedKeys> {
+    let boxed_loaded_keys = LOADED_KEYS.lock();
+    if boxed_loaded_keys.is_err() {
+        return None;
+    }
+    let loaded_keys = boxed_loaded_keys.unwrap();
+    if loaded_keys.is_none() {
+        return None;
+    }
+    loaded_keys.as_ref().unwrap().get(cache_key).cloned()
+}
+
+fn insert_loaded_keys(cache_key: String, keys: LoadedKeys) {
+    let boxed_loaded_keys = LOADED_KEYS.lock();
+    if boxed_loaded_keys.is_err() {
+        return;
+    }
+    boxed_loaded_keys.unwrap().get_or_insert_with(HashMap::new).insert(cache_key, keys);
+}
+
+/// Generates new keypair, returns private key protected by the secret and public key.
+fn generate_private_public_keys(secret: &str, options: &KeyOptions) -> Result<(String, String), CryptoError> {
+    let boxed_valid = options.validate();
+    if boxed_valid.is_err() {
+        return Err(boxed_valid.err().unwrap());
+    }
+
+    let boxed_pkey = options.algorithm.generate();
+    if boxed_pkey.is_err() {
+        return Err(boxed_pkey.err().unwrap());
+    }
+    let pkey = boxed_pkey.unwrap();
 
-    let boxed_public_key = rsa.public_key_to_pem();
-    let public_key = String::from_utf8(boxed_public_key.unwrap()).unwrap();
+    let boxed_private_key = get_private_key_pem(&pkey);
+    if boxed_private_key.is_err() {
+        return Err(boxed_private_key.err().unwrap());
+    }
+    let private_key = boxed_private_key.unwrap();
 
-    let boxed_public_key = read_or_create_and_write(public_key_path, public_key.as_str());
+    let boxed_public_key = get_public_key_pem(&pkey);
     if boxed_public_key.is_err() {
-        let message = boxed_public_key.err().unwrap();
-        return Err(message)
+        return Err(boxed_public_key.err().unwrap());
     }
     let public_key = boxed_public_key.unwrap();
 
-    Ok((private_key.to_string(), public_key.to_string()))
+    let boxed_protected = protect_private_key(private_key.expose(), secret, &options.kdf, options.cipher);
+    if boxed_protected.is_err() {
+        return Err(boxed_protected.err().unwrap());
+    }
+    let protected_private_key = boxed_protected.unwrap();
+
+    Ok((protected_private_key, public_key))
 }
 
-pub fn get_static_filepath(path: &str) -> Result<String, String> {
-    let boxed_dir = env::current_dir();
-    if boxed_dir.is_err() {
-        let error = boxed_dir.err().unwrap();
-        eprintln!("{}", error);
-        return Err(error.to_string());
+/// Key id is the beginning of the SHA-256 of the DER encoded public key.
+fn get_key_id(public_key: &str) -> Result<String, CryptoError> {
+    let boxed_pkey = PKey::public_key_from_pem(public_key.as_bytes());
+    if boxed_pkey.is_err() {
+        let message = format!("unable to parse public key: {}", boxed_pkey.err().unwrap());
+        return Err(CryptoError::MalformedKey(message))
     }
-    let dir = boxed_dir.unwrap();
 
+    let boxed_der = boxed_pkey.unwrap().public_key_to_der();
+    if boxed_der.is_err() {
+        let message = format!("unable to encode public key: {}", boxed_der.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    let key_id = hex::encode(sha256(boxed_der.unwrap().as_ref()));
+    Ok(key_id[..KEY_ID_LENGTH].to_string())
+}
 
-    let boxed_working_directory = dir.as_path().to_str();
-    if boxed_working_directory.is_none() {
-        let error = "working directory is not set";
-        eprintln!("{}", error);
-        return Err(error.to_string());
+fn get_key_algorithm(public_key: &str) -> Result<KeyAlgorithm, CryptoError> {
+    let boxed_pkey = PKey::public_key_from_pem(public_key.as_bytes());
+    if boxed_pkey.is_err() {
+        let message = format!("unable to parse public key: {}", boxed_pkey.err().unwrap());
+        return Err(CryptoError::MalformedKey(message))
     }
 
-    let working_directory = boxed_working_directory.unwrap();
-    let absolute_path = [working_directory, path].join("");
-    Ok(absolute_path)
+    KeyAlgorithm::from_id(boxed_pkey.unwrap().id())
 }
 
-fn get_path_relative_to_working_directory(boxed_path_to_encryption_parameters: Option<&str>, filename: &str) -> String {
-    if boxed_path_to_encryption_parameters.is_some() {
-        let path_to_encryption_parameters = boxed_path_to_encryption_parameters.unwrap();
-        return [path_to_encryption_parameters, filename].join("");
+/// Converts private key protected by the passphrase stored in plaintext
+/// beside it into the secret protected format and removes the passphrase.
+fn migrate_legacy_private_key(legacy_private_key: &str, secret: &str, key_provider: &dyn KeyProvider, private_key_name: &str, legacy_passphrase_name: &str) -> Result<String, CryptoError> {
+    let boxed_read = key_provider.read(legacy_passphrase_name);
+    if boxed_read.is_err() {
+        return Err(boxed_read.err().unwrap());
+    }
+    let boxed_passphrase = boxed_read.unwrap();
+    if boxed_passphrase.is_none() {
+        let message = format!("private key is in legacy format, passphrase {} is required to migrate it", legacy_passphrase_name);
+        return Err(CryptoError::Configuration(message))
+    }
+    let passphrase = SecretString::new(boxed_passphrase.unwrap());
+
+    let boxed_rsa = Rsa::private_key_from_pem_passphrase(legacy_private_key.as_bytes(), passphrase.expose().as_bytes());
+    if boxed_rsa.is_err() {
+        return Err(CryptoError::BadPassphrase)
+    }
+    let boxed_pkey = PKey::from_rsa(boxed_rsa.unwrap());
+    if boxed_pkey.is_err() {
+        let message = format!("unable to read legacy private key: {}", boxed_pkey.err().unwrap());
+        return Err(CryptoError::MalformedKey(message))
+    }
+
+    let boxed_private_key = get_private_key_pem(&boxed_pkey.unwrap());
+    if boxed_private_key.is_err() {
+        return Err(boxed_private_key.err().unwrap());
+    }
+    let private_key = boxed_private_key.unwrap();
+
+    let boxed_protected = protect_private_key(private_key.expose(), secret, &Kdf::default(), KeyProtectionCipher::default());
+    if boxed_protected.is_err() {
+        return Err(boxed_protected.err().unwrap());
     }
+    let protected_private_key = boxed_protected.unwrap();
 
-    filename.to_string()
-}
\ No newline at end of file
+    let boxed_write = key_provider.write(private_key_name, protected_private_key.as_str());
+    if boxed_write.is_err() {
+        return Err(boxed_write.err().unwrap());
+    }
+
+    let boxed_remove = key_provider.remove(legacy_passphrase_name);
+    if boxed_remove.is_err() {
+        return Err(boxed_remove.err().unwrap());
+    }
+
+    Ok(protected_private_key)
+}
+
+fn get_private_key_pem<T: HasPrivate>(pkey: &PKeyRef<T>) -> Result<SecretString, CryptoError> {
+    let boxed_pem = pkey.private_key_to_pem_pkcs8();
+    if boxed_pem.is_err() {
+        let message = format!("unable to encode private key: {}", boxed_pem.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    let boxed_private_key = String::from_utf8(boxed_pem.unwrap());
+    if boxed_private_key.is_err() {
+        let message = format!("unable to encode private key: {}", boxed_private_key.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+    Ok(SecretString::new(boxed_private_key.unwrap()))
+}
+
+fn get_public_key_pem<T: HasPublic>(pkey: &PKeyRef<T>) -> Result<String, CryptoError> {
+    let boxed_pem = pkey.public_key_to_pem();
+    if boxed_pem.is_err() {
+        let message = format!("unable to encode public key: {}", boxed_pem.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+
+    let boxed_public_key = String::from_utf8(boxed_pem.unwrap());
+    if boxed_public_key.is_err() {
+        let message = format!("unable to encode public key: {}", boxed_public_key.err().unwrap());
+        return Err(CryptoError::Crypto(message))
+    }
+    Ok(boxed_public_key.unwrap())
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+use openssl::memcmp;
+use zeroize::Zeroize;
+
+/// String holding secret material: the operator secret, passphrases and unencrypted
+/// private keys. Memory is zeroed on drop and `Debug` output is redacted. Content
+/// is only lent out by `expose`, copies have to be made explicitly with `clone`.
+#[derive(Clone, Default)]
+pub struct SecretString {
+    value: String,
+}
+
+impl SecretString {
+    pub fn new(value: String) -> SecretString {
+        SecretString { value }
+    }
+
+    pub fn expose(&self) -> &str {
+        self.value.as_str()
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        SecretString::new(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        SecretString::new(value.to_string())
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.value.zeroize();
+    }
+}
+
+/// Comparison time does not depend on the position of the first difference.
+impl PartialEq for SecretString {
+    fn eq(&self, other: &Self) -> bool {
+        self.value.len() == other.value.len() && memcmp::eq(self.value.as_bytes(), other.value.as_bytes())
+    }
+}
+
+impl Eq for SecretString {}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretString([REDACTED])")
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::crawler::FetchEvent;
+use crate::estimator::Estimate;
+use crate::metrics::get_error_class;
+
+#[cfg(test)]
+mod tests;
+
+/// Fetches listed under the progress, newest first.
+pub const RECENT_FETCHES: usize = 10;
+/// Screen is redrawn at most this often on busy crawls.
+pub const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+const PROGRESS_BAR_WIDTH: usize = 40;
+
+/// Keys pressed on the dashboard, shared with the crawl workers.
+#[derive(Debug, Default)]
+pub struct DashboardControl {
+    is_paused: AtomicBool,
+    is_backup_requested: AtomicBool,
+    is_stop_requested: AtomicBool,
+}
+
+impl DashboardControl {
+    /// `p` or space pauses and resumes, `b` takes a backup, `q` or Ctrl-C stops the crawl.
+    pub fn handle_key(&self, key: u8) {
+        match key {
+            b'p' | b'P' | b' ' => { self.is_paused.fetch_xor(true, Ordering::SeqCst); }
+            b'b' | b'B' => self.is_backup_requested.store(true, Ordering::SeqCst),
+            b'q' | b'Q' | 3 => {
+                self.is_stop_requested.store(true, Ordering::SeqCst);
+                self.is_paused.store(false, Ordering::SeqCst);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused.load(Ordering::SeqCst)
+    }
+
+    pub fn is_stop_requested(&self) -> bool {
+        self.is_stop_requested.load(Ordering::SeqCst)
+    }
+
+    /// Returns true once per press of the backup key.
+    pub fn take_backup_request(&self) -> bool {
+        self.is_backup_requested.swap(false, Ordering::SeqCst)
+    }
+
+    /// Holds a worker before its next request for as long as the crawl is paused.
+    pub fn wait_while_paused(&self) {
+        while self.is_paused() && !self.is_stop_requested() {
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecentFetch {
+    pub app_id: i64,
+    /// App name, or the error of a failed attempt.
+    pub outcome: String,
+    pub error_class: Option<&'static str>,
+    pub at: u64,
+}
+
+/// What the dashboard shows, kept apart from the terminal so it can be tested.
+#[derive(Clone, Debug)]
+pub struct DashboardState {
+    fetched: usize,
+    failed: usize,
+    requests_per_minute: u32,
+    retry_delay_seconds: u64,
+    recent: VecDeque<RecentFetch>,
+    /// Requests of the last minute, for the rate limit budget.
+    requests: VecDeque<u64>,
+    /// Failed apps waiting for their retry and when they failed.
+    backing_off: HashMap<i64, u64>,
+    backup_status: Option<String>,
+}
+
+impl DashboardState {
+    pub fn new(requests_per_minute: u32, retry_delay_seconds: u64) -> DashboardState {
+        DashboardState {
+            fetched: 0,
+            failed: 0,
+            requests_per_minute,
+            retry_delay_seconds,
+            recent: VecDeque::new(),
+            requests: VecDeque::new(),
+            backing_off: HashMap::new(),
+            backup_status: None,
+        }
+    }
+
+    pub fn record_event(&mut self, event: &FetchEvent, now: u64) {
+        match event {
+            FetchEvent::Fetched { app_id, name } => {
+                self.fetched += 1;
+                self.requests.push_back(now);
+                self.push_recent(RecentFetch { app_id: *app_id, outcome: name.to_string(), error_class: None, at: now });
+            }
+            FetchEvent::Failed { app_id, error } => {
+                self.requests.push_back(now);
+                self.backing_off.insert(*app_id, now);
+                self.push_recent(RecentFetch { app_id: *app_id, outcome: error.to_string(), error_class: Some(get_error_class(error)), at: now });
+            }
+            FetchEvent::Retried { app_id } => {
+                self.backing_off.remove(app_id);
+            }
+            FetchEvent::Finished { app_id } => {
+                // app finished right after a failure was given up on
+                if self.backing_off.remove(app_id).is_some() {
+                    self.failed += 1;
+                }
+            }
+            FetchEvent::Idle => {}
+        }
+        while self.requests.front().map(|at| *at + 60 <= now).unwrap_or(false) {
+            self.requests.pop_front();
+        }
+    }
+
+    pub fn set_backup_status(&mut self, status: &str) {
+        self.backup_status = Some(status.to_string());
+    }
+
+    pub fn render(&self, estimate: &Estimate, now: u64, width: usize, is_paused: bool) -> Vec<String> {
+        let filled = ((estimate.percentage / 100.0 * PROGRESS_BAR_WIDTH as f64) as usize).min(PROGRESS_BAR_WIDTH);
+
+        let state = if is_paused { "PAUSED" } else { "running" };
+        let eta = estimate.eta_seconds.map(format_duration).unwrap_or_else(|| "unknown".to_string());
+        let budget = if self.requests_per_minute == 0 {
+            format!("unlimited, {} requests in the last minute", self.requests.len())
+        } else {
+            format!("{} of {} requests left this minute", (self.requests_per_minute as usize).saturating_sub(self.requests.len()), self.requests_per_minute)
+        };
+        let backoff = if self.backing_off.is_empty() {
+            "none".to_string()
+        } else {
+            let next_retry = self.backing_off.values().min().unwrap() + self.retry_delay_seconds;
+            format!("{} apps waiting, next retry in {}", self.backing_off.len(), format_duration(next_retry.saturating_sub(now)))
+        };
+
+        let mut lines = vec![
+            format!("steam apps crawl, {}, running for {}", state, format_duration(now.saturating_sub(estimate.started_at))),
+            "".to_string(),
+            format!("[{}{}] {:.1}% of the app list", "#".repeat(filled), "-".repeat(PROGRESS_BAR_WIDTH - filled), estimate.percentage),
+            format!("{} of {} apps processed, this run {} of {} ({:.1}%)", estimate.processed_total, estimate.app_list_size, estimate.processed, estimate.apps_to_fetch, estimate.run_percentage),
+            format!("fetched {}, given up {}", self.fetched, self.failed),
+            format!("throughput {:.1} apps/min, eta {}", estimate.apps_per_minute, eta),
+            format!("backoff: {}, {} retries lost {}", backoff, estimate.retries, format_duration(estimate.backoff_seconds)),
+            format!("rate limit: {}", budget),
+            format!("backup: {}", self.backup_status.as_deref().unwrap_or("none this run")),
+            "".to_string(),
+            "recent fetches:".to_string(),
+        ];
+        for recent in self.recent.iter() {
+            let line = match recent.error_class {
+                None => format!("  ok      {:>8}  {}", recent.app_id, recent.outcome),
+                Some(error_class) => format!("  failed  {:>8}  [{}] {}", recent.app_id, error_class, recent.outcome),
+            };
+            lines.push(line);
+        }
+        lines.push("".to_string());
+        lines.push(format!("keys: p {}, b backup now, q stop", if is_paused { "resume" } else { "pause" }));
+
+        lines.into_iter().map(|line| truncate(line, width)).collect()
+    }
+
+    fn push_recent(&mut self, recent: RecentFetch) {
+        self.recent.push_front(recent);
+        self.recent.truncate(RECENT_FETCHES);
+    }
+}
+
+/// `1h 02m 03s`, hours and minutes are left out while zero.
+pub fn format_duration(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+    if hours > 0 {
+        return format!("{}h {:02}m {:02}s", hours, minutes, seconds);
+    }
+    if minutes > 0 {
+        return format!("{}m {:02}s", minutes, seconds);
+    }
+    format!("{}s", seconds)
+}
+
+fn truncate(line: String, width: usize) -> String {
+    if line.chars().count() <= width {
+        return line;
+    }
+    line.chars().take(width).collect()
+}
+
+/// Terminal in raw mode on the alternate screen, restored when dropped.
+pub struct Terminal {
+    original: libc::termios,
+}
+
+impl Terminal {
+    pub fn enter() -> Result<Terminal, String> {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err("dashboard needs a terminal on standard input".to_string());
+        }
+        // keys are read one at a time, Ctrl-C is a key so the terminal is restored on stop
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(format!("unable to switch the terminal to raw mode: {}", std::io::Error::last_os_error()));
+        }
+        print!("\x1b[?1049h\x1b[?25l");
+        let _ = std::io::stdout().flush();
+        Ok(Terminal { original })
+    }
+
+    pub fn get_width(&self) -> usize {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+        if result != 0 || size.ws_col == 0 {
+            return 80;
+        }
+        size.ws_col as usize
+    }
+
+    pub fn draw(&self, lines: &[String]) {
+        let mut screen = "\x1b[H\x1b[2J".to_string();
+        screen.push_str(lines.join("\r\n").as_str());
+        print!("{}", screen);
+        let _ = std::io::stdout().flush();
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Dashboard of a crawl running in this process.
+pub struct Dashboard {
+    state: DashboardState,
+    control: Arc<DashboardControl>,
+    terminal: Terminal,
+    last_drawn_at: Option<Instant>,
+}
+
+impl Dashboard {
+    /// Takes over the terminal and reads keys on a background thread.
+    pub fn open(state: DashboardState) -> Result<Dashboard, String> {
+        let boxed_terminal = Terminal::enter();
+        if boxed_terminal.is_err() {
+            return Err(boxed_terminal.err().unwrap());
+        }
+        let control = Arc::new(DashboardControl::default());
+        let key_control = control.clone();
+        thread::spawn(move || {
+            let mut key = [0u8; 1];
+            while std::io::stdin().read(&mut key).map(|read| read == 1).unwrap_or(false) {
+                key_control.handle_key(key[0]);
+            }
+        });
+        Ok(Dashboard { state, control, terminal: boxed_terminal.unwrap(), last_drawn_at: None })
+    }
+
+    pub fn get_control(&self) -> Arc<DashboardControl> {
+        self.control.clone()
+    }
+
+    pub fn get_state(&mut self) -> &mut DashboardState {
+        &mut self.state
+    }
+
+    /// Records the event and redraws, idle events always redraw to keep the clock going.
+    pub fn handle_event(&mut self, event: &FetchEvent, estimate: &Estimate, now: u64) {
+        self.state.record_event(event, now);
+        let is_due = self.last_drawn_at.map(|drawn_at| drawn_at.elapsed() >= REDRAW_INTERVAL).unwrap_or(true);
+        if is_due || *event == FetchEvent::Idle {
+            self.draw(estimate, now);
+        }
+    }
+
+    pub fn draw(&mut self, estimate: &Estimate, now: u64) {
+        let lines = self.state.render(estimate, now, self.terminal.get_width(), self.control.is_paused());
+        self.terminal.draw(&lines);
+        self.last_drawn_at = Some(Instant::now());
+    }
+}
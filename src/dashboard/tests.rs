@@ -0,0 +1,60 @@
+use crate::crawler::FetchEvent;
+use crate::dashboard::{format_duration, DashboardControl, DashboardState};
+use crate::estimator::ProgressEstimator;
+
+#[test]
+fn dashboard_state() {
+    let mut estimator = ProgressEstimator::new(1000, 100, 60, 1_000);
+    let mut state = DashboardState::new(30, 60);
+    let mut record = |event: FetchEvent, now: u64, state: &mut DashboardState| {
+        estimator.record_event(&event, now);
+        state.record_event(&event, now);
+        estimator.get_estimate(now)
+    };
+
+    record(FetchEvent::Fetched { app_id: 10, name: "Counter-Strike".to_string() }, 1_010, &mut state);
+    record(FetchEvent::Finished { app_id: 10 }, 1_010, &mut state);
+    let estimate = record(FetchEvent::Failed { app_id: 20, error: "Operation timed out (API call)".to_string() }, 1_020, &mut state);
+    let lines = state.render(&estimate, 1_030, 200, false);
+    assert!(lines.contains(&"backoff: 1 apps waiting, next retry in 50s, 0 retries lost 0s".to_string()));
+    assert!(lines.contains(&"rate limit: 28 of 30 requests left this minute".to_string()));
+    assert!(lines.contains(&"  failed        20  [timeout] Operation timed out (API call)".to_string()));
+
+    record(FetchEvent::Finished { app_id: 20 }, 1_060, &mut state);
+    let estimate = record(FetchEvent::Idle, 1_120, &mut state);
+
+    let lines = state.render(&estimate, 1_120, 200, true);
+    assert_eq!("steam apps crawl, PAUSED, running for 2m 00s", lines[0]);
+    assert_eq!(format!("[{}{}] 90.2% of the app list", "#".repeat(36), "-".repeat(4)), lines[2]);
+    assert_eq!("902 of 1000 apps processed, this run 2 of 100 (2.0%)", lines[3]);
+    assert_eq!("fetched 1, given up 1", lines[4]);
+    assert_eq!("throughput 1.0 apps/min, eta 1h 38m 00s", lines[5]);
+    assert_eq!("backoff: none, 0 retries lost 0s", lines[6]);
+    assert_eq!("rate limit: 30 of 30 requests left this minute", lines[7]);
+    assert_eq!("  ok            10  Counter-Strike", lines[12]);
+    assert_eq!("keys: p resume, b backup now, q stop", lines.last().unwrap());
+    assert!(state.render(&estimate, 1_120, 20, false).iter().all(|line| line.chars().count() <= 20));
+
+    assert_eq!("59s", format_duration(59));
+    assert_eq!("1h 00m 01s", format_duration(3601));
+}
+
+#[test]
+fn dashboard_keys() {
+    let control = DashboardControl::default();
+    control.handle_key(b'p');
+    assert!(control.is_paused());
+    control.handle_key(b' ');
+    assert!(!control.is_paused());
+
+    control.handle_key(b'b');
+    assert!(control.take_backup_request());
+    assert!(!control.take_backup_request());
+
+    // stopping lets paused workers go
+    control.handle_key(b'p');
+    control.handle_key(3);
+    assert!(control.is_stop_requested());
+    assert!(!control.is_paused());
+    control.wait_while_paused();
+}
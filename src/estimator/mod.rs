@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::crawler::FetchEvent;
+
+#[cfg(test)]
+mod tests;
+
+/// Apps processed within the window make the fetch rate, older ones are forgotten.
+pub const RATE_WINDOW_SECONDS: u64 = 600;
+/// Latest estimate of a running crawl, written to the cache directory for `status`.
+pub const STATUS_FILENAME: &str = "crawl_status.json";
+
+/// Progress of the crawl. Overall numbers count against the whole app list, run
+/// numbers against the apps this run set out to fetch.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Estimate {
+    pub app_list_size: usize,
+    pub processed_total: usize,
+    pub percentage: f64,
+    pub apps_to_fetch: usize,
+    pub processed: usize,
+    pub run_percentage: f64,
+    /// Moving average over the last `RATE_WINDOW_SECONDS`.
+    pub apps_per_minute: f64,
+    /// Unknown until the first app is processed.
+    pub eta_seconds: Option<u64>,
+    pub retries: u64,
+    /// Worker time spent waiting for retries.
+    pub backoff_seconds: u64,
+    pub started_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProgressEstimator {
+    app_list_size: usize,
+    already_processed: usize,
+    apps_to_fetch: usize,
+    processed: usize,
+    retry_delay_seconds: u64,
+    retries: u64,
+    started_at: u64,
+    processed_at: VecDeque<u64>,
+}
+
+impl ProgressEstimator {
+    pub fn new(app_list_size: usize, apps_to_fetch: usize, retry_delay_seconds: u64, now: u64) -> ProgressEstimator {
+        ProgressEstimator {
+            app_list_size,
+            already_processed: app_list_size.saturating_sub(apps_to_fetch),
+            apps_to_fetch,
+            processed: 0,
+            retry_delay_seconds,
+            retries: 0,
+            started_at: now,
+            processed_at: VecDeque::new(),
+        }
+    }
+
+    /// Apps left to fetch changed, a new shard was claimed. The rate is kept.
+    pub fn set_remaining(&mut self, remaining: usize) {
+        self.apps_to_fetch = self.processed + remaining;
+    }
+
+    pub fn record_event(&mut self, event: &FetchEvent, now: u64) {
+        match event {
+            FetchEvent::Finished { .. } => {
+                self.processed += 1;
+                self.processed_at.push_back(now);
+            }
+            FetchEvent::Retried { .. } => self.retries += 1,
+            _ => {}
+        }
+        while self.processed_at.front().map(|at| *at + RATE_WINDOW_SECONDS <= now).unwrap_or(false) {
+            self.processed_at.pop_front();
+        }
+    }
+
+    /// Apps processed in the window per minute, a crawl younger than the window
+    /// is averaged over its age.
+    pub fn get_apps_per_minute(&self, now: u64) -> f64 {
+        let recent = self.processed_at.iter().filter(|at| **at + RATE_WINDOW_SECONDS > now).count();
+        let span = now.saturating_sub(self.started_at).clamp(1, RATE_WINDOW_SECONDS);
+        recent as f64 * 60.0 / span as f64
+    }
+
+    pub fn get_estimate(&self, now: u64) -> Estimate {
+        let processed_total = self.already_processed + self.processed;
+        let apps_per_minute = self.get_apps_per_minute(now);
+        let remaining = self.apps_to_fetch.saturating_sub(self.processed);
+        let eta_seconds = if remaining == 0 {
+            Some(0)
+        } else if apps_per_minute > 0.0 {
+            Some((remaining as f64 * 60.0 / apps_per_minute).round() as u64)
+        } else {
+            None
+        };
+
+        Estimate {
+            app_list_size: self.app_list_size,
+            processed_total,
+            percentage: get_percentage(processed_total, self.app_list_size),
+            apps_to_fetch: self.apps_to_fetch,
+            processed: self.processed,
+            run_percentage: get_percentage(self.processed, self.apps_to_fetch),
+            apps_per_minute,
+            eta_seconds,
+            retries: self.retries,
+            backoff_seconds: self.retries * self.retry_delay_seconds,
+            started_at: self.started_at,
+            updated_at: now,
+        }
+    }
+}
+
+fn get_percentage(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        return 100.0;
+    }
+    (part as f64 * 1000.0 / whole as f64).round() / 10.0
+}
+
+/// Replaces the status file at once, `status` never reads a partial one.
+pub fn write_status(path: &Path, estimate: &Estimate) -> Result<(), String> {
+    let serialized = serde_json::to_string_pretty(estimate).unwrap_or_default();
+    let mut temporary_path = path.to_path_buf().into_os_string();
+    temporary_path.push(".tmp");
+    let boxed_write = fs::write(&temporary_path, serialized).and_then(|_| fs::rename(&temporary_path, path));
+    if boxed_write.is_err() {
+        return Err(format!("unable to write crawl status {}: {}", path.display(), boxed_write.err().unwrap()));
+    }
+    Ok(())
+}
+
+pub fn read_status(path: &Path) -> Result<Estimate, String> {
+    let boxed_content = fs::read_to_string(path);
+    if boxed_content.is_err() {
+        return Err(format!("unable to read crawl status {}: {}", path.display(), boxed_content.err().unwrap()));
+    }
+    let boxed_estimate = serde_json::from_str(boxed_content.unwrap().as_str());
+    if boxed_estimate.is_err() {
+        return Err(format!("unable to parse crawl status {}: {}", path.display(), boxed_estimate.err().unwrap()));
+    }
+    Ok(boxed_estimate.unwrap())
+}
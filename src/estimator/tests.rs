@@ -0,0 +1,47 @@
+use std::fs;
+use std::path::Path;
+use crate::crawler::FetchEvent;
+use crate::estimator::{read_status, write_status, ProgressEstimator, RATE_WINDOW_SECONDS};
+
+#[test]
+fn moving_average() {
+    let mut estimator = ProgressEstimator::new(1000, 100, 60, 0);
+    let estimate = estimator.get_estimate(0);
+    assert_eq!((900, 90.0, 0.0, None), (estimate.processed_total, estimate.percentage, estimate.run_percentage, estimate.eta_seconds));
+
+    // 10 apps a minute for the first 10 minutes
+    for minute in 0..10 {
+        for app in 0..10 {
+            estimator.record_event(&FetchEvent::Finished { app_id: minute * 10 + app }, minute as u64 * 60 + app as u64 * 6 + 6);
+        }
+    }
+    let estimate = estimator.get_estimate(RATE_WINDOW_SECONDS);
+    assert_eq!(10.0, estimate.apps_per_minute);
+    assert_eq!(Some(0), estimate.eta_seconds);
+    assert_eq!((1000, 100.0, 100.0), (estimate.processed_total, estimate.percentage, estimate.run_percentage));
+
+    // slowdown shows once the fast minutes leave the window
+    estimator.set_remaining(20);
+    estimator.record_event(&FetchEvent::Retried { app_id: 200 }, 900);
+    estimator.record_event(&FetchEvent::Finished { app_id: 200 }, 900);
+    let estimate = estimator.get_estimate(1_200);
+    assert_eq!(0.1, estimate.apps_per_minute);
+    assert_eq!(Some(19 * 600), estimate.eta_seconds);
+    assert_eq!((120, 101, 84.2), (estimate.apps_to_fetch, estimate.processed, estimate.run_percentage));
+    assert_eq!((1, 60), (estimate.retries, estimate.backoff_seconds));
+    assert!(estimator.get_estimate(1_500).eta_seconds.is_none());
+}
+
+#[test]
+fn status_file() {
+    // target directory is ignored by git, status directory is recreated on every run
+    let directory = "target/estimator_tests";
+    let _ = fs::remove_dir_all(directory);
+    fs::create_dir_all(directory).unwrap();
+    let path = Path::new(directory).join("crawl_status.json");
+
+    let estimate = ProgressEstimator::new(10, 5, 60, 100).get_estimate(160);
+    write_status(path.as_path(), &estimate).unwrap();
+    assert_eq!(estimate, read_status(path.as_path()).unwrap());
+    assert!(read_status(Path::new(directory).join("missing.json").as_path()).is_err());
+}
@@ -0,0 +1,188 @@
+use std::fs;
+use std::fs::read;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use sha256::digest;
+use crate::crypto_ext::error::CryptoError;
+use crate::crypto_ext::keyring::{get_unix_timestamp, Keyring};
+
+#[cfg(test)]
+mod tests;
+
+pub const MANIFEST_FILENAME: &str = "integrity_manifest.json";
+pub const MANIFEST_SIGNATURE_FILENAME: &str = "integrity_manifest.json.sig";
+
+/// Suffix of the files written before they replace the manifest and its signature.
+pub const PENDING_SUFFIX: &str = ".tmp";
+
+pub const MANIFEST_VERSION: u8 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub filename: String,
+    pub sha256: String,
+}
+
+/// SHA-256 of the cache artifacts, signed with the keyring of the operator.
+/// Manifest is stored next to the artifacts, signature in a separate file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct IntegrityManifest {
+    pub version: u8,
+    pub created_at: u64,
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IntegrityStatus {
+    /// There is no manifest yet, cache written before the manifests were introduced.
+    Missing,
+    Verified,
+    /// Manifest is signed by the operator, but listed files differ from it.
+    /// Cache can be restored from backup.
+    Corrupted(Vec<String>),
+    /// Manifest or its signature was changed without the operator key.
+    Tampered(String),
+}
+
+/// Hashes given files in the directory and writes the signed manifest.
+/// Files which do not exist yet are left out of the manifest.
+pub fn write_manifest(directory: &str, filenames: &[&str], keyring: &Keyring) -> Result<IntegrityManifest, String> {
+    let mut files: Vec<ManifestEntry> = vec![];
+    for filename in filenames {
+        let path = get_filepath(directory, filename);
+        if !Path::new(path.as_str()).is_file() {
+            continue;
+        }
+
+        let boxed_content = read(&path);
+        if boxed_content.is_err() {
+            let message = format!("unable to read {}: {}", path, boxed_content.err().unwrap());
+            return Err(message)
+        }
+
+        files.push(ManifestEntry {
+            filename: filename.to_string(),
+            sha256: digest(boxed_content.unwrap().as_slice()),
+        });
+    }
+
+    let manifest = IntegrityManifest {
+        version: MANIFEST_VERSION,
+        created_at: get_unix_timestamp(),
+        files,
+    };
+    let boxed_serialized = serde_json::to_string_pretty(&manifest);
+    if boxed_serialized.is_err() {
+        let message = format!("unable to serialize integrity manifest: {}", boxed_serialized.err().unwrap());
+        return Err(message)
+    }
+    let serialized = boxed_serialized.unwrap();
+
+    let boxed_signature = keyring.sign(serialized.as_bytes());
+    if boxed_signature.is_err() {
+        let message = format!("unable to sign integrity manifest: {}", boxed_signature.err().unwrap());
+        return Err(message)
+    }
+
+    // both are written in full before either is replaced, signature is replaced last,
+    // manifest left with the previous signature is completed from the pending one
+    let manifest_path = get_filepath(directory, MANIFEST_FILENAME);
+    let signature_path = get_filepath(directory, MANIFEST_SIGNATURE_FILENAME);
+    let pending_manifest_path = [manifest_path.as_str(), PENDING_SUFFIX].join("");
+    let pending_signature_path = [signature_path.as_str(), PENDING_SUFFIX].join("");
+    let boxed_write = fs::write(&pending_manifest_path, serialized)
+        .and_then(|_| fs::write(&pending_signature_path, boxed_signature.unwrap()));
+    if boxed_write.is_err() {
+        let message = format!("unable to write integrity manifest: {}", boxed_write.err().unwrap());
+        return Err(message)
+    }
+
+    let boxed_rename = fs::rename(&pending_manifest_path, &manifest_path)
+        .and_then(|_| fs::rename(&pending_signature_path, &signature_path));
+    if boxed_rename.is_err() {
+        let message = format!("unable to replace integrity manifest: {}", boxed_rename.err().unwrap());
+        return Err(message)
+    }
+
+    Ok(manifest)
+}
+
+/// Checks the manifest signature first and then the files listed in the manifest.
+/// Error is returned only if the keyring itself is not usable.
+pub fn verify_manifest(directory: &str, keyring: &Keyring) -> Result<IntegrityStatus, String> {
+    let manifest_path = get_filepath(directory, MANIFEST_FILENAME);
+    let signature_path = get_filepath(directory, MANIFEST_SIGNATURE_FILENAME);
+    let is_manifest_present = Path::new(manifest_path.as_str()).is_file();
+    let is_signature_present = Path::new(signature_path.as_str()).is_file();
+    if !is_manifest_present && !is_signature_present {
+        return Ok(IntegrityStatus::Missing);
+    }
+    if !is_manifest_present {
+        return Ok(IntegrityStatus::Tampered("integrity manifest is removed".to_string()));
+    }
+    if !is_signature_present {
+        return Ok(IntegrityStatus::Tampered("integrity manifest signature is removed".to_string()));
+    }
+
+    let boxed_manifest = read(&manifest_path);
+    let boxed_signature = fs::read_to_string(&signature_path);
+    if boxed_manifest.is_err() || boxed_signature.is_err() {
+        let message = format!("unable to read integrity manifest from {}", directory);
+        return Err(message)
+    }
+    let serialized = boxed_manifest.unwrap();
+
+    let mut boxed_verify = keyring.verify(serialized.as_slice(), boxed_signature.unwrap().trim());
+    // write interrupted between replacing the manifest and its signature
+    let pending_signature_path = [signature_path.as_str(), PENDING_SUFFIX].join("");
+    if boxed_verify == Ok(false) && Path::new(pending_signature_path.as_str()).is_file() {
+        let boxed_pending_signature = fs::read_to_string(&pending_signature_path);
+        let is_pending_valid = boxed_pending_signature.is_ok()
+            && keyring.verify(serialized.as_slice(), boxed_pending_signature.unwrap().trim()) == Ok(true);
+        if is_pending_valid {
+            let boxed_rename = fs::rename(&pending_signature_path, &signature_path);
+            if boxed_rename.is_err() {
+                let message = format!("unable to complete integrity manifest signature: {}", boxed_rename.err().unwrap());
+                return Err(message)
+            }
+            boxed_verify = Ok(true);
+        }
+    }
+    if boxed_verify.is_err() {
+        let error = boxed_verify.err().unwrap();
+        return match error {
+            CryptoError::UnknownKey(_) | CryptoError::Unsupported(_) => {
+                let reason = format!("integrity manifest is signed with a foreign key: {}", error);
+                Ok(IntegrityStatus::Tampered(reason))
+            }
+            _ => Err(format!("unable to verify integrity manifest: {}", error)),
+        }
+    }
+    if !boxed_verify.unwrap() {
+        return Ok(IntegrityStatus::Tampered("integrity manifest signature does not match".to_string()));
+    }
+
+    let boxed_parsed: Result<IntegrityManifest, _> = serde_json::from_slice(serialized.as_slice());
+    if boxed_parsed.is_err() {
+        let reason = format!("signed integrity manifest is not readable: {}", boxed_parsed.err().unwrap());
+        return Ok(IntegrityStatus::Tampered(reason));
+    }
+    let manifest = boxed_parsed.unwrap();
+
+    let mut corrupted_files: Vec<String> = vec![];
+    for entry in manifest.files {
+        let boxed_content = read(get_filepath(directory, entry.filename.as_str()));
+        if boxed_content.is_err() || digest(boxed_content.unwrap().as_slice()) != entry.sha256 {
+            corrupted_files.push(entry.filename);
+        }
+    }
+    if !corrupted_files.is_empty() {
+        return Ok(IntegrityStatus::Corrupted(corrupted_files));
+    }
+
+    Ok(IntegrityStatus::Verified)
+}
+
+fn get_filepath(directory: &str, filename: &str) -> String {
+    [directory, "/", filename].join("")
+}
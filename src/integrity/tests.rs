@@ -0,0 +1,94 @@
+use std::fs;
+use std::path::Path;
+use crate::crypto_ext::KeyOptions;
+use crate::crypto_ext::algorithm::KeyAlgorithm;
+use crate::crypto_ext::keyring::Keyring;
+use crate::crypto_ext::key_store::KeyStore;
+use crate::integrity::{IntegrityStatus, MANIFEST_FILENAME, MANIFEST_SIGNATURE_FILENAME, PENDING_SUFFIX, verify_manifest, write_manifest};
+
+const TEST_SECRET: &str = "integrity test secret";
+
+fn setup(name: &str) -> (String, Keyring) {
+    // target directory is ignored by git, cache and keyring are recreated on every run
+    let key_store = KeyStore::new(Path::new("target/integrity_tests").join(name)).unwrap();
+    let _ = fs::remove_dir_all(key_store.get_directory());
+    let keyring = Keyring::open_with_options(&key_store, TEST_SECRET, &KeyOptions::new(KeyAlgorithm::Ed25519)).unwrap();
+    let directory = [key_store.get_directory().to_str().unwrap(), "/"].join("");
+
+    fs::write([directory.as_str(), "processed_app_id_list.json"].join(""), "[10,20]").unwrap();
+    fs::write([directory.as_str(), "ISteamApps-GetAppList-v2.json"].join(""), "{}").unwrap();
+
+    (directory.trim_end_matches('/').to_string(), keyring)
+}
+
+#[test]
+fn verified() {
+    let (directory, keyring) = setup("verified");
+    assert_eq!(IntegrityStatus::Missing, verify_manifest(directory.as_str(), &keyring).unwrap());
+
+    let manifest = write_manifest(directory.as_str(), &["processed_app_id_list.json", "ISteamApps-GetAppList-v2.json", "absent.json"], &keyring).unwrap();
+    assert_eq!(2, manifest.files.len());
+    assert_eq!(IntegrityStatus::Verified, verify_manifest(directory.as_str(), &keyring).unwrap());
+}
+
+#[test]
+fn corrupted() {
+    let (directory, keyring) = setup("corrupted");
+    write_manifest(directory.as_str(), &["processed_app_id_list.json", "ISteamApps-GetAppList-v2.json"], &keyring).unwrap();
+
+    fs::write([directory.as_str(), "/processed_app_id_list.json"].join(""), "[10,2").unwrap();
+    fs::remove_file([directory.as_str(), "/ISteamApps-GetAppList-v2.json"].join("")).unwrap();
+
+    let expected = vec!["processed_app_id_list.json".to_string(), "ISteamApps-GetAppList-v2.json".to_string()];
+    assert_eq!(IntegrityStatus::Corrupted(expected), verify_manifest(directory.as_str(), &keyring).unwrap());
+}
+
+#[test]
+fn tampered() {
+    let (directory, keyring) = setup("tampered");
+    write_manifest(directory.as_str(), &["processed_app_id_list.json"], &keyring).unwrap();
+
+    // rewriting the list together with its hash is detected by the signature
+    let manifest_path = [directory.as_str(), "/", MANIFEST_FILENAME].join("");
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    let tampered_manifest = manifest.replace(sha256::digest("[10,20]").as_str(), sha256::digest("[10,20,30]").as_str());
+    assert_ne!(manifest, tampered_manifest);
+    fs::write([directory.as_str(), "/processed_app_id_list.json"].join(""), "[10,20,30]").unwrap();
+    fs::write(&manifest_path, tampered_manifest).unwrap();
+    assert!(matches!(verify_manifest(directory.as_str(), &keyring).unwrap(), IntegrityStatus::Tampered(_)));
+
+    // manifest signed by someone else
+    let (foreign_directory, foreign_keyring) = setup("tampered_foreign");
+    write_manifest(foreign_directory.as_str(), &["processed_app_id_list.json"], &foreign_keyring).unwrap();
+    fs::copy([foreign_directory.as_str(), "/", MANIFEST_FILENAME].join(""), &manifest_path).unwrap();
+    fs::copy([foreign_directory.as_str(), "/", MANIFEST_SIGNATURE_FILENAME].join(""), [directory.as_str(), "/", MANIFEST_SIGNATURE_FILENAME].join("")).unwrap();
+    assert!(matches!(verify_manifest(directory.as_str(), &keyring).unwrap(), IntegrityStatus::Tampered(_)));
+
+    fs::remove_file([directory.as_str(), "/", MANIFEST_SIGNATURE_FILENAME].join("")).unwrap();
+    assert!(matches!(verify_manifest(directory.as_str(), &keyring).unwrap(), IntegrityStatus::Tampered(_)));
+}
+
+#[test]
+fn interrupted_write() {
+    let (directory, keyring) = setup("interrupted_write");
+    let signature_path = [directory.as_str(), "/", MANIFEST_SIGNATURE_FILENAME].join("");
+    let pending_signature_path = [signature_path.as_str(), PENDING_SUFFIX].join("");
+    write_manifest(directory.as_str(), &["processed_app_id_list.json"], &keyring).unwrap();
+    let previous_signature = fs::read_to_string(&signature_path).unwrap();
+
+    // killed after the manifest was replaced, before its signature was
+    fs::write([directory.as_str(), "/processed_app_id_list.json"].join(""), "[10,20,30]").unwrap();
+    write_manifest(directory.as_str(), &["processed_app_id_list.json"], &keyring).unwrap();
+    fs::rename(&signature_path, &pending_signature_path).unwrap();
+    fs::write(&signature_path, previous_signature.as_str()).unwrap();
+    assert_eq!(IntegrityStatus::Verified, verify_manifest(directory.as_str(), &keyring).unwrap());
+    assert!(!Path::new(pending_signature_path.as_str()).exists());
+
+    // pending signature not matching the manifest does not vouch for it
+    fs::write(&pending_signature_path, fs::read_to_string(&signature_path).unwrap()).unwrap();
+    fs::write(&signature_path, previous_signature.as_str()).unwrap();
+    let manifest_path = [directory.as_str(), "/", MANIFEST_FILENAME].join("");
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    fs::write(&manifest_path, manifest.replace("\"version\": 1", "\"version\":  1")).unwrap();
+    assert!(matches!(verify_manifest(directory.as_str(), &keyring).unwrap(), IntegrityStatus::Tampered(_)));
+}
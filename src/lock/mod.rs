@@ -0,0 +1,182 @@
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::process;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::crypto_ext::keyring::get_unix_timestamp;
+use crate::logging;
+
+#[cfg(test)]
+mod tests;
+
+pub const LOCK_FILENAME: &str = "crawler.lock";
+
+/// Content of the lock file, identifies the instance holding the lock.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub hostname: String,
+    pub started_at: u64,
+}
+
+impl LockHolder {
+    pub fn current() -> LockHolder {
+        LockHolder {
+            pid: process::id(),
+            hostname: get_hostname(),
+            started_at: get_unix_timestamp(),
+        }
+    }
+
+    /// Holder on another host can not be checked and is assumed to be alive.
+    pub fn is_alive(&self) -> bool {
+        self.hostname != get_hostname() || is_process_alive(self.pid)
+    }
+}
+
+/// Advisory lock on the cache directory, only one crawler writes to the cache at a time.
+/// The lock is an exclusive `flock` on the lock file, the kernel releases it when
+/// the holder exits, however it exits. Lock file names the holder while the lock is
+/// held and is emptied when the lock is dropped, a holder left in it by a crashed
+/// or killed instance is stale and is taken over.
+#[derive(Debug)]
+pub struct CacheLock {
+    file: File,
+    holder: LockHolder,
+}
+
+impl CacheLock {
+    pub fn acquire(directory: &str) -> Result<CacheLock, String> {
+        let boxed_create = fs::create_dir_all(directory);
+        if boxed_create.is_err() {
+            let message = format!("unable to create {}: {}", directory, boxed_create.err().unwrap());
+            return Err(message)
+        }
+
+        // lock file is never removed, an instance could lock the removed file while another locks its replacement
+        let path = Path::new(directory).join(LOCK_FILENAME);
+        let boxed_file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path);
+        if boxed_file.is_err() {
+            let message = format!("unable to create lock {}: {}", path.display(), boxed_file.err().unwrap());
+            return Err(message)
+        }
+        let mut file = boxed_file.unwrap();
+
+        let boxed_locked = try_lock(&file);
+        if boxed_locked.is_err() {
+            let message = format!("unable to lock {}: {}", path.display(), boxed_locked.err().unwrap());
+            return Err(message)
+        }
+
+        let boxed_holder = read_lock_holder(path.as_path());
+        if !boxed_locked.unwrap() {
+            if boxed_holder.is_err() {
+                return Err(format!("another instance is running, lock {}", path.display()));
+            }
+            let existing_holder = boxed_holder.unwrap();
+            let message = format!(
+                "another instance is running: PID {} on {} started at {}, lock {}",
+                existing_holder.pid, existing_holder.hostname, existing_holder.started_at, path.display());
+            return Err(message)
+        }
+
+        if let Ok(stale_holder) = boxed_holder {
+            logging::warn("taking over stale lock", &[
+                ("pid", json!(stale_holder.pid)), ("hostname", json!(stale_holder.hostname)), ("started_at", json!(stale_holder.started_at)),
+            ]);
+        }
+
+        let holder = LockHolder::current();
+        let content = serde_json::to_string(&holder).unwrap_or_default();
+        let boxed_write = file.set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| file.write_all(content.as_bytes()))
+            .and_then(|_| file.sync_all());
+        if boxed_write.is_err() {
+            let message = format!("unable to write lock {}: {}", path.display(), boxed_write.err().unwrap());
+            return Err(message)
+        }
+
+        Ok(CacheLock { file, holder })
+    }
+
+    pub fn get_holder(&self) -> &LockHolder {
+        &self.holder
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        // lock itself is released when the file is closed
+        let _ = self.file.set_len(0);
+    }
+}
+
+pub fn read_lock_holder(path: &Path) -> Result<LockHolder, String> {
+    let boxed_content = fs::read_to_string(path);
+    if boxed_content.is_err() {
+        let message = format!("unable to read lock {}: {}", path.display(), boxed_content.err().unwrap());
+        return Err(message)
+    }
+
+    let boxed_holder = serde_json::from_str(boxed_content.unwrap().as_str());
+    if boxed_holder.is_err() {
+        let message = format!("unable to parse lock {}: {}, remove it if no other instance is running", path.display(), boxed_holder.err().unwrap());
+        return Err(message)
+    }
+    Ok(boxed_holder.unwrap())
+}
+
+/// Returns false if another open lock file holds the lock.
+#[cfg(unix)]
+fn try_lock(file: &File) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let result = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if result == 0 {
+        return Ok(true);
+    }
+    let error = io::Error::last_os_error();
+    if error.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        return Ok(false);
+    }
+    Err(error)
+}
+
+/// Without advisory locks the lock file only names the instance that wrote it last.
+#[cfg(not(unix))]
+fn try_lock(_file: &File) -> io::Result<bool> {
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn is_process_alive(pid: u32) -> bool {
+    if pid == 0 || pid > i32::MAX as u32 {
+        return false;
+    }
+    // signal 0 only checks the process exists, EPERM means it belongs to another user
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn is_process_alive(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(unix)]
+pub fn get_hostname() -> String {
+    let mut buffer = [0u8; 256];
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+    if result != 0 {
+        return "localhost".to_string();
+    }
+    let length = buffer.iter().position(|byte| *byte == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..length]).to_string()
+}
+
+#[cfg(not(unix))]
+pub fn get_hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| "localhost".to_string())
+}
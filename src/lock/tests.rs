@@ -0,0 +1,84 @@
+use std::fs;
+use std::process::Command;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use crate::lock::{CacheLock, LOCK_FILENAME, LockHolder, read_lock_holder};
+
+fn setup(name: &str) -> String {
+    // target directory is ignored by git, lock directory is recreated on every run
+    let directory = ["target/lock_tests/", name].join("");
+    let _ = fs::remove_dir_all(&directory);
+    directory
+}
+
+fn write_lock(directory: &str, holder: &LockHolder) {
+    fs::create_dir_all(directory).unwrap();
+    fs::write([directory, "/", LOCK_FILENAME].join(""), serde_json::to_string(holder).unwrap()).unwrap();
+}
+
+#[test]
+fn single_instance() {
+    let directory = setup("single_instance");
+    let lock = CacheLock::acquire(directory.as_str()).unwrap();
+    let lock_path = [directory.as_str(), "/", LOCK_FILENAME].join("");
+    assert_eq!(lock.get_holder(), &read_lock_holder(lock_path.as_ref()).unwrap());
+    assert_eq!(std::process::id(), lock.get_holder().pid);
+
+    let error = CacheLock::acquire(directory.as_str()).err().unwrap();
+    assert!(error.starts_with(format!("another instance is running: PID {}", std::process::id()).as_str()));
+
+    drop(lock);
+    assert!(read_lock_holder(lock_path.as_ref()).is_err());
+    CacheLock::acquire(directory.as_str()).unwrap();
+}
+
+#[test]
+fn concurrent_instances() {
+    let directory = setup("concurrent_instances");
+    fs::create_dir_all(&directory).unwrap();
+
+    // instances starting together, the lock is held until all of them tried
+    let barrier = Arc::new(Barrier::new(8));
+    let handles: Vec<_> = (0..8).map(|_| {
+        let directory = directory.to_string();
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            barrier.wait();
+            let boxed_lock = CacheLock::acquire(directory.as_str());
+            barrier.wait();
+            boxed_lock.is_ok()
+        })
+    }).collect();
+    let acquired = handles.into_iter().map(|handle| handle.join().unwrap()).filter(|is_acquired| *is_acquired).count();
+    assert_eq!(1, acquired);
+}
+
+#[test]
+fn stale_lock() {
+    let directory = setup("stale_lock");
+    let mut child = Command::new("true").spawn().unwrap();
+    let exited_pid = child.id();
+    child.wait().unwrap();
+
+    // holder is gone, its lock is taken over
+    let stale_holder = LockHolder { pid: exited_pid, ..LockHolder::current() };
+    assert!(!stale_holder.is_alive());
+    write_lock(directory.as_str(), &stale_holder);
+    let lock = CacheLock::acquire(directory.as_str()).unwrap();
+    assert_eq!(std::process::id(), lock.get_holder().pid);
+    drop(lock);
+
+    // lock file decides nothing without the lock on it, whatever it names
+    let foreign_holder = LockHolder { pid: exited_pid, hostname: "crawler-2.example.com".to_string(), started_at: 0 };
+    write_lock(directory.as_str(), &foreign_holder);
+    let lock = CacheLock::acquire(directory.as_str()).unwrap();
+    drop(lock);
+
+    fs::write([directory.as_str(), "/", LOCK_FILENAME].join(""), "not a holder").unwrap();
+    let lock = CacheLock::acquire(directory.as_str()).unwrap();
+
+    // holder of the lock is named even if it can not be checked
+    fs::write([directory.as_str(), "/", LOCK_FILENAME].join(""), serde_json::to_string(&foreign_holder).unwrap()).unwrap();
+    assert!(CacheLock::acquire(directory.as_str()).err().unwrap().contains("on crawler-2.example.com"));
+    drop(lock);
+}
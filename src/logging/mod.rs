@@ -0,0 +1,281 @@
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[cfg(test)]
+mod tests;
+
+/// Named values attached to a log event, `app_id`, `attempt`, `error_class`, `duration_ms`...
+pub type Fields<'a> = &'a [(&'a str, Value)];
+
+/// Severity of an event, events less severe than the configured level are dropped.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(format!("unknown log level {}, use error, warn, info or debug", value)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `<timestamp> <LEVEL> <message> key=value...`
+    Text,
+    /// One JSON object per line with `timestamp`, `level`, `message` and the fields.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {}, use text or json", value)),
+        }
+    }
+}
+
+/// Log file rotated once it would grow past `max_bytes`: `<path>.1` is the newest
+/// rotated file, files past `<path>.<max_files>` are removed.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: u32,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    pub fn open(path: &str, max_bytes: u64, max_files: u32) -> Result<RotatingFile, String> {
+        let path = PathBuf::from(path);
+        if path.parent().is_some() && !path.parent().unwrap().as_os_str().is_empty() {
+            let boxed_create = fs::create_dir_all(path.parent().unwrap());
+            if boxed_create.is_err() {
+                return Err(format!("unable to create log directory: {}", boxed_create.err().unwrap()));
+            }
+        }
+        let boxed_file = OpenOptions::new().create(true).append(true).open(&path);
+        if boxed_file.is_err() {
+            return Err(format!("unable to open log file {}: {}", path.display(), boxed_file.err().unwrap()));
+        }
+        let file = boxed_file.unwrap();
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        Ok(RotatingFile { path, max_bytes, max_files, file, size })
+    }
+
+    pub fn write_line(&mut self, line: &str) -> Result<(), String> {
+        let length = line.len() as u64 + 1;
+        if self.size > 0 && self.size + length > self.max_bytes {
+            let boxed_rotate = self.rotate();
+            if boxed_rotate.is_err() {
+                return Err(boxed_rotate.err().unwrap());
+            }
+        }
+        let boxed_write = self.file.write_all([line, "\n"].join("").as_bytes());
+        if boxed_write.is_err() {
+            return Err(format!("unable to write log file {}: {}", self.path.display(), boxed_write.err().unwrap()));
+        }
+        self.size += length;
+        Ok(())
+    }
+
+    fn get_rotated_path(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> Result<(), String> {
+        if self.max_files == 0 {
+            let _ = fs::remove_file(&self.path);
+        } else {
+            let _ = fs::remove_file(self.get_rotated_path(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let _ = fs::rename(self.get_rotated_path(index), self.get_rotated_path(index + 1));
+            }
+            let boxed_rename = fs::rename(&self.path, self.get_rotated_path(1));
+            if boxed_rename.is_err() {
+                return Err(format!("unable to rotate log file {}: {}", self.path.display(), boxed_rename.err().unwrap()));
+            }
+        }
+
+        let boxed_file = OpenOptions::new().create(true).append(true).open(&self.path);
+        if boxed_file.is_err() {
+            return Err(format!("unable to open log file {}: {}", self.path.display(), boxed_file.err().unwrap()));
+        }
+        self.file = boxed_file.unwrap();
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Writes events to the log file, or to stdout and errors and warnings to stderr without one.
+#[derive(Debug)]
+pub struct Logger {
+    level: Level,
+    format: LogFormat,
+    file: Option<RotatingFile>,
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Logger::new(Level::Info, LogFormat::Text, None)
+    }
+}
+
+impl Logger {
+    pub fn new(level: Level, format: LogFormat, file: Option<RotatingFile>) -> Logger {
+        Logger { level, format, file }
+    }
+
+    pub fn is_enabled(&self, level: Level) -> bool {
+        level <= self.level
+    }
+
+    pub fn format_line(&self, level: Level, message: &str, fields: Fields, timestamp_millis: u64) -> String {
+        let timestamp = format_timestamp(timestamp_millis);
+        match self.format {
+            LogFormat::Json => {
+                let mut object = Map::new();
+                object.insert("timestamp".to_string(), Value::String(timestamp));
+                object.insert("level".to_string(), Value::String(level.to_string()));
+                object.insert("message".to_string(), Value::String(message.to_string()));
+                for (name, value) in fields {
+                    object.insert(name.to_string(), value.clone());
+                }
+                Value::Object(object).to_string()
+            }
+            LogFormat::Text => {
+                let mut line = format!("{} {:5} {}", timestamp, level.to_string().to_uppercase(), message);
+                for (name, value) in fields {
+                    line.push_str(format!(" {}={}", name, format_text_value(value)).as_str());
+                }
+                line
+            }
+        }
+    }
+
+    pub fn log(&mut self, level: Level, message: &str, fields: Fields) {
+        if !self.is_enabled(level) {
+            return;
+        }
+        let line = self.format_line(level, message, fields, get_unix_timestamp_millis());
+        if let Some(file) = self.file.as_mut() {
+            let boxed_write = file.write_line(line.as_str());
+            if boxed_write.is_ok() {
+                return;
+            }
+            eprintln!("{}", boxed_write.err().unwrap());
+        }
+        if level <= Level::Warn {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Logger of the process, text at info level to stdout until `init` is called.
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+
+pub fn init(logger: Logger) {
+    let mut global_logger = LOGGER.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    *global_logger = Some(logger);
+}
+
+pub fn log(level: Level, message: &str, fields: Fields) {
+    let mut global_logger = LOGGER.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    global_logger.get_or_insert_with(Logger::default).log(level, message, fields);
+}
+
+pub fn error(message: &str, fields: Fields) {
+    log(Level::Error, message, fields);
+}
+
+pub fn warn(message: &str, fields: Fields) {
+    log(Level::Warn, message, fields);
+}
+
+pub fn info(message: &str, fields: Fields) {
+    log(Level::Info, message, fields);
+}
+
+pub fn debug(message: &str, fields: Fields) {
+    log(Level::Debug, message, fields);
+}
+
+/// Plain strings stay unquoted, anything with spaces or quotes is written as a JSON string.
+fn format_text_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => {
+            let is_plain = !text.is_empty() && !text.chars().any(|character| character.is_whitespace() || character == '"' || character == '=');
+            if is_plain {
+                text.to_string()
+            } else {
+                value.to_string()
+            }
+        }
+        _ => value.to_string(),
+    }
+}
+
+fn get_unix_timestamp_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_millis() as u64).unwrap_or(0)
+}
+
+/// RFC 3339 UTC timestamp with milliseconds, `1970-01-01T00:00:00.000Z`.
+pub fn format_timestamp(timestamp_millis: u64) -> String {
+    let seconds = timestamp_millis / 1000;
+    let days = (seconds / 86400) as i64;
+    let seconds_of_day = seconds % 86400;
+
+    // days since the epoch to the proleptic Gregorian calendar date
+    let shifted_days = days + 719468;
+    let era = shifted_days / 146097;
+    let day_of_era = shifted_days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month, day, seconds_of_day / 3600, seconds_of_day % 3600 / 60, seconds_of_day % 60, timestamp_millis % 1000)
+}
@@ -0,0 +1,58 @@
+use std::fs;
+use serde_json::{json, Value};
+use crate::logging::{format_timestamp, Level, LogFormat, Logger, RotatingFile};
+
+#[test]
+fn log_lines() {
+    assert_eq!("1970-01-01T00:00:00.000Z", format_timestamp(0));
+    assert_eq!("2022-08-08T23:06:40.250Z", format_timestamp(1_660_000_000_250));
+    assert_eq!("2024-02-29T12:00:00.000Z", format_timestamp(1_709_208_000_000));
+
+    let fields = [("app_id", json!(440)), ("attempt", json!(2)), ("error", json!("no response from API")), ("error_class", json!("no_response"))];
+    let logger = Logger::new(Level::Info, LogFormat::Json, None);
+    let line = logger.format_line(Level::Warn, "app details fetch failed", &fields, 0);
+    let event: Value = serde_json::from_str(line.as_str()).unwrap();
+    assert_eq!(json!({
+        "timestamp": "1970-01-01T00:00:00.000Z",
+        "level": "warn",
+        "message": "app details fetch failed",
+        "app_id": 440,
+        "attempt": 2,
+        "error": "no response from API",
+        "error_class": "no_response",
+    }), event);
+
+    let logger = Logger::new(Level::Info, LogFormat::Text, None);
+    let line = logger.format_line(Level::Info, "backup done", &[("path", json!("/var/backups/cache.tar")), ("duration_ms", json!(12))], 0);
+    assert_eq!("1970-01-01T00:00:00.000Z INFO  backup done path=/var/backups/cache.tar duration_ms=12", line);
+    let line = logger.format_line(Level::Error, "fetch failed", &[("error", json!("no response"))], 0);
+    assert_eq!("1970-01-01T00:00:00.000Z ERROR fetch failed error=\"no response\"", line);
+
+    assert!(logger.is_enabled(Level::Warn));
+    assert!(!logger.is_enabled(Level::Debug));
+    assert_eq!(Ok(Level::Debug), "DEBUG".parse::<Level>());
+    assert!("verbose".parse::<Level>().is_err());
+}
+
+#[test]
+fn log_rotation() {
+    // target directory is ignored by git, log directory is recreated on every run
+    let directory = "target/logging_tests";
+    let _ = fs::remove_dir_all(directory);
+    let path = [directory, "/crawler.log"].join("");
+
+    let mut file = RotatingFile::open(path.as_str(), 20, 2).unwrap();
+    for line in ["first line", "second line", "third line", "fourth line"] {
+        file.write_line(line).unwrap();
+    }
+    assert_eq!("fourth line\n", fs::read_to_string(&path).unwrap());
+    assert_eq!("third line\n", fs::read_to_string([path.as_str(), ".1"].join("")).unwrap());
+    assert_eq!("second line\n", fs::read_to_string([path.as_str(), ".2"].join("")).unwrap());
+    assert!(fs::metadata([path.as_str(), ".3"].join("")).is_err());
+
+    // size of a reopened file counts towards the limit
+    let mut file = RotatingFile::open(path.as_str(), 20, 2).unwrap();
+    file.write_line("fifth line").unwrap();
+    assert_eq!("fourth line\n", fs::read_to_string([path.as_str(), ".1"].join("")).unwrap());
+    assert_eq!("fifth line\n", fs::read_to_string(&path).unwrap());
+}
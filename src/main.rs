@@ -1,47 +1,258 @@
 extern crate core;
 
 mod crypto_ext;
+mod audit;
+mod integrity;
+mod backup;
+mod records;
+mod config;
+mod crawler;
+mod lock;
+mod shard;
+mod coordinator;
+mod metrics;
+mod logging;
+mod dashboard;
+mod estimator;
+mod session;
+mod catalog;
+mod search;
 
 use std::fs::{File, OpenOptions, read_to_string};
-use std::path::Path;
-use std::{fs, thread, time};
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
 use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use sha256::digest;
+use serde_json::json;
+use crypto_ext::get_secret_from_environment;
+use audit::{AUDIT_LOG_FILENAME, AuditEvent, AuditLog, DEFAULT_SEAL_INTERVAL};
+use crypto_ext::KeyOptions;
+use crypto_ext::algorithm::{KeyAlgorithm, KeyUsage};
+use crypto_ext::key_export::{export_public_key, get_fingerprint, PublicKeyFormat};
+use crypto_ext::keyring::Keyring;
+use crypto_ext::key_provider::{EnvironmentKeyProvider, PRIVATE_KEY_ENVIRONMENT_VARIABLE};
+use crypto_ext::key_store::KeyStore;
+use crypto_ext::secret::SecretString;
+use backup::BACKUP_FILENAME;
+use integrity::{IntegrityStatus, MANIFEST_FILENAME, MANIFEST_SIGNATURE_FILENAME};
+use config::Config;
+use crawler::FetchEvent;
+use lock::CacheLock;
+use shard::WorkDirectory;
+use coordinator::{Coordinator, FailureReport, HeartbeatRequest, LeaseRequest, SuccessReport};
+use coordinator::http::CoordinatorClient;
+use crypto_ext::keyring::get_unix_timestamp;
+use metrics::Metrics;
+use logging::{Logger, RotatingFile};
+use dashboard::{format_duration, Dashboard, DashboardState};
+use estimator::{ProgressEstimator, STATUS_FILENAME};
+use session::{SessionLog, SessionOutcome, SessionReport, SESSIONS_DIRECTORY};
+use catalog::{Catalog, Health};
+use search::{IndexEntry, IndexFile, INDEX_FILENAME};
 
 // How to use: 1. First step is to import crate functions.
 use steam_webapi_rust_sdk::{get_app_list, get_app_details, get_cached_app_list};
 use steam_webapi_rust_sdk::isteam_apps::get_app_list::SteamApp;
 use steam_webapi_rust_sdk::util::get_cache_dir_path;
+use steam_webapi_rust_sdk::store_steampowered_com::appdetails::get_resource_filepath;
+
+const PROCESSED_APP_ID_LIST_FILENAME: &str = "processed_app_id_list.json";
+const APP_LIST_FILENAME: &str = "ISteamApps-GetAppList-v2.json";
+const CHECKSUM_SUFFIX: &str = ".sha256";
+const PENDING_SUFFIX: &str = ".tmp";
+const DASHBOARD_LOG_FILENAME: &str = "dashboard.log";
+/// Minimum time between signatures of the integrity manifest during a crawl.
+const CACHE_SIGNING_INTERVAL_SECONDS: u64 = 60;
 
 fn main() {
-    println!("retrieve-all-steam-apps-details-demo-app");
+    let args: Vec<String> = env::args().collect();
+    let boxed_command = args.get(1);
+    if boxed_command.is_some() && boxed_command.unwrap() == "config" {
+        do_config(args.get(2));
+        return;
+    }
+
+    let config = load_config();
+    if boxed_command.is_none() {
+        println!("retrieve-all-steam-apps-details-demo-app");
+        do_job(&config, CrawlMode::Local);
+        return;
+    }
+
+    let command = boxed_command.unwrap();
+    match command.as_str() {
+        "rotate-key" => do_rotate_key(&config, args.get(2)),
+        "import-key" => do_import_key(&config, args.get(2), args.get(3)),
+        "export-public-key" => do_export_public_key(&config, args.get(2), args.get(3)),
+        "fingerprint" => do_print_fingerprint(&config, args.get(2)),
+        "verify-audit-log" => do_verify_audit_log(&config),
+        "export-records" => do_export_records(&config, args.get(2), args.get(3)),
+        "decrypt-records" => do_decrypt_records(args.get(2), args.get(3), args.get(4)),
+        "shard" => do_shard(&config, args.get(2)),
+        "coordinator" => do_job(&config, CrawlMode::Coordinator),
+        "dashboard" => do_job(&config, CrawlMode::Dashboard),
+        "status" => do_status(),
+        "history" => do_history(),
+        "serve" => do_serve(&config),
+        "search" => do_search(&args[2..]),
+        "worker" => do_worker(&config),
+        _ => {
+            eprintln!("unknown command: {}", command);
+            eprintln!("usage: retrieve-all-steam-apps-details-demo-app [command]");
+            eprintln!("  rotate-key [rsa4096|ed25519|x25519]");
+            eprintln!("  import-key <private key PEM file> [public key PEM file]");
+            eprintln!("  export-public-key [pem|openssh|jwk] [key id]");
+            eprintln!("  fingerprint [key id]");
+            eprintln!("  verify-audit-log");
+            eprintln!("  export-records [recipients JSON file] [output file]");
+            eprintln!("  decrypt-records <export file> <private key PEM file> [signer public key PEM file]");
+            eprintln!("  config check");
+            eprintln!("  shard start|status");
+            eprintln!("  coordinator");
+            eprintln!("  dashboard");
+            eprintln!("  status");
+            eprintln!("  history");
+            eprintln!("  serve");
+            eprintln!("  search [--rebuild] <query>");
+            eprintln!("  worker");
+            process::exit(1);
+        }
+    }
+}
 
-   do_job()
+/// Whether the apps are fetched by this process or leased to worker processes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CrawlMode {
+    Local,
+    Coordinator,
+    /// Local crawl shown on an interactive terminal dashboard.
+    Dashboard,
 }
 
-fn do_job() {
+/// Crawls while holding the cache lock, another instance running on the cache stops this one.
+/// The report of the session is written when it starts and when the crawl ends.
+fn do_job(config: &Config, mode: CrawlMode) {
+    let boxed_lock = CacheLock::acquire(get_cache_dir_path().as_str());
+    if boxed_lock.is_err() {
+        logging::error("unable to lock the cache, exiting...", &[("error", json!(boxed_lock.err().unwrap()))]);
+        process::exit(1);
+    }
+    let lock = boxed_lock.unwrap();
+    logging::info("cache locked", &[("pid", json!(lock.get_holder().pid)), ("hostname", json!(lock.get_holder().hostname))]);
+
+    // log lines would be drawn over the dashboard
+    if mode == CrawlMode::Dashboard && config.logging.file.is_none() {
+        let log_path = get_cache_filepath(DASHBOARD_LOG_FILENAME);
+        let boxed_file = RotatingFile::open(log_path.as_str(), config.logging.max_file_bytes, config.logging.max_files);
+        if boxed_file.is_err() {
+            logging::error("unable to open the dashboard log, exiting...", &[("error", json!(boxed_file.err().unwrap()))]);
+            process::exit(1);
+        }
+        println!("dashboard logs to {}", log_path);
+        logging::init(Logger::new(config.logging.level, config.logging.format, Some(boxed_file.unwrap())));
+    }
+
+    session::install_stop_handler();
+    let metrics = start_metrics(config);
+    let session_log = SessionLog::new(get_cache_filepath(SESSIONS_DIRECTORY));
+    let boxed_previous = session_log.list().unwrap_or_default().into_iter().rev().find(|report| report.app_list_size > 0);
+    let holder = lock.get_holder();
+    let mut report = SessionReport::new(format!("{:?}", mode).to_lowercase().as_str(), holder.pid, holder.hostname.as_str(), holder.started_at);
+    write_session_report(&session_log, &report);
+
+    do_crawl(config, mode, &metrics);
+
+    report.update(&metrics.get_totals(), boxed_previous.as_ref());
+    let outcome = if session::is_stop_requested() { SessionOutcome::Stopped } else { SessionOutcome::Completed };
+    report.finish(outcome, get_unix_timestamp());
+    write_session_report(&session_log, &report);
+    logging::info("session finished", &[
+        ("session", json!(report.id)), ("outcome", json!(report.outcome)),
+        ("attempted", json!(report.attempted)), ("succeeded", json!(report.succeeded)), ("failed", json!(report.failed)),
+        ("retried", json!(report.retried)), ("bytes_stored", json!(report.bytes_stored)),
+    ]);
+    print_session_report(&report);
+}
+
+fn write_session_report(session_log: &SessionLog, report: &SessionReport) {
+    let boxed_write = session_log.write(report);
+    if boxed_write.is_err() {
+        logging::warn("unable to write the session report", &[("session", json!(report.id)), ("error", json!(boxed_write.err().unwrap()))]);
+    }
+}
+
+fn print_session_report(report: &SessionReport) {
+    let now = get_unix_timestamp();
+    let ended_at = report.ended_at.unwrap_or(now);
+    let outcome = if report.is_interrupted() { "interrupted".to_string() } else { format!("{:?}", report.outcome).to_lowercase() };
+    println!("session {} ({}, {} mode, PID {} on {})", report.id, outcome, report.mode, report.pid, report.hostname);
+    println!("  started {}, ended {}, took {}",
+             logging::format_timestamp(report.started_at * 1000),
+             report.ended_at.map(|ended_at| logging::format_timestamp(ended_at * 1000)).unwrap_or_else(|| "-".to_string()),
+             format_duration(ended_at.saturating_sub(report.started_at)));
+    println!("  app list: {} apps, {} new, {} to fetch", report.app_list_size, report.new_apps, report.apps_to_fetch);
+    println!("  apps: {} attempted, {} succeeded, {} failed, {} retries", report.attempted, report.succeeded, report.failed, report.retried);
+    if !report.failed_by_class.is_empty() {
+        let classes: Vec<String> = report.failed_by_class.iter().map(|(class, count)| format!("{} {}", class, count)).collect();
+        println!("  failed requests: {}", classes.join(", "));
+    }
+    println!("  stored: {} bytes", report.bytes_stored);
+    println!("  backups: {} taken, {} failed, restores: {} done, {} failed", report.backups_taken, report.backups_failed, report.restores, report.restores_failed);
+    if !report.integrity_events.is_empty() {
+        let events: Vec<String> = report.integrity_events.iter().map(|(result, count)| format!("{} {}", result, count)).collect();
+        println!("  integrity checks: {}", events.join(", "));
+    }
+}
+
+/// Prints the reports of past sessions on the cache, oldest first.
+fn do_history() {
+    let session_log = SessionLog::new(get_cache_filepath(SESSIONS_DIRECTORY));
+    let boxed_reports = session_log.list();
+    if boxed_reports.is_err() {
+        eprintln!("{}", boxed_reports.err().unwrap());
+        process::exit(1);
+    }
+    let reports = boxed_reports.unwrap();
+    if reports.is_empty() {
+        println!("no crawl session has run on the cache yet");
+        return;
+    }
+    for report in reports.iter() {
+        print_session_report(report);
+    }
+}
+
+fn do_crawl(config: &Config, mode: CrawlMode, metrics: &Arc<Metrics>) {
     // How to use: 2. Getting app list from Steam store.
 
 
     let mut processed_app_id_list: Vec<i64> = vec![];
 
-    println!("Getting list of already processed app ids. This may take a while...");
-    let already_processed_app_id_list_path = [get_cache_dir_path(), "/".to_string(), "processed_app_id_list.json".to_string()].join("");
-    let already_processed_app_id_list_path_sha_256 = [get_cache_dir_path(), "/".to_string(), "processed_app_id_list.json.sha256".to_string()].join("");
+    let keyring = open_keyring(config);
+    let mut audit_log = open_audit_log();
+    verify_cache_integrity(config, &keyring, &mut audit_log, metrics);
+
+    logging::info("getting list of already processed app ids, this may take a while...", &[]);
+    let already_processed_app_id_list_path = get_cache_filepath(PROCESSED_APP_ID_LIST_FILENAME);
+    let already_processed_app_id_list_path_sha_256 = [already_processed_app_id_list_path.as_str(), CHECKSUM_SUFFIX].join("");
     let file_exists = Path::new(already_processed_app_id_list_path.as_str()).is_file();
     if file_exists {
         let serialized_string = read_to_string(&already_processed_app_id_list_path).unwrap();
-        if serialized_string.len() > 0 {
+        if !serialized_string.is_empty() {
             let boxed_processed_app_id_list = serde_json::from_str(serialized_string.as_str());
-            if boxed_processed_app_id_list.is_ok() {
-                processed_app_id_list = boxed_processed_app_id_list.unwrap();
+            if let Ok(list) = boxed_processed_app_id_list {
+                processed_app_id_list = list;
 
 
                 //Verification
                 let list_as_string: String = format!("{:?}", &processed_app_id_list);
                 let list_as_u8 : &[u8] = list_as_string.as_bytes();
                 let sha_256 = digest(list_as_u8);
-                println!("SHA256 deserialized list: {}", sha_256);
+                logging::debug("processed app id list deserialized", &[("sha256", json!(sha_256))]);
 
                 let mut file = OpenOptions::new()
                     .read(true)
@@ -54,183 +265,1186 @@ fn do_job() {
                 let mut sha256_from_file: String = "".to_string();
                 let boxed_sha = file.read_to_string(&mut sha256_from_file);
                 if boxed_sha.is_ok() {
-                    println!("SHA256 from file: {}", sha256_from_file);
+                    logging::debug("processed app id list checksum read", &[("sha256", json!(sha256_from_file))]);
                 }
 
                 if sha_256 != sha256_from_file {
-                    do_restore_from_backup();
-                    //retry after backup restore
-                    do_job();
+                    logging::warn("processed app id list does not match its checksum, restoring from backup", &[("sha256", json!(sha_256)), ("expected_sha256", json!(sha256_from_file))]);
+                    do_restore_from_backup(config, &mut audit_log, &keyring, metrics);
+                    //retry after backup restore, the restored cache is crawled by the nested call
+                    do_crawl(config, mode, metrics);
+                    return;
                 }
-                do_backup();
+                do_backup(config, &mut audit_log, &keyring, metrics);
             } else {
-                println!("unable to deserialize processed app list");
-                do_restore_from_backup();
-                do_job();
+                logging::warn("unable to deserialize processed app id list, restoring from backup", &[]);
+                do_restore_from_backup(config, &mut audit_log, &keyring, metrics);
+                do_crawl(config, mode, metrics);
+                return;
             }
         }
     } else {
         File::create(&already_processed_app_id_list_path).unwrap();
     }
+    if !sign_cache(&keyring) {
+        process::exit(1);
+    }
 
-    println!("Filtering already processed app details. This may take a while...");
-    let mut iteration = 0;
+    logging::info("filtering already processed app details, this may take a while...", &[]);
     let app_list : Vec<SteamApp> = get_steam_app_list();
-    let app_list_path_sha_256 = [get_cache_dir_path(), "/".to_string(), "ISteamApps-GetAppList-v2.json.sha256".to_string()].join("");
+    let app_list_path_sha_256 = [get_cache_filepath(APP_LIST_FILENAME).as_str(), CHECKSUM_SUFFIX].join("");
     let list_as_string: String = format!("{:?}", &app_list);
     let list_as_u8 : &[u8] = list_as_string.as_bytes();
     let digest = write_sha256(&app_list_path_sha_256, list_as_u8);
-    println!("Written SHA256 for the app list: {}", digest);
+    logging::debug("app list checksum written", &[("sha256", json!(digest))]);
+    if !sign_cache(&keyring) {
+        process::exit(1);
+    }
 
     let app_list_size = app_list.len();
+    let app_list_ids: Vec<i64> = app_list.iter().map(|steam_app| steam_app.appid).collect();
     let filtered_list: Vec<SteamApp> = app_list
         .into_iter()
-        .filter(|steam_app| {
-            iteration = iteration + 1;
-            print!("\rFiltering already processed apps. Iteration {} of {}", iteration, app_list_size);
-            !processed_app_id_list.contains(&steam_app.appid)
-
-        })
+        .filter(|steam_app| !processed_app_id_list.contains(&steam_app.appid))
         .collect();
+    logging::info("already processed apps filtered", &[("apps", json!(app_list_size)), ("apps_to_fetch", json!(filtered_list.len()))]);
 
-    let filtered_list_len = filtered_list.len();
-
-    let mut iteration_number = 1;
-    for app in filtered_list {
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(false)
-            .open(&already_processed_app_id_list_path)
-            .unwrap();
-        let calculated_percentage = (100_f32 * iteration_number as f32) / filtered_list_len as f32;
+    let mut progress = CrawlProgress {
+        processed_app_id_list,
+        processed_app_id_list_path: already_processed_app_id_list_path,
+        processed_app_id_list_path_sha_256: already_processed_app_id_list_path_sha_256,
+        estimator: ProgressEstimator::new(app_list_size, filtered_list.len(), config.retry.delay_seconds, get_unix_timestamp()),
+        metrics: metrics.clone(),
+        dashboard: None,
+        signed_at: get_unix_timestamp(),
+    };
+    metrics.set_apps(app_list_size, filtered_list.len());
 
-        println!("\n\n Iteration number: {} \n App List size:    {}  {}%  After filtering: {}", iteration_number, app_list_size, calculated_percentage, filtered_list_len);
-        retrieve_detailed_app_info(app.appid);
-        iteration_number = iteration_number + 1;
-        let _ = &processed_app_id_list.push(app.appid);
+    if mode == CrawlMode::Dashboard {
+        let state = DashboardState::new(config.crawl.requests_per_minute, config.retry.delay_seconds);
+        let boxed_dashboard = Dashboard::open(state);
+        if boxed_dashboard.is_err() {
+            logging::error("unable to open the dashboard, exiting...", &[("error", json!(boxed_dashboard.err().unwrap()))]);
+            process::exit(1);
+        }
+        progress.dashboard = Some(boxed_dashboard.unwrap());
+    }
 
-        let serialized_list = serde_json::to_string(&processed_app_id_list).unwrap();
-        file.write_all(serialized_list.as_ref()).unwrap();
+    if mode == CrawlMode::Coordinator {
+        let app_ids: Vec<i64> = filtered_list.iter().map(|app| app.appid).collect();
+        serve_workers(config, app_ids, &mut progress, &mut audit_log, &keyring);
+    } else if config.shard.work_directory.is_some() {
+        crawl_shards(config, &app_list_ids, &mut progress, &mut audit_log, &keyring);
+    } else {
+        let app_ids: Vec<i64> = filtered_list.iter().map(|app| app.appid).collect();
+        let boxed_control = progress.dashboard.as_ref().map(|dashboard| dashboard.get_control());
+        crawler::fetch_apps(app_ids, &config.crawl, &config.retry, |app_id| {
+            if let Some(control) = boxed_control.as_ref() {
+                control.wait_while_paused();
+            }
+            fetch_app_details(metrics, app_id)
+        }, |event| {
+            handle_fetch_event(config, event, &mut progress, &mut audit_log, &keyring)
+        });
+    }
 
-        let list_as_string: String = format!("{:?}", &processed_app_id_list);
-        let list_as_u8 : &[u8] = list_as_string.as_bytes();
-        let digest = write_sha256(&already_processed_app_id_list_path_sha_256, list_as_u8);
-        println!("SHA256 after write for the list of already processed app ids: {}", digest);
+    // the manifest is signed at checkpoints only, the last apps processed are covered here
+    sign_cache(&keyring);
+    // entries since the last seal are signed before the session ends
+    let boxed_seal = audit_log.seal(&keyring);
+    if boxed_seal.is_err() {
+        logging::error("unable to seal the audit log", &[("error", json!(boxed_seal.err().unwrap()))]);
+    }
+    // apps fetched again are appended to the search index, their earlier lines are dropped
+    match IndexFile::new(get_cache_filepath(INDEX_FILENAME)).compact() {
+        Ok(0) => {}
+        Ok(dropped_line_count) => logging::info("search index compacted", &[("dropped_lines", json!(dropped_line_count))]),
+        Err(error) => logging::warn("unable to compact the search index", &[("error", json!(error))]),
     }
 }
 
-fn retrieve_detailed_app_info(app_id: i64) {
-    // How to use: 3. Getting app details from Steam store.
-    let boxed_result = get_app_details(app_id);
-    if boxed_result.is_ok() {
-        let app_details = boxed_result.unwrap();
-        println!("result is ok for {} app id {}", app_details.name, app_details.app_id);
+/// Local bookkeeping of the crawl, the list of processed app ids with its SHA-256.
+struct CrawlProgress {
+    processed_app_id_list: Vec<i64>,
+    processed_app_id_list_path: String,
+    processed_app_id_list_path_sha_256: String,
+    estimator: ProgressEstimator,
+    metrics: Arc<Metrics>,
+    dashboard: Option<Dashboard>,
+    /// When the integrity manifest was last signed, in seconds since the epoch.
+    signed_at: u64,
+}
 
-    } else {
-        let error_message = boxed_result.err().unwrap();
-        println!("{} {}", error_message, app_id);
+// How to use: 3. Getting app details from Steam store.
+fn fetch_app_details(metrics: &Metrics, app_id: i64) -> Result<String, String> {
+    let started_at = Instant::now();
+    let boxed_name = get_app_details(app_id).map(|app_details| app_details.name);
+    metrics.observe_fetch(started_at.elapsed(), boxed_name.is_ok(), get_unix_timestamp());
+    boxed_name
+}
 
-        let is_not_steam_unsuccessful_response = error_message != "steampowered api returned failed response";
-        let is_not_invalid_utf8_sequence = error_message != "invalid utf-8 sequence";
-        let no_response_from_api = error_message == "no response from API";
+/// Returns false once the crawl is stopped from the dashboard or by a signal.
+fn handle_fetch_event(config: &Config, event: FetchEvent, progress: &mut CrawlProgress, audit_log: &mut AuditLog, keyring: &Keyring) -> bool {
+    let now = get_unix_timestamp();
+    progress.metrics.record_event(&event);
+    progress.estimator.record_event(&event, now);
+    let estimate = progress.estimator.get_estimate(now);
+    progress.metrics.set_estimate(&estimate);
+    if let Some(dashboard) = progress.dashboard.as_mut() {
+        dashboard.handle_event(&event, &estimate, now);
+        if dashboard.get_control().take_backup_request() {
+            dashboard.get_state().set_backup_status("in progress");
+            dashboard.draw(&estimate, now);
+            let is_backed_up = do_backup(config, audit_log, keyring, &progress.metrics);
+            let status = if is_backed_up {
+                format!("taken at {}", logging::format_timestamp(get_unix_timestamp() * 1000))
+            } else {
+                "failed, see the log".to_string()
+            };
+            dashboard.get_state().set_backup_status(status.as_str());
+            dashboard.draw(&estimate, get_unix_timestamp());
+        }
+    }
 
-        if (is_not_steam_unsuccessful_response && is_not_invalid_utf8_sequence) || no_response_from_api {
-            println!("result is not ok for app id {}, retry in 1 min ", app_id);
+    match event {
+        // fetches are logged by the crawler, or the worker in coordinator mode
+        FetchEvent::Fetched { app_id, name } => {
+            let boxed_document = fs::read_to_string(get_resource_filepath(app_id));
+            if let Ok(document) = boxed_document {
+                progress.metrics.record_stored(document.len() as u64);
+                index_app_details(app_id, document.as_str());
+            }
+            record_audit_event(audit_log, keyring, AuditEvent::AppFetched { app_id, name });
+        }
+        FetchEvent::Failed { app_id, error } => {
+            record_audit_event(audit_log, keyring, AuditEvent::AppFailed { app_id, error });
+        }
+        FetchEvent::Retried { app_id } => {
+            record_audit_event(audit_log, keyring, AuditEvent::AppRetried { app_id });
+        }
+        FetchEvent::Finished { app_id } => {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&progress.processed_app_id_list_path)
+                .unwrap();
+            logging::info("app processed", &[
+                ("app_id", json!(app_id)),
+                ("processed", json!(estimate.processed_total)), ("apps", json!(estimate.app_list_size)), ("percentage", json!(estimate.percentage)),
+                ("run_processed", json!(estimate.processed)), ("run_apps_to_fetch", json!(estimate.apps_to_fetch)), ("run_percentage", json!(estimate.run_percentage)),
+                ("apps_per_minute", json!((estimate.apps_per_minute * 10.0).round() / 10.0)), ("eta_seconds", json!(estimate.eta_seconds)),
+                ("retries", json!(estimate.retries)), ("backoff_seconds", json!(estimate.backoff_seconds)),
+            ]);
+            let boxed_status = estimator::write_status(Path::new(get_cache_filepath(STATUS_FILENAME).as_str()), &estimate);
+            if boxed_status.is_err() {
+                logging::warn("unable to write crawl status", &[("error", json!(boxed_status.err().unwrap()))]);
+            }
+            if !progress.processed_app_id_list.contains(&app_id) {
+                progress.processed_app_id_list.push(app_id);
+            }
 
-            let one_minute = time::Duration::from_secs(60);
-            thread::sleep(one_minute);
+            let serialized_list = serde_json::to_string(&progress.processed_app_id_list).unwrap();
+            file.write_all(serialized_list.as_ref()).unwrap();
 
-            retrieve_detailed_app_info(app_id);
+            let list_as_string: String = format!("{:?}", &progress.processed_app_id_list);
+            let list_as_u8 : &[u8] = list_as_string.as_bytes();
+            let digest = write_sha256(&progress.processed_app_id_list_path_sha_256, list_as_u8);
+            logging::debug("processed app id list checksum written", &[("app_id", json!(app_id)), ("sha256", json!(digest))]);
+            // signing hashes the whole app list, the manifest is brought up to date once per interval
+            if now.saturating_sub(progress.signed_at) >= CACHE_SIGNING_INTERVAL_SECONDS && sign_cache(keyring) {
+                progress.signed_at = now;
+            }
         }
+        FetchEvent::Idle => {}
+    }
+    if progress.dashboard.is_some() && progress.dashboard.as_ref().unwrap().get_control().is_stop_requested() {
+        session::request_stop();
+    }
+    !session::is_stop_requested()
+}
+
+/// Adds the stored details of the app to the search index.
+fn index_app_details(app_id: i64, document: &str) {
+    let boxed_entry = IndexEntry::from_document(app_id, document);
+    if boxed_entry.is_err() {
+        logging::warn("unable to index app details", &[("app_id", json!(app_id)), ("error", json!(boxed_entry.err().unwrap()))]);
+        return;
+    }
+    let boxed_entry = boxed_entry.unwrap();
+    if boxed_entry.is_none() {
+        return;
+    }
+    let boxed_append = IndexFile::new(get_cache_filepath(INDEX_FILENAME)).append(&boxed_entry.unwrap());
+    if boxed_append.is_err() {
+        logging::warn("unable to index app details", &[("app_id", json!(app_id)), ("error", json!(boxed_append.err().unwrap()))]);
     }
 }
 
-fn do_backup() {
-    let already_processed_app_id_list_path = [get_cache_dir_path(), "/".to_string(), "processed_app_id_list.json".to_string()].join("");
-    let already_processed_app_id_list_path_sha_256 = [get_cache_dir_path(), "/".to_string(), "processed_app_id_list.json.sha256".to_string()].join("");
+/// Claims shards of the current generation in the shared work directory until none is
+/// left, starting the first generation from the app list if there is none yet.
+/// Apps of a sharded crawl are fetched once per generation, whatever the local cache holds.
+fn crawl_shards(config: &Config, app_ids: &[i64], progress: &mut CrawlProgress, audit_log: &mut AuditLog, keyring: &Keyring) {
+    let work_directory = WorkDirectory::new(config.shard.work_directory.as_ref().unwrap());
+    let worker_id = get_worker_id(config);
 
-    let backup_already_processed_app_id_list_path = [get_cache_dir_path(), "/".to_string(), "backup_processed_app_id_list.json".to_string()].join("");
-    let backup_already_processed_app_id_list_path_sha_256 = [get_cache_dir_path(), "/".to_string(), "backup_processed_app_id_list.json.sha256".to_string()].join("");
+    let mut boxed_generation = work_directory.get_current_generation();
+    if boxed_generation.is_ok() && boxed_generation.as_ref().unwrap().is_none() {
+        boxed_generation = work_directory.start_generation(app_ids, config.shard.shard_size).map(Some);
+    }
+    if boxed_generation.is_err() {
+        logging::error("unable to get the crawl generation, exiting...", &[("error", json!(boxed_generation.err().unwrap()))]);
+        process::exit(1);
+    }
+    let generation = boxed_generation.unwrap().unwrap();
 
-    let app_list_path = [get_cache_dir_path(), "/".to_string(), "ISteamApps-GetAppList-v2.json".to_string()].join("");
-    let app_list_path_sha_256 = [get_cache_dir_path(), "/".to_string(), "ISteamApps-GetAppList-v2.json.sha256".to_string()].join("");
+    loop {
+        let boxed_claim = work_directory.claim(&generation, worker_id.as_str(), config.shard.lease_seconds);
+        if boxed_claim.is_err() {
+            logging::error("unable to claim a shard, exiting...", &[("error", json!(boxed_claim.err().unwrap()))]);
+            process::exit(1);
+        }
+        if boxed_claim.as_ref().unwrap().is_none() {
+            logging::info("no shard left to claim", &[("generation", json!(generation.number)), ("worker_id", json!(worker_id))]);
+            break;
+        }
+        let mut claim = boxed_claim.unwrap().unwrap();
+        logging::info("shard claimed", &[
+            ("worker_id", json!(worker_id)), ("shard", json!(claim.shard)), ("generation", json!(generation.number)),
+            ("lease_expires_at", json!(claim.get_lease().expires_at)), ("apps_to_fetch", json!(claim.remaining_app_ids.len())),
+        ]);
 
-    let backup_app_list_path = [get_cache_dir_path(), "/".to_string(), "backup_ISteamApps-GetAppList-v2.json".to_string()].join("");
-    let backup_app_list_path_sha_256 = [get_cache_dir_path(), "/".to_string(), "backup_ISteamApps-GetAppList-v2.json.sha256".to_string()].join("");
+        progress.estimator.set_remaining(claim.remaining_app_ids.len());
+        let mut boxed_lease_error: Option<String> = None;
+        let metrics = progress.metrics.clone();
+        let boxed_control = progress.dashboard.as_ref().map(|dashboard| dashboard.get_control());
+        crawler::fetch_apps(claim.remaining_app_ids.to_vec(), &config.crawl, &config.retry, |app_id| {
+            if let Some(control) = boxed_control.as_ref() {
+                control.wait_while_paused();
+            }
+            fetch_app_details(&metrics, app_id)
+        }, |event| {
+            let boxed_record = match event {
+                FetchEvent::Finished { app_id } => claim.record_fetched(app_id),
+                _ => claim.renew(),
+            };
+            let is_running = handle_fetch_event(config, event, progress, audit_log, keyring);
+            if boxed_record.is_err() && boxed_lease_error.is_none() {
+                boxed_lease_error = Some(boxed_record.err().unwrap());
+            }
+            is_running && boxed_lease_error.is_none()
+        });
 
+        if let Some(lease_error) = boxed_lease_error {
+            logging::warn("shard lease lost, leaving the shard to its new holder", &[("shard", json!(claim.shard)), ("error", json!(lease_error))]);
+            continue;
+        }
+        // stopped from the dashboard or by a signal, the rest of the shard is left to other workers
+        if session::is_stop_requested() {
+            claim.release();
+            break;
+        }
+        let boxed_complete = claim.complete();
+        if boxed_complete.is_err() {
+            logging::error("unable to complete the shard, exiting...", &[("error", json!(boxed_complete.err().unwrap()))]);
+            process::exit(1);
+        }
+    }
 
-    let boxed_backup = fs::copy(&already_processed_app_id_list_path, &backup_already_processed_app_id_list_path);
-    if boxed_backup.is_err() {
-        println!("backup creation failed, exiting...");
+    let boxed_status = work_directory.get_status(&generation);
+    if boxed_status.is_ok() && boxed_status.as_ref().unwrap().is_complete() {
+        logging::info("generation is complete, run shard start to begin the next one", &[("generation", json!(generation.number))]);
+    }
+}
+
+/// Leases the apps to worker processes and keeps the progress of their reports,
+/// documents the workers send are stored in the cache like fetched ones.
+fn serve_workers(config: &Config, app_ids: Vec<i64>, progress: &mut CrawlProgress, audit_log: &mut AuditLog, keyring: &Keyring) {
+    let boxed_listener = TcpListener::bind(config.coordinator.address.as_str());
+    if boxed_listener.is_err() {
+        logging::error("unable to listen, exiting...", &[("address", json!(config.coordinator.address)), ("error", json!(boxed_listener.err().unwrap().to_string()))]);
+        process::exit(1);
+    }
+    let listener = boxed_listener.unwrap();
+    logging::info("coordinator listening", &[("address", json!(config.coordinator.address)), ("apps_to_fetch", json!(app_ids.len()))]);
+
+    let mut coordinator = Coordinator::new(app_ids, config.coordinator.lease_seconds);
+    let boxed_serve = coordinator::http::serve(&listener, &mut coordinator, config.coordinator.token.as_deref(), |event| {
+        handle_fetch_event(config, event, progress, audit_log, keyring)
+    }, store_app_details);
+    if boxed_serve.is_err() {
+        logging::error("coordinator stopped, exiting...", &[("error", json!(boxed_serve.err().unwrap()))]);
+        process::exit(1);
+    }
+    let status = coordinator.get_status(get_unix_timestamp());
+    if !coordinator.is_finished() {
+        // stopped from the dashboard or by a signal, apps not reported are fetched in the next session
+        logging::info("coordinator stopped", &[("fetched", json!(status.fetched)), ("failed", json!(status.failed)), ("pending", json!(status.pending)), ("leased", json!(status.leased))]);
         return;
+    }
+    logging::info("every app was reported", &[("fetched", json!(status.fetched)), ("failed", json!(status.failed))]);
+}
+
+fn store_app_details(app_id: i64, document: &str) -> Result<(), String> {
+    let filepath = get_resource_filepath(app_id);
+    let boxed_parent = Path::new(filepath.as_str()).parent();
+    if let Some(parent) = boxed_parent {
+        let boxed_create = fs::create_dir_all(parent);
+        if boxed_create.is_err() {
+            return Err(format!("unable to store app details of {}: {}", app_id, boxed_create.err().unwrap()));
+        }
+    }
+    // written next to the stored details and renamed over them, readers never see half a document
+    let temporary_path = [filepath.as_str(), PENDING_SUFFIX].join("");
+    let boxed_write = File::create(temporary_path.as_str()).and_then(|mut file| file.write_all(document.as_bytes()).and_then(|_| file.sync_all()));
+    if boxed_write.is_err() {
+        return Err(format!("unable to store app details of {}: {}", app_id, boxed_write.err().unwrap()));
+    }
+    let boxed_rename = fs::rename(temporary_path.as_str(), filepath.as_str());
+    if boxed_rename.is_err() {
+        return Err(format!("unable to store app details of {}: {}", app_id, boxed_rename.err().unwrap()));
+    }
+    Ok(())
+}
+
+/// Fetches the apps leased by the coordinator and reports them back. The worker keeps
+/// no progress of its own and needs no keys, it exits once the coordinator has nothing left.
+fn do_worker(config: &Config) {
+    let client = CoordinatorClient::new(config.coordinator.address.as_str(), config.coordinator.token.as_deref());
+    let worker_id = get_worker_id(config);
+    let metrics = start_metrics(config);
+
+    loop {
+        let boxed_lease = client.lease(&LeaseRequest { worker_id: worker_id.to_string(), max_apps: config.coordinator.batch_size });
+        if boxed_lease.is_err() {
+            logging::error("unable to lease apps, exiting...", &[("error", json!(boxed_lease.err().unwrap()))]);
+            process::exit(1);
+        }
+        let lease = boxed_lease.unwrap();
+        if lease.app_ids.is_empty() {
+            if lease.finished {
+                logging::info("coordinator has no apps left, exiting...", &[]);
+                return;
+            }
+            thread::sleep(Duration::from_secs(lease.retry_after_seconds));
+            continue;
+        }
+        logging::info("apps leased", &[("worker_id", json!(worker_id)), ("apps", json!(lease.app_ids.len())), ("lease_seconds", json!(lease.lease_seconds))]);
+
+        // leases of the batch are renewed once half of them passed
+        let mut last_heartbeat = get_unix_timestamp();
+        let mut fetched_app_ids: Vec<i64> = vec![];
+        let mut last_error = "".to_string();
+        let mut boxed_report_error: Option<String> = None;
+        crawler::fetch_apps(lease.app_ids, &config.crawl, &config.retry, |app_id| fetch_app_details(&metrics, app_id), |event| {
+            metrics.record_event(&event);
+            let boxed_report = match event {
+                FetchEvent::Fetched { app_id, name } => {
+                    fetched_app_ids.push(app_id);
+                    let document = read_to_string(get_resource_filepath(app_id)).ok();
+                    client.report_success(&SuccessReport { worker_id: worker_id.to_string(), app_id, name, document })
+                }
+                FetchEvent::Failed { error, .. } => {
+                    last_error = error;
+                    Ok(())
+                }
+                FetchEvent::Retried { app_id } => {
+                    client.report_failure(&FailureReport { worker_id: worker_id.to_string(), app_id, error: last_error.to_string(), will_retry: true })
+                }
+                FetchEvent::Finished { app_id } => {
+                    if fetched_app_ids.contains(&app_id) {
+                        Ok(())
+                    } else {
+                        client.report_failure(&FailureReport { worker_id: worker_id.to_string(), app_id, error: last_error.to_string(), will_retry: false })
+                    }
+                }
+                FetchEvent::Idle => Ok(()),
+            };
+
+            let now = get_unix_timestamp();
+            if boxed_report.is_ok() && now >= last_heartbeat + lease.lease_seconds / 2 {
+                last_heartbeat = now;
+                let boxed_heartbeat = client.heartbeat(&HeartbeatRequest { worker_id: worker_id.to_string() });
+                if boxed_heartbeat.is_err() {
+                    boxed_report_error = Some(boxed_heartbeat.err().unwrap());
+                }
+            }
+            if boxed_report.is_err() {
+                boxed_report_error = Some(boxed_report.err().unwrap());
+            }
+            boxed_report_error.is_none()
+        });
+
+        if let Some(report_error) = boxed_report_error {
+            logging::error("unable to report to the coordinator, exiting...", &[("error", json!(report_error))]);
+            process::exit(1);
+        }
+    }
+}
+
+/// Prints the latest estimate of the crawl running on the cache, or of the last one.
+fn do_status() {
+    let status_path = get_cache_filepath(STATUS_FILENAME);
+    let boxed_estimate = estimator::read_status(Path::new(status_path.as_str()));
+    if boxed_estimate.is_err() {
+        eprintln!("{}, no crawl has processed an app yet", boxed_estimate.err().unwrap());
+        process::exit(1);
+    }
+    let estimate = boxed_estimate.unwrap();
+    let now = get_unix_timestamp();
+
+    let boxed_holder = lock::read_lock_holder(Path::new(get_cache_filepath(lock::LOCK_FILENAME).as_str())).ok().filter(|holder| holder.is_alive());
+    if let Some(holder) = boxed_holder {
+        println!("crawler running: PID {} on {}", holder.pid, holder.hostname);
     } else {
-        println!("backup done.")
+        println!("crawler not running");
     }
+    println!("updated {} ago, crawl started {} ago", format_duration(now.saturating_sub(estimate.updated_at)), format_duration(now.saturating_sub(estimate.started_at)));
+    println!("app list: {} of {} apps processed ({:.1}%)", estimate.processed_total, estimate.app_list_size, estimate.percentage);
+    println!("this run: {} of {} apps processed ({:.1}%)", estimate.processed, estimate.apps_to_fetch, estimate.run_percentage);
+    let eta = estimate.eta_seconds.map(format_duration).unwrap_or_else(|| "unknown".to_string());
+    println!("throughput: {:.1} apps/min over the last {}, eta {}", estimate.apps_per_minute, format_duration(estimator::RATE_WINDOW_SECONDS), eta);
+    println!("retries: {}, time lost to backoff {}", estimate.retries, format_duration(estimate.backoff_seconds));
+}
 
-    let boxed_backup_sha256 = fs::copy(&already_processed_app_id_list_path_sha_256, &backup_already_processed_app_id_list_path_sha_256);
-    if boxed_backup_sha256.is_err() {
-        println!("backup for sha256 creation failed, exiting...");
-        return;
+/// Serves the stored app details over HTTP. Only reads the cache, so it runs next to the crawler.
+fn do_serve(config: &Config) {
+    let boxed_listener = TcpListener::bind(config.serve.address.as_str());
+    if boxed_listener.is_err() {
+        logging::error("unable to listen, exiting...", &[("address", json!(config.serve.address)), ("error", json!(boxed_listener.err().unwrap().to_string()))]);
+        process::exit(1);
+    }
+    let listener = boxed_listener.unwrap();
+
+    // the SDK fetches the app list when it is not cached, the read API never calls Steam
+    let mut names: Vec<(i64, String)> = vec![];
+    if Path::new(get_cache_filepath(APP_LIST_FILENAME).as_str()).is_file() {
+        match get_cached_app_list() {
+            Ok(app_list) => names = app_list.into_iter().map(|app| (app.appid, app.name)).collect(),
+            Err(error) => logging::warn("unable to read the app list, search finds no app", &[("error", json!(error))]),
+        }
     } else {
-        println!("backup sha256 done.")
+        logging::warn("app list is not cached yet, search finds no app", &[]);
     }
+    let mut catalog = Catalog::new(names);
+    let mut last_modified: Option<SystemTime> = None;
+    refresh_catalog(&mut catalog, &mut last_modified);
+    logging::info("serving app details", &[("address", json!(config.serve.address)), ("apps_with_details", json!(catalog.get_app_count()))]);
 
-    let boxed_backup_app_list = fs::copy(&app_list_path, &backup_app_list_path);
-    if boxed_backup_app_list.is_err() {
-        println!("backup for app list creation failed, exiting...");
+    catalog::http::serve(&listener, &mut catalog, config.serve.token.as_deref(), |catalog| {
+        refresh_catalog(catalog, &mut last_modified)
+    }, |app_id| {
+        fs::read_to_string(get_resource_filepath(app_id)).ok()
+    }, |catalog| {
+        let boxed_estimate = estimator::read_status(Path::new(get_cache_filepath(STATUS_FILENAME).as_str())).ok();
+        let boxed_holder = lock::read_lock_holder(Path::new(get_cache_filepath(lock::LOCK_FILENAME).as_str()));
+        let is_crawler_running = boxed_holder.is_ok() && boxed_holder.unwrap().is_alive();
+        Health::new(boxed_estimate.as_ref(), is_crawler_running, catalog.get_app_count(), config.serve.stale_after_seconds, get_unix_timestamp())
+    });
+}
+
+/// Searches the names and descriptions of the apps, `--rebuild` first rebuilds the
+/// index from the stored app details. Names of the cached app list are searched too.
+fn do_search(arguments: &[String]) {
+    let is_rebuild = arguments.first().map(|argument| argument == "--rebuild").unwrap_or(false);
+    let query = arguments.iter().skip(if is_rebuild { 1 } else { 0 }).cloned().collect::<Vec<String>>().join(" ");
+    if is_rebuild {
+        rebuild_search_index();
+        if query.trim().is_empty() {
+            return;
+        }
+    }
+    if query.trim().is_empty() {
+        eprintln!("usage: search [--rebuild] <query>");
+        process::exit(1);
+    }
+
+    let boxed_index = IndexFile::new(get_cache_filepath(INDEX_FILENAME)).load();
+    if boxed_index.is_err() {
+        eprintln!("{}", boxed_index.err().unwrap());
+        process::exit(1);
+    }
+    let mut index = boxed_index.unwrap();
+    // the SDK fetches the app list when it is not cached, searching never calls Steam
+    if Path::new(get_cache_filepath(APP_LIST_FILENAME).as_str()).is_file() {
+        for app in get_cached_app_list().unwrap_or_default() {
+            index.insert_name(app.appid, app.name.as_str());
+        }
+    }
+
+    if index.len() == 0 {
+        println!("search index is empty, crawl apps or run search --rebuild");
         return;
-    } else {
-        println!("backup for app list done.")
     }
+    let results = index.search(query.as_str(), search::DEFAULT_LIMIT);
+    if results.is_empty() {
+        println!("no app matches {}", query);
+        return;
+    }
+    for result in results {
+        println!("{}\t{:.3}\t{}", result.app_id, result.score, result.name);
+    }
+}
+
+/// Indexes the stored details of every processed app again, the crawler is locked out meanwhile.
+fn rebuild_search_index() {
+    let boxed_lock = CacheLock::acquire(get_cache_dir_path().as_str());
+    if boxed_lock.is_err() {
+        eprintln!("unable to lock the cache to rebuild the search index: {}", boxed_lock.err().unwrap());
+        process::exit(1);
+    }
+
+    let mut entries: Vec<IndexEntry> = vec![];
+    for app_id in read_processed_app_id_list() {
+        let boxed_document = fs::read_to_string(get_resource_filepath(app_id));
+        if boxed_document.is_err() {
+            continue;
+        }
+        let boxed_entry = IndexEntry::from_document(app_id, boxed_document.unwrap().as_str());
+        if boxed_entry.is_err() {
+            eprintln!("skipping app {}: {}", app_id, boxed_entry.err().unwrap());
+            continue;
+        }
+        entries.extend(boxed_entry.unwrap());
+    }
+
+    let boxed_rewrite = IndexFile::new(get_cache_filepath(INDEX_FILENAME)).rewrite(&entries);
+    if boxed_rewrite.is_err() {
+        eprintln!("{}", boxed_rewrite.err().unwrap());
+        process::exit(1);
+    }
+    println!("search index rebuilt with {} apps", entries.len());
+}
 
-    let boxed_backup_app_list_sha256 = fs::copy(&app_list_path_sha_256, &backup_app_list_path_sha_256);
-    if boxed_backup_app_list_sha256.is_err() {
-        println!("backup for app list sha256 creation failed, exiting...");
+/// Apps processed so far, none if the list is missing or unreadable.
+fn read_processed_app_id_list() -> Vec<i64> {
+    let path = get_cache_filepath(PROCESSED_APP_ID_LIST_FILENAME);
+    read_to_string(path.as_str()).ok()
+        .and_then(|content| serde_json::from_str(content.as_str()).ok())
+        .unwrap_or_default()
+}
+
+/// Adds the apps processed since the list of processed apps was last read.
+fn refresh_catalog(catalog: &mut Catalog, last_modified: &mut Option<SystemTime>) {
+    let path = get_cache_filepath(PROCESSED_APP_ID_LIST_FILENAME);
+    let boxed_modified = fs::metadata(path.as_str()).and_then(|metadata| metadata.modified()).ok();
+    if boxed_modified.is_none() || boxed_modified == *last_modified {
+        return;
+    }
+    // list being written is read again with the next request
+    let boxed_app_ids = read_to_string(path.as_str()).ok().and_then(|content| serde_json::from_str::<Vec<i64>>(content.as_str()).ok());
+    if boxed_app_ids.is_none() {
         return;
-    } else {
-        println!("backup for app list sha256 done.")
     }
+    for app_id in boxed_app_ids.unwrap() {
+        if catalog.is_loaded(app_id) {
+            continue;
+        }
+        let boxed_document = fs::read_to_string(get_resource_filepath(app_id));
+        if boxed_document.is_err() {
+            catalog.insert_missing(app_id);
+            continue;
+        }
+        let boxed_insert = catalog.insert_document(app_id, boxed_document.unwrap().as_str());
+        if boxed_insert.is_err() {
+            logging::warn("unable to read stored app details", &[("app_id", json!(app_id)), ("error", json!(boxed_insert.err().unwrap()))]);
+        }
+    }
+    *last_modified = boxed_modified;
 }
 
-fn do_restore_from_backup() {
-    let already_processed_app_id_list_path = [get_cache_dir_path(), "/".to_string(), "processed_app_id_list.json".to_string()].join("");
-    let already_processed_app_id_list_path_sha_256 = [get_cache_dir_path(), "/".to_string(), "processed_app_id_list.json.sha256".to_string()].join("");
+fn get_worker_id(config: &Config) -> String {
+    if let Some(worker_id) = config.shard.worker_id.as_ref() {
+        return worker_id.to_string();
+    }
+    [lock::get_hostname(), process::id().to_string()].join("-")
+}
 
-    let backup_already_processed_app_id_list_path = [get_cache_dir_path(), "/".to_string(), "backup_processed_app_id_list.json".to_string()].join("");
-    let backup_already_processed_app_id_list_path_sha_256 = [get_cache_dir_path(), "/".to_string(), "backup_processed_app_id_list.json.sha256".to_string()].join("");
+/// Starts the next generation of the sharded crawl or prints the state of the current one.
+fn do_shard(config: &Config, boxed_subcommand: Option<&String>) {
+    if config.shard.work_directory.is_none() {
+        eprintln!("shard.work_directory is not configured");
+        process::exit(1);
+    }
+    let work_directory = WorkDirectory::new(config.shard.work_directory.as_ref().unwrap());
 
-    let app_list_path = [get_cache_dir_path(), "/".to_string(), "ISteamApps-GetAppList-v2.json".to_string()].join("");
-    let app_list_path_sha_256 = [get_cache_dir_path(), "/".to_string(), "ISteamApps-GetAppList-v2.json.sha256".to_string()].join("");
+    let subcommand = boxed_subcommand.map(|subcommand| subcommand.as_str()).unwrap_or("");
+    match subcommand {
+        "start" => {
+            let app_ids: Vec<i64> = get_steam_app_list().iter().map(|app| app.appid).collect();
+            let boxed_generation = work_directory.start_generation(&app_ids, config.shard.shard_size);
+            if boxed_generation.is_err() {
+                eprintln!("{}", boxed_generation.err().unwrap());
+                process::exit(1);
+            }
+            let generation = boxed_generation.unwrap();
+            println!("started generation {}: {} apps in {} shards", generation.number, generation.app_count, generation.shard_count);
+        }
+        "status" => {
+            let boxed_generation = work_directory.get_current_generation();
+            if boxed_generation.is_err() {
+                eprintln!("{}", boxed_generation.err().unwrap());
+                process::exit(1);
+            }
+            let boxed_current = boxed_generation.unwrap();
+            if boxed_current.is_none() {
+                println!("no generation started in {}", work_directory.get_path().display());
+                return;
+            }
+            let generation = boxed_current.unwrap();
 
-    let backup_app_list_path = [get_cache_dir_path(), "/".to_string(), "backup_ISteamApps-GetAppList-v2.json".to_string()].join("");
-    let backup_app_list_path_sha_256 = [get_cache_dir_path(), "/".to_string(), "backup_ISteamApps-GetAppList-v2.json.sha256".to_string()].join("");
+            let boxed_status = work_directory.get_status(&generation);
+            if boxed_status.is_err() {
+                eprintln!("{}", boxed_status.err().unwrap());
+                process::exit(1);
+            }
+            let status = boxed_status.unwrap();
+            println!("generation {}: {} of {} apps fetched", generation.number, status.fetched_apps, status.app_count);
+            println!("shards: {} done, {} leased, {} expired, {} pending of {}", status.done, status.leased, status.expired, status.pending, status.shards);
+        }
+        _ => {
+            eprintln!("usage: shard start|status");
+            process::exit(1);
+        }
+    }
+}
 
+/// Returns whether the backup was taken.
+fn do_backup(config: &Config, audit_log: &mut AuditLog, keyring: &Keyring, metrics: &Metrics) -> bool {
+    let started_at = Instant::now();
+    let boxed_backup_directory = get_backup_directory(config);
+    if boxed_backup_directory.is_err() {
+        logging::error("backup creation failed", &[("error", json!(boxed_backup_directory.err().unwrap()))]);
+        metrics.record_backup(false);
+        return false;
+    }
+    let backup_directory = boxed_backup_directory.unwrap();
+    let backup_path = backup_directory.join(BACKUP_FILENAME);
 
-    let boxed_backup_restore = fs::copy(&backup_already_processed_app_id_list_path, &already_processed_app_id_list_path);
-    if boxed_backup_restore.is_err() {
-        println!("backup restore for processed already apps failed, exiting...");
-        return;
+    // the manifest in the backup has to match the files it is taken with
+    if !sign_cache(keyring) {
+        logging::error("backup creation failed", &[("path", json!(backup_path.display().to_string())), ("error", json!("integrity manifest is not up to date"))]);
+        metrics.record_backup(false);
+        return false;
+    }
+    let boxed_backup = backup::create_backup(get_cache_dir_path().as_str(), &get_backup_entries(), backup_directory.as_path(), keyring);
+    let duration_ms = started_at.elapsed().as_millis() as u64;
+    if boxed_backup.is_err() {
+        logging::error("backup creation failed", &[
+            ("path", json!(backup_path.display().to_string())), ("duration_ms", json!(duration_ms)), ("error", json!(boxed_backup.err().unwrap())),
+        ]);
+        metrics.record_backup(false);
+        return false;
+    } else {
+        logging::info("backup done", &[("path", json!(backup_path.display().to_string())), ("duration_ms", json!(duration_ms))]);
     }
 
-    let boxed_backup_restore_sha256 = fs::copy(&backup_already_processed_app_id_list_path_sha_256, &already_processed_app_id_list_path_sha_256);
-    if boxed_backup_restore_sha256.is_err() {
-        println!("backup sha256 restore for processed already apps failed, exiting...");
+    metrics.record_backup(true);
+    record_audit_event(audit_log, keyring, AuditEvent::BackupTaken);
+    true
+}
+
+/// Files and directories of the cache included in the backup.
+fn get_backup_entries() -> Vec<String> {
+    vec![
+        PROCESSED_APP_ID_LIST_FILENAME.to_string(),
+        [PROCESSED_APP_ID_LIST_FILENAME, CHECKSUM_SUFFIX].join(""),
+        APP_LIST_FILENAME.to_string(),
+        [APP_LIST_FILENAME, CHECKSUM_SUFFIX].join(""),
+        MANIFEST_FILENAME.to_string(),
+        MANIFEST_SIGNATURE_FILENAME.to_string(),
+        get_app_details_store_directory(),
+    ]
+}
+
+/// Top directory of the app details store in the cache, as laid out by the sdk.
+fn get_app_details_store_directory() -> String {
+    let details_path = PathBuf::from(get_resource_filepath(0));
+    let relative_path = details_path.strip_prefix(get_cache_dir_path()).unwrap_or(details_path.as_path());
+    let boxed_component = relative_path.components().next();
+    boxed_component.map(|component| component.as_os_str().to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn do_restore_from_backup(config: &Config, audit_log: &mut AuditLog, keyring: &Keyring, metrics: &Metrics) {
+    let started_at = Instant::now();
+    let boxed_backup_directory = get_backup_directory(config);
+    if boxed_backup_directory.is_err() {
+        logging::error("backup restore failed", &[("error", json!(boxed_backup_directory.err().unwrap()))]);
+        metrics.record_restore(false);
         return;
     }
+    let backup_path = boxed_backup_directory.unwrap().join(BACKUP_FILENAME);
 
-    let boxed_backup_restore_app_list = fs::copy(&backup_app_list_path, &app_list_path);
-    if boxed_backup_restore_app_list.is_err() {
-        println!("backup applist restore failed, exiting...");
+    // signature is verified before anything is decrypted or written to the cache
+    let boxed_restore = backup::restore_backup(backup_path.as_path(), get_cache_dir_path().as_str(), keyring);
+    let duration_ms = started_at.elapsed().as_millis() as u64;
+    if boxed_restore.is_err() {
+        logging::error("backup restore failed", &[
+            ("path", json!(backup_path.display().to_string())), ("duration_ms", json!(duration_ms)), ("error", json!(boxed_restore.err().unwrap())),
+        ]);
+        metrics.record_restore(false);
         return;
     }
+    logging::info("backup restored", &[
+        ("path", json!(backup_path.display().to_string())), ("duration_ms", json!(duration_ms)), ("files", json!(boxed_restore.unwrap())),
+    ]);
 
-    let boxed_backup_restore_app_list_sha256 = fs::copy(&backup_app_list_path_sha_256, &app_list_path_sha_256);
-    if boxed_backup_restore_app_list_sha256.is_err() {
-        println!("backup applist sha256 restore failed, exiting...");
-        return;
+    metrics.record_restore(true);
+    record_audit_event(audit_log, keyring, AuditEvent::RestorePerformed);
+}
+
+/// Serves the metrics on `metrics.address` if configured, a taken address stops the crawler.
+fn start_metrics(config: &Config) -> Arc<Metrics> {
+    let metrics = Arc::new(Metrics::new(config.retry.delay_seconds));
+    if config.metrics.address.is_none() {
+        return metrics;
+    }
+    let address = config.metrics.address.as_ref().unwrap();
+    let boxed_listener = TcpListener::bind(address.as_str());
+    if boxed_listener.is_err() {
+        logging::error("unable to serve metrics, exiting...", &[("address", json!(address)), ("error", json!(boxed_listener.err().unwrap().to_string()))]);
+        process::exit(1);
+    }
+    metrics::serve(boxed_listener.unwrap(), metrics.clone());
+    logging::info("metrics served", &[("url", json!(format!("http://{}/metrics", address)))]);
+    metrics
+}
+
+fn get_backup_directory(config: &Config) -> Result<PathBuf, String> {
+    if let Some(backup_directory) = config.encryption.backup_directory.as_ref() {
+        return Ok(PathBuf::from(backup_directory));
+    }
+    backup::get_backup_directory()
+}
+
+fn open_keyring(config: &Config) -> Keyring {
+    let boxed_secret = get_secret_from_environment();
+    if boxed_secret.is_err() {
+        eprintln!("{}, exiting...", boxed_secret.err().unwrap());
+        process::exit(1);
+    }
+    let secret = boxed_secret.unwrap();
+
+    // keypair supplied by the environment takes precedence over the key store
+    if EnvironmentKeyProvider::is_configured() {
+        let boxed_key_provider = EnvironmentKeyProvider::from_environment(secret.expose());
+        if boxed_key_provider.is_err() {
+            eprintln!("{}, exiting...", boxed_key_provider.err().unwrap());
+            process::exit(1);
+        }
+
+        let boxed_keyring = Keyring::open(&boxed_key_provider.unwrap(), secret.expose());
+        if boxed_keyring.is_err() {
+            eprintln!("unable to open keyring from {}: {}, exiting...", PRIVATE_KEY_ENVIRONMENT_VARIABLE, boxed_keyring.err().unwrap());
+            process::exit(1);
+        }
+        let keyring = boxed_keyring.unwrap();
+
+        // no other key can be stored next to the supplied one to encrypt backups
+        if keyring.active_for(KeyUsage::Encryption).is_err() {
+            eprintln!("key supplied by {} can not encrypt backups, supply an {} key, exiting...", PRIVATE_KEY_ENVIRONMENT_VARIABLE, KeyAlgorithm::Rsa4096);
+            process::exit(1);
+        }
+        return keyring;
+    }
+
+    // keys and keyring index are created on the first run
+    let boxed_key_store = match config.encryption.key_store_directory.as_ref() {
+        Some(key_store_directory) => KeyStore::new(key_store_directory),
+        None => KeyStore::from_environment(),
+    };
+    if boxed_key_store.is_err() {
+        eprintln!("{}, exiting...", boxed_key_store.err().unwrap());
+        process::exit(1);
+    }
+    let key_store = boxed_key_store.unwrap();
+
+    let boxed_keyring = Keyring::open_with_options(&key_store, secret.expose(), &KeyOptions::new(config.encryption.key_algorithm));
+    if boxed_keyring.is_err() {
+        let error = boxed_keyring.err().unwrap();
+        eprintln!("unable to open keyring: {}", error);
+        if error.is_restorable_from_backup() {
+            eprintln!("restore encryption parameters in {} from backup", key_store.get_directory().display());
+        }
+        eprintln!("exiting...");
+        process::exit(1);
+    }
+    let mut keyring = boxed_keyring.unwrap();
+
+    // ed25519 key only signs, backups are encrypted with a separate x25519 key
+    if keyring.active_for(KeyUsage::Encryption).is_err() {
+        let boxed_key_id = keyring.rotate(&KeyOptions::new(KeyAlgorithm::X25519));
+        if boxed_key_id.is_err() {
+            eprintln!("unable to add {} key for backup encryption: {}, exiting...", KeyAlgorithm::X25519, boxed_key_id.err().unwrap());
+            process::exit(1);
+        }
+        logging::info("key for backup encryption added", &[("key_id", json!(boxed_key_id.unwrap())), ("algorithm", json!(KeyAlgorithm::X25519.to_string()))]);
+    }
+
+    keyring
+}
+
+/// Corrupted cache is restored from backup, tampered cache stops the crawler
+/// until the operator inspects it.
+fn verify_cache_integrity(config: &Config, keyring: &Keyring, audit_log: &mut AuditLog, metrics: &Metrics) {
+    let boxed_status = integrity::verify_manifest(get_cache_dir_path().as_str(), keyring);
+    if boxed_status.is_err() {
+        logging::error("unable to verify the integrity manifest, exiting...", &[("error", json!(boxed_status.err().unwrap()))]);
+        process::exit(1);
+    }
+
+    match boxed_status.unwrap() {
+        IntegrityStatus::Missing => {
+            metrics.record_integrity_check("missing");
+            logging::info("integrity manifest is missing, it will be created for the current cache", &[]);
+        }
+        IntegrityStatus::Verified => {
+            metrics.record_integrity_check("verified");
+            logging::info("integrity manifest verified", &[]);
+        }
+        IntegrityStatus::Corrupted(filenames) => {
+            logging::warn("corrupted cache files, restoring from backup", &[("files", json!(filenames))]);
+            do_restore_from_backup(config, audit_log, keyring, metrics);
+
+            let boxed_status = integrity::verify_manifest(get_cache_dir_path().as_str(), keyring);
+            if boxed_status != Ok(IntegrityStatus::Verified) {
+                logging::error("cache is not valid after restore from backup, exiting...", &[("status", json!(format!("{:?}", boxed_status)))]);
+                process::exit(1);
+            }
+            metrics.record_integrity_check("restored");
+            logging::info("integrity manifest verified after restore from backup", &[]);
+        }
+        IntegrityStatus::Tampered(reason) => {
+            metrics.record_integrity_check("tampered");
+            let message = format!("ALERT: cache was tampered with, refusing to run, inspect the cache and remove {} and {} to accept it", MANIFEST_FILENAME, MANIFEST_SIGNATURE_FILENAME);
+            logging::error(message.as_str(), &[("cache_directory", json!(get_cache_dir_path())), ("reason", json!(reason))]);
+            process::exit(2);
+        }
+    }
+}
+
+fn open_audit_log() -> AuditLog {
+    let audit_log_path = get_cache_filepath(AUDIT_LOG_FILENAME);
+    let boxed_audit_log = AuditLog::open(audit_log_path.as_str(), DEFAULT_SEAL_INTERVAL);
+    if boxed_audit_log.is_err() {
+        eprintln!("{}, run verify-audit-log to find the broken entry, exiting...", boxed_audit_log.err().unwrap());
+        process::exit(1);
+    }
+    boxed_audit_log.unwrap()
+}
+
+fn record_audit_event(audit_log: &mut AuditLog, keyring: &Keyring, event: AuditEvent) {
+    let boxed_append = audit_log.append(event, keyring);
+    if boxed_append.is_err() {
+        logging::error("unable to append to the audit log", &[("error", json!(boxed_append.err().unwrap()))]);
+    }
+}
+
+fn do_verify_audit_log(config: &Config) {
+    let keyring = open_keyring(config);
+    let audit_log_path = get_cache_filepath(AUDIT_LOG_FILENAME);
+    let boxed_report = audit::verify_audit_log(audit_log_path.as_str(), &keyring);
+    if boxed_report.is_err() {
+        eprintln!("{}", boxed_report.err().unwrap());
+        process::exit(1);
+    }
+    let report = boxed_report.unwrap();
+
+    println!("audit log entries: {}, seals: {}, unsealed entries: {}", report.entries, report.seals, report.unsealed_entries);
+    if let Some(broken_link) = report.broken_link {
+        eprintln!("audit log chain is broken at line {}: {}", broken_link.line, broken_link.reason);
+        process::exit(2);
+    }
+    println!("audit log chain is intact");
+}
+
+fn do_rotate_key(config: &Config, boxed_algorithm: Option<&String>) {
+    let mut algorithm = config.encryption.key_algorithm;
+    if let Some(algorithm_name) = boxed_algorithm {
+        let boxed_parse = algorithm_name.parse::<KeyAlgorithm>();
+        if boxed_parse.is_err() {
+            eprintln!("{}", boxed_parse.err().unwrap());
+            process::exit(1);
+        }
+        algorithm = boxed_parse.unwrap();
+    }
+
+    let _lock = lock_cache();
+    let mut keyring = open_keyring(config);
+    let boxed_key_id = keyring.rotate(&KeyOptions::new(algorithm));
+    if boxed_key_id.is_err() {
+        eprintln!("unable to rotate key: {}", boxed_key_id.err().unwrap());
+        process::exit(1);
+    }
+    let key_id = boxed_key_id.unwrap();
+    println!("rotated to {} key {}", algorithm, key_id);
+
+    let mut audit_log = open_audit_log();
+    record_audit_event(&mut audit_log, &keyring, AuditEvent::KeyRotated { key_id, algorithm });
+
+    // rotation is sealed right away with the active signing key
+    let boxed_seal = audit_log.seal(&keyring);
+    if boxed_seal.is_err() {
+        eprintln!("{}", boxed_seal.err().unwrap());
+    }
+}
+
+fn do_import_key(config: &Config, boxed_private_key_path: Option<&String>, boxed_public_key_path: Option<&String>) {
+    if boxed_private_key_path.is_none() {
+        eprintln!("path to the private key PEM file is required");
+        process::exit(1);
+    }
+
+    let boxed_private_key = read_to_string(boxed_private_key_path.unwrap());
+    if boxed_private_key.is_err() {
+        eprintln!("unable to read private key: {}", boxed_private_key.err().unwrap());
+        process::exit(1);
+    }
+    let private_key = SecretString::new(boxed_private_key.unwrap());
+
+    let mut public_key: Option<String> = None;
+    if let Some(public_key_path) = boxed_public_key_path {
+        let boxed_public_key = read_to_string(public_key_path);
+        if boxed_public_key.is_err() {
+            eprintln!("unable to read public key: {}", boxed_public_key.err().unwrap());
+            process::exit(1);
+        }
+        public_key = Some(boxed_public_key.unwrap());
+    }
+
+    let _lock = lock_cache();
+    let mut keyring = open_keyring(config);
+    let boxed_key_id = keyring.import(private_key.expose(), public_key.as_deref(), &KeyOptions::default());
+    if boxed_key_id.is_err() {
+        eprintln!("unable to import key: {}", boxed_key_id.err().unwrap());
+        process::exit(1);
+    }
+    let key_id = boxed_key_id.unwrap();
+    let algorithm = keyring.find(key_id.as_str()).unwrap().algorithm;
+    println!("imported {} key {}", algorithm, key_id);
+    do_print_fingerprint(config, Some(&key_id));
+
+    let mut audit_log = open_audit_log();
+    record_audit_event(&mut audit_log, &keyring, AuditEvent::KeyImported { key_id, algorithm });
+    let boxed_seal = audit_log.seal(&keyring);
+    if boxed_seal.is_err() {
+        eprintln!("{}", boxed_seal.err().unwrap());
+    }
+}
+
+/// Keeps the crawler from appending to the audit log meanwhile, exits if one is running.
+fn lock_cache() -> CacheLock {
+    let boxed_lock = CacheLock::acquire(get_cache_dir_path().as_str());
+    if boxed_lock.is_err() {
+        eprintln!("unable to lock the cache, stop the crawler first: {}", boxed_lock.err().unwrap());
+        process::exit(1);
+    }
+    boxed_lock.unwrap()
+}
+
+fn do_export_public_key(config: &Config, boxed_format: Option<&String>, boxed_key_id: Option<&String>) {
+    let mut format = PublicKeyFormat::default();
+    if let Some(format_name) = boxed_format {
+        let boxed_parse = format_name.parse::<PublicKeyFormat>();
+        if boxed_parse.is_err() {
+            eprintln!("{}", boxed_parse.err().unwrap());
+            process::exit(1);
+        }
+        format = boxed_parse.unwrap();
+    }
+
+    let public_key = read_public_key(config, boxed_key_id);
+    let boxed_exported = export_public_key(public_key.as_str(), format);
+    if boxed_exported.is_err() {
+        eprintln!("unable to export public key: {}", boxed_exported.err().unwrap());
+        process::exit(1);
+    }
+    print!("{}", boxed_exported.unwrap());
+}
+
+fn do_print_fingerprint(config: &Config, boxed_key_id: Option<&String>) {
+    let public_key = read_public_key(config, boxed_key_id);
+    let boxed_fingerprint = get_fingerprint(public_key.as_str());
+    if boxed_fingerprint.is_err() {
+        eprintln!("unable to compute fingerprint: {}", boxed_fingerprint.err().unwrap());
+        process::exit(1);
+    }
+    println!("{}", boxed_fingerprint.unwrap());
+}
+
+/// Public key of the given key, or of the active signing key which signs the exports.
+fn read_public_key(config: &Config, boxed_key_id: Option<&String>) -> String {
+    let keyring = open_keyring(config);
+    let key_id = if let Some(key_id) = boxed_key_id {
+        key_id.to_string()
+    } else {
+        let boxed_active = keyring.active_for(KeyUsage::Signing);
+        if boxed_active.is_err() {
+            eprintln!("{}", boxed_active.err().unwrap());
+            process::exit(1);
+        }
+        boxed_active.unwrap().id.to_string()
+    };
+
+    let boxed_public_key = keyring.get_public_key(key_id.as_str());
+    if boxed_public_key.is_err() {
+        eprintln!("unable to read public key: {}", boxed_public_key.err().unwrap());
+        process::exit(1);
+    }
+    boxed_public_key.unwrap()
+}
+
+/// Encrypts the cached app details for the partners listed in the recipients file.
+/// Paths default to the export settings of the configuration.
+fn do_export_records(config: &Config, boxed_recipients_path: Option<&String>, boxed_output_path: Option<&String>) {
+    let boxed_recipients_path = boxed_recipients_path.or(config.export.recipients_file.as_ref());
+    let boxed_output_path = boxed_output_path.or(config.export.output_file.as_ref());
+    if boxed_recipients_path.is_none() || boxed_output_path.is_none() {
+        eprintln!("usage: export-records [recipients JSON file] [output file], or set export.recipients_file and export.output_file");
+        process::exit(1);
+    }
+
+    let boxed_recipients = records::read_recipients(Path::new(boxed_recipients_path.unwrap()));
+    if boxed_recipients.is_err() {
+        eprintln!("{}", boxed_recipients.err().unwrap());
+        process::exit(1);
+    }
+
+    let output_path = boxed_output_path.unwrap();
+    let keyring = open_keyring(config);
+    let read_document = |app_id: i64| fs::read(get_resource_filepath(app_id));
+    // records are ordered by app id, whatever order the apps were crawled in
+    let mut app_ids = read_processed_app_id_list();
+    app_ids.sort_unstable();
+    app_ids.dedup();
+    let boxed_summary = records::export_records(&app_ids, read_document, boxed_recipients.unwrap().as_slice(), Path::new(output_path), &keyring);
+    if boxed_summary.is_err() {
+        eprintln!("{}", boxed_summary.err().unwrap());
+        process::exit(1);
+    }
+    let summary = boxed_summary.unwrap();
+    println!("exported {} records to {}, {} not shared with any recipient", summary.records, output_path, summary.skipped);
+    println!("signature written to {}{}, verify it with the key printed by export-public-key", output_path, records::SIGNATURE_SUFFIX);
+}
+
+/// Prints the app details of the export addressed to the private key, one JSON document per line.
+/// Export is checked against its signature first if the public key of the signer is given.
+fn do_decrypt_records(boxed_export_path: Option<&String>, boxed_private_key_path: Option<&String>, boxed_signer_public_key_path: Option<&String>) {
+    if boxed_export_path.is_none() || boxed_private_key_path.is_none() {
+        eprintln!("usage: decrypt-records <export file> <private key PEM file> [signer public key PEM file]");
+        process::exit(1);
+    }
+
+    if let Some(signer_public_key_path) = boxed_signer_public_key_path {
+        let boxed_public_key = read_to_string(signer_public_key_path);
+        if boxed_public_key.is_err() {
+            eprintln!("unable to read {}: {}", signer_public_key_path, boxed_public_key.err().unwrap());
+            process::exit(1);
+        }
+        let boxed_verify = records::verify_export(Path::new(boxed_export_path.unwrap()), boxed_public_key.unwrap().as_str());
+        if boxed_verify.is_err() {
+            eprintln!("{}", boxed_verify.err().unwrap());
+            process::exit(2);
+        }
+    }
+
+    let boxed_private_key = read_to_string(boxed_private_key_path.unwrap());
+    if boxed_private_key.is_err() {
+        eprintln!("unable to read {}: {}", boxed_private_key_path.unwrap(), boxed_private_key.err().unwrap());
+        process::exit(1);
+    }
+    let private_key = SecretString::new(boxed_private_key.unwrap());
+
+    let boxed_documents = records::decrypt_records(Path::new(boxed_export_path.unwrap()), private_key.expose());
+    if boxed_documents.is_err() {
+        eprintln!("{}", boxed_documents.err().unwrap());
+        process::exit(1);
+    }
+    for (_, document) in boxed_documents.unwrap() {
+        println!("{}", document);
+    }
+}
+
+/// Loads the configuration and changes into the cache base directory, exits if either fails.
+fn load_config() -> Config {
+    let boxed_config = Config::from_environment();
+    if boxed_config.is_err() {
+        eprintln!("{}, run config check to inspect it, exiting...", boxed_config.err().unwrap());
+        process::exit(1);
+    }
+    let (config, _) = boxed_config.unwrap();
+
+    if let Some(base_directory) = config.cache.base_directory.as_ref() {
+        let boxed_change = fs::create_dir_all(base_directory).and_then(|_| env::set_current_dir(base_directory));
+        if boxed_change.is_err() {
+            eprintln!("unable to change into cache base directory {}: {}, exiting...", base_directory, boxed_change.err().unwrap());
+            process::exit(1);
+        }
+    }
+    init_logging(&config);
+    config
+}
+
+/// Log file is opened after changing into the cache base directory, relative paths resolve from it.
+fn init_logging(config: &Config) {
+    let mut boxed_file: Option<RotatingFile> = None;
+    if let Some(file) = config.logging.file.as_ref() {
+        let boxed_open = RotatingFile::open(file, config.logging.max_file_bytes, config.logging.max_files);
+        if boxed_open.is_err() {
+            eprintln!("{}, exiting...", boxed_open.err().unwrap());
+            process::exit(1);
+        }
+        boxed_file = Some(boxed_open.unwrap());
     }
+    logging::init(Logger::new(config.logging.level, config.logging.format, boxed_file));
+}
+
+fn do_config(boxed_subcommand: Option<&String>) {
+    if boxed_subcommand.is_none() || boxed_subcommand.unwrap() != "check" {
+        eprintln!("usage: config check");
+        process::exit(1);
+    }
+
+    let boxed_config = Config::from_environment();
+    if boxed_config.is_err() {
+        eprintln!("configuration is invalid: {}", boxed_config.err().unwrap());
+        process::exit(1);
+    }
+    let (config, boxed_path) = boxed_config.unwrap();
+
+    let boxed_toml = config.to_toml();
+    if boxed_toml.is_err() {
+        eprintln!("{}", boxed_toml.err().unwrap());
+        process::exit(1);
+    }
+
+    if let Some(path) = boxed_path {
+        println!("# configuration file: {}", path.display());
+    } else {
+        println!("# configuration file: none, {} not found and {} not set", config::DEFAULT_CONFIG_FILENAME, config::CONFIG_FILE_ENVIRONMENT_VARIABLE);
+    }
+    for (name, setting) in config::ENVIRONMENT_OVERRIDES {
+        if env::var(name).map(|value| !value.trim().is_empty()).unwrap_or(false) {
+            println!("# {} overridden by {}", setting, name);
+        }
+    }
+    print!("{}", boxed_toml.unwrap());
+}
+
+fn get_cache_filepath(filename: &str) -> String {
+    Path::new(get_cache_dir_path().as_str()).join(filename).to_string_lossy().to_string()
+}
+
+/// Writes and signs the integrity manifest of the cache, returns whether it was written.
+fn sign_cache(keyring: &Keyring) -> bool {
+    let filenames = [PROCESSED_APP_ID_LIST_FILENAME, APP_LIST_FILENAME];
+    let boxed_manifest = integrity::write_manifest(get_cache_dir_path().as_str(), &filenames, keyring);
+    if boxed_manifest.is_err() {
+        logging::error("unable to write the integrity manifest", &[("error", json!(boxed_manifest.err().unwrap()))]);
+        return false;
+    }
+    true
 }
 
 fn write_sha256(path: &String, data: &[u8]) -> String {
@@ -248,14 +1462,11 @@ fn write_sha256(path: &String, data: &[u8]) -> String {
 }
 
 fn get_steam_app_list() -> Vec<SteamApp> {
-    let app_list : Vec<SteamApp>;
     let boxed_cached_app_list = get_cached_app_list();
-    if boxed_cached_app_list.is_ok() {
-        app_list = boxed_cached_app_list.unwrap();
-    } else {
-        app_list = get_app_list().unwrap();
+    if let Ok(app_list) = boxed_cached_app_list {
+        return app_list;
     }
 
-    app_list
+    get_app_list().unwrap()
 }
 
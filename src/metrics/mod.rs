@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::coordinator::http::{read_request, write_response, IO_TIMEOUT};
+use crate::crawler::FetchEvent;
+use crate::estimator::Estimate;
+
+#[cfg(test)]
+mod tests;
+
+/// Upper bounds of the fetch latency histogram buckets, in seconds.
+pub const FETCH_DURATION_BUCKETS: [f64; 9] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+/// Results of the cache integrity check at start.
+pub const INTEGRITY_RESULTS: [&str; 4] = ["verified", "missing", "restored", "tampered"];
+
+/// Label of an SDK error, keeps the number of time series bounded whatever the errors say.
+pub fn get_error_class(error: &str) -> &'static str {
+    let error = error.to_lowercase();
+    if error.contains("timed out") {
+        return "timeout";
+    }
+    if error.contains("no response") {
+        return "no_response";
+    }
+    if error.contains("failed response") {
+        return "failed_response";
+    }
+    if error.contains("utf-8") {
+        return "invalid_encoding";
+    }
+    if error.contains("lease") {
+        return "lease_lost";
+    }
+    "other"
+}
+
+#[derive(Debug, Default)]
+struct MetricsState {
+    apps_total: usize,
+    apps_to_fetch: usize,
+    apps_processed: u64,
+    apps_fetched: u64,
+    errors: BTreeMap<&'static str, u64>,
+    retries: u64,
+    fetch_duration_buckets: [u64; 9],
+    fetch_duration_sum: f64,
+    fetch_count: u64,
+    last_success_timestamp: u64,
+    backing_off: HashSet<i64>,
+    backups: u64,
+    backups_failed: u64,
+    restores: u64,
+    restores_failed: u64,
+    apps_per_minute: f64,
+    eta_seconds: Option<u64>,
+    retry_backoff_seconds: u64,
+    stored_bytes: u64,
+    integrity_checks: BTreeMap<&'static str, u64>,
+}
+
+/// Counters of the process so far, a crawl session runs in one process.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetricsTotals {
+    pub apps_total: usize,
+    pub apps_to_fetch: usize,
+    pub apps_processed: u64,
+    pub apps_fetched: u64,
+    pub errors: BTreeMap<String, u64>,
+    pub retries: u64,
+    pub stored_bytes: u64,
+    pub backups: u64,
+    pub backups_failed: u64,
+    pub restores: u64,
+    pub restores_failed: u64,
+    pub integrity_checks: BTreeMap<String, u64>,
+}
+
+/// Counters and gauges of the crawl, shared between the crawl and the metrics endpoint.
+#[derive(Debug)]
+pub struct Metrics {
+    retry_delay_seconds: u64,
+    state: Mutex<MetricsState>,
+}
+
+impl Metrics {
+    pub fn new(retry_delay_seconds: u64) -> Metrics {
+        Metrics { retry_delay_seconds, state: Mutex::new(MetricsState::default()) }
+    }
+
+    /// Size of the app list and the apps left to fetch in this run.
+    pub fn set_apps(&self, total: usize, to_fetch: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.apps_total = total;
+        state.apps_to_fetch = to_fetch;
+    }
+
+    /// Failed app waits for its retry until it is retried or given up on.
+    pub fn record_event(&self, event: &FetchEvent) {
+        let mut state = self.state.lock().unwrap();
+        match event {
+            FetchEvent::Fetched { .. } => state.apps_fetched += 1,
+            FetchEvent::Failed { app_id, error } => {
+                *state.errors.entry(get_error_class(error)).or_insert(0) += 1;
+                state.backing_off.insert(*app_id);
+            }
+            FetchEvent::Retried { app_id } => {
+                state.retries += 1;
+                state.backing_off.remove(app_id);
+            }
+            FetchEvent::Finished { app_id } => {
+                state.apps_processed += 1;
+                state.backing_off.remove(app_id);
+            }
+            FetchEvent::Idle => {}
+        }
+    }
+
+    /// Latency of one request to the API, `now` is the Unix timestamp it ended at.
+    pub fn observe_fetch(&self, duration: Duration, is_ok: bool, now: u64) {
+        let mut state = self.state.lock().unwrap();
+        let seconds = duration.as_secs_f64();
+        for (index, upper_bound) in FETCH_DURATION_BUCKETS.iter().enumerate() {
+            if seconds <= *upper_bound {
+                state.fetch_duration_buckets[index] += 1;
+            }
+        }
+        state.fetch_duration_sum += seconds;
+        state.fetch_count += 1;
+        if is_ok {
+            state.last_success_timestamp = now;
+        }
+    }
+
+    pub fn set_estimate(&self, estimate: &Estimate) {
+        let mut state = self.state.lock().unwrap();
+        state.apps_per_minute = estimate.apps_per_minute;
+        state.eta_seconds = estimate.eta_seconds;
+        state.retry_backoff_seconds = estimate.backoff_seconds;
+    }
+
+    /// Size of an app details document written to the cache.
+    pub fn record_stored(&self, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.stored_bytes += bytes;
+    }
+
+    /// `result` is one of `INTEGRITY_RESULTS`.
+    pub fn record_integrity_check(&self, result: &'static str) {
+        let mut state = self.state.lock().unwrap();
+        *state.integrity_checks.entry(result).or_insert(0) += 1;
+    }
+
+    pub fn get_totals(&self) -> MetricsTotals {
+        let state = self.state.lock().unwrap();
+        let to_owned_keys = |map: &BTreeMap<&'static str, u64>| map.iter().map(|(key, count)| (key.to_string(), *count)).collect();
+        MetricsTotals {
+            apps_total: state.apps_total,
+            apps_to_fetch: state.apps_to_fetch,
+            apps_processed: state.apps_processed,
+            apps_fetched: state.apps_fetched,
+            errors: to_owned_keys(&state.errors),
+            retries: state.retries,
+            stored_bytes: state.stored_bytes,
+            backups: state.backups,
+            backups_failed: state.backups_failed,
+            restores: state.restores,
+            restores_failed: state.restores_failed,
+            integrity_checks: to_owned_keys(&state.integrity_checks),
+        }
+    }
+
+    pub fn record_backup(&self, is_ok: bool) {
+        let mut state = self.state.lock().unwrap();
+        if is_ok {
+            state.backups += 1;
+        } else {
+            state.backups_failed += 1;
+        }
+    }
+
+    pub fn record_restore(&self, is_ok: bool) {
+        let mut state = self.state.lock().unwrap();
+        if is_ok {
+            state.restores += 1;
+        } else {
+            state.restores_failed += 1;
+        }
+    }
+
+    /// Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut text = String::new();
+
+        write_metric(&mut text, "crawler_apps_total", "gauge", "Apps in the Steam app list.", &[("", state.apps_total as f64)]);
+        write_metric(&mut text, "crawler_apps_to_fetch", "gauge", "Apps left to fetch when the crawl started.", &[("", state.apps_to_fetch as f64)]);
+        write_metric(&mut text, "crawler_apps_processed_total", "counter", "Apps fetched or given up on.", &[("", state.apps_processed as f64)]);
+        write_metric(&mut text, "crawler_apps_fetched_total", "counter", "Apps fetched successfully.", &[("", state.apps_fetched as f64)]);
+
+        let mut errors: Vec<(String, f64)> = vec![];
+        for error_class in ["timeout", "no_response", "failed_response", "invalid_encoding", "lease_lost", "other"] {
+            let count = state.errors.get(error_class).copied().unwrap_or(0);
+            errors.push((format!("{{error_class=\"{}\"}}", error_class), count as f64));
+        }
+        let errors: Vec<(&str, f64)> = errors.iter().map(|(labels, count)| (labels.as_str(), *count)).collect();
+        write_metric(&mut text, "crawler_fetch_errors_total", "counter", "Failed fetch attempts by error class.", &errors);
+        write_metric(&mut text, "crawler_retries_total", "counter", "Fetches retried after a failure.", &[("", state.retries as f64)]);
+
+        let _ = writeln!(text, "# HELP crawler_fetch_duration_seconds Latency of requests to the Steam API.");
+        let _ = writeln!(text, "# TYPE crawler_fetch_duration_seconds histogram");
+        for (index, upper_bound) in FETCH_DURATION_BUCKETS.iter().enumerate() {
+            let _ = writeln!(text, "crawler_fetch_duration_seconds_bucket{{le=\"{}\"}} {}", upper_bound, state.fetch_duration_buckets[index]);
+        }
+        let _ = writeln!(text, "crawler_fetch_duration_seconds_bucket{{le=\"+Inf\"}} {}", state.fetch_count);
+        let _ = writeln!(text, "crawler_fetch_duration_seconds_sum {}", state.fetch_duration_sum);
+        let _ = writeln!(text, "crawler_fetch_duration_seconds_count {}", state.fetch_count);
+
+        write_metric(&mut text, "crawler_apps_per_minute", "gauge", "Apps processed per minute, moving average.", &[("", state.apps_per_minute)]);
+        let eta_seconds = state.eta_seconds.map(|eta_seconds| eta_seconds as f64).unwrap_or(f64::NAN);
+        write_metric(&mut text, "crawler_eta_seconds", "gauge", "Estimated time until the apps of this run are processed, NaN until known.", &[("", eta_seconds)]);
+        write_metric(&mut text, "crawler_retry_backoff_seconds_total", "counter", "Worker time spent waiting for retries.", &[("", state.retry_backoff_seconds as f64)]);
+        write_metric(&mut text, "crawler_last_success_timestamp_seconds", "gauge", "Unix time of the last successful fetch, 0 before the first one.", &[("", state.last_success_timestamp as f64)]);
+        let backoff_seconds = if state.backing_off.is_empty() { 0 } else { self.retry_delay_seconds };
+        write_metric(&mut text, "crawler_backoff_seconds", "gauge", "Delay before the retry of a failed fetch, 0 if none is waiting.", &[("", backoff_seconds as f64)]);
+        write_metric(&mut text, "crawler_apps_backing_off", "gauge", "Failed apps waiting for their retry.", &[("", state.backing_off.len() as f64)]);
+        write_metric(&mut text, "crawler_stored_bytes_total", "counter", "Bytes of app details written to the cache.", &[("", state.stored_bytes as f64)]);
+        let mut integrity_checks: Vec<(String, f64)> = vec![];
+        for result in INTEGRITY_RESULTS {
+            let count = state.integrity_checks.get(result).copied().unwrap_or(0);
+            integrity_checks.push((format!("{{result=\"{}\"}}", result), count as f64));
+        }
+        let integrity_checks: Vec<(&str, f64)> = integrity_checks.iter().map(|(labels, count)| (labels.as_str(), *count)).collect();
+        write_metric(&mut text, "crawler_integrity_checks_total", "counter", "Cache integrity checks by result.", &integrity_checks);
+        write_metric(&mut text, "crawler_backups_total", "counter", "Cache backups by result.", &[("{result=\"ok\"}", state.backups as f64), ("{result=\"failed\"}", state.backups_failed as f64)]);
+        write_metric(&mut text, "crawler_restores_total", "counter", "Cache restores from the backup by result.", &[("{result=\"ok\"}", state.restores as f64), ("{result=\"failed\"}", state.restores_failed as f64)]);
+        text
+    }
+}
+
+fn write_metric(text: &mut String, name: &str, metric_type: &str, help: &str, samples: &[(&str, f64)]) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, metric_type);
+    for (labels, value) in samples {
+        let _ = writeln!(text, "{}{} {}", name, labels, value);
+    }
+}
+
+/// Serves `GET /metrics` on a background thread for as long as the process runs.
+pub fn serve(listener: TcpListener, metrics: Arc<Metrics>) {
+    std::thread::spawn(move || {
+        for boxed_stream in listener.incoming() {
+            if boxed_stream.is_err() {
+                continue;
+            }
+            let stream = boxed_stream.unwrap();
+            let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+            let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+
+            let boxed_request = read_request(&stream);
+            if boxed_request.is_err() {
+                let _ = write_response(&stream, 400, CONTENT_TYPE, boxed_request.err().unwrap().as_bytes());
+                continue;
+            }
+            let request = boxed_request.unwrap();
+            if request.method != "GET" || request.path != "/metrics" {
+                let _ = write_response(&stream, 404, CONTENT_TYPE, b"only GET /metrics is served\n");
+                continue;
+            }
+            let _ = write_response(&stream, 200, CONTENT_TYPE, metrics.render().as_bytes());
+        }
+    });
+}
@@ -0,0 +1,87 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::crawler::FetchEvent;
+use crate::estimator::ProgressEstimator;
+use crate::metrics::{get_error_class, serve, Metrics};
+
+#[test]
+fn crawl_metrics() {
+    let metrics = Metrics::new(60);
+    metrics.set_apps(100, 3);
+
+    metrics.observe_fetch(Duration::from_millis(300), true, 1_660_000_000);
+    metrics.record_event(&FetchEvent::Fetched { app_id: 1, name: "app".to_string() });
+    metrics.record_event(&FetchEvent::Finished { app_id: 1 });
+
+    metrics.observe_fetch(Duration::from_secs(45), false, 1_660_000_100);
+    metrics.record_event(&FetchEvent::Failed { app_id: 2, error: "Operation timed out (API call)".to_string() });
+    let text = metrics.render();
+    assert!(text.contains("crawler_backoff_seconds 60\n"));
+    assert!(text.contains("crawler_apps_backing_off 1\n"));
+
+    metrics.record_event(&FetchEvent::Retried { app_id: 2 });
+    metrics.record_event(&FetchEvent::Failed { app_id: 2, error: "steampowered api returned failed response".to_string() });
+    metrics.record_event(&FetchEvent::Finished { app_id: 2 });
+    metrics.record_backup(true);
+    metrics.record_restore(false);
+    metrics.record_stored(2_048);
+    metrics.record_integrity_check("restored");
+
+    let text = metrics.render();
+    assert!(text.contains("# TYPE crawler_apps_processed_total counter\ncrawler_apps_processed_total 2\n"));
+    assert!(text.contains("crawler_apps_total 100\n"));
+    assert!(text.contains("crawler_apps_fetched_total 1\n"));
+    assert!(text.contains("crawler_fetch_errors_total{error_class=\"timeout\"} 1\n"));
+    assert!(text.contains("crawler_fetch_errors_total{error_class=\"failed_response\"} 1\n"));
+    assert!(text.contains("crawler_fetch_errors_total{error_class=\"other\"} 0\n"));
+    assert!(text.contains("crawler_retries_total 1\n"));
+    assert!(text.contains("crawler_fetch_duration_seconds_bucket{le=\"0.25\"} 0\n"));
+    assert!(text.contains("crawler_fetch_duration_seconds_bucket{le=\"0.5\"} 1\n"));
+    assert!(text.contains("crawler_fetch_duration_seconds_bucket{le=\"60\"} 2\n"));
+    assert!(text.contains("crawler_fetch_duration_seconds_count 2\n"));
+    assert!(text.contains("crawler_last_success_timestamp_seconds 1660000000\n"));
+    assert!(text.contains("crawler_eta_seconds NaN\n"));
+    assert!(text.contains("crawler_backoff_seconds 0\n"));
+    assert!(text.contains("crawler_backups_total{result=\"ok\"} 1\n"));
+    assert!(text.contains("crawler_restores_total{result=\"failed\"} 1\n"));
+    assert!(text.contains("crawler_stored_bytes_total 2048\n"));
+    assert!(text.contains("crawler_integrity_checks_total{result=\"restored\"} 1\n"));
+    assert!(text.contains("crawler_integrity_checks_total{result=\"tampered\"} 0\n"));
+
+    let mut estimator = ProgressEstimator::new(100, 10, 60, 0);
+    estimator.record_event(&FetchEvent::Retried { app_id: 2 }, 30);
+    estimator.record_event(&FetchEvent::Finished { app_id: 2 }, 30);
+    metrics.set_estimate(&estimator.get_estimate(60));
+    let text = metrics.render();
+    assert!(text.contains("crawler_apps_per_minute 1\n"));
+    assert!(text.contains("crawler_eta_seconds 540\n"));
+    assert!(text.contains("crawler_retry_backoff_seconds_total 60\n"));
+
+    assert_eq!("no_response", get_error_class("no response from API"));
+    assert_eq!("other", get_error_class("expected value at line 1 column 1"));
+}
+
+#[test]
+fn metrics_endpoint() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let metrics = Arc::new(Metrics::new(60));
+    metrics.set_apps(7, 7);
+    serve(listener, metrics);
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+    assert!(response.contains("crawler_apps_total 7\n"));
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 404"));
+}
@@ -0,0 +1,318 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use openssl::sha::Sha256;
+use serde::{Deserialize, Serialize};
+use crate::crypto_ext::{envelope, verify};
+use crate::crypto_ext::error::CryptoError;
+use crate::crypto_ext::keyring::{Keyring, SIGNATURE_KEY_ID_SEPARATOR};
+
+#[cfg(test)]
+mod tests;
+
+/// Keyring signature of the export is written next to it, to a file with this suffix.
+pub const SIGNATURE_SUFFIX: &str = ".sig";
+
+const PENDING_SUFFIX: &str = ".tmp";
+
+/// Partner an export is shared with, as listed in the recipients file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Recipient {
+    pub name: String,
+    /// PEM encoded RSA or X25519 public key, relative paths are resolved against the recipients file.
+    pub public_key: String,
+    /// Apps the partner may read, all of them if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_ids: Option<Vec<i64>>,
+}
+
+/// Recipient with its public key loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecipientKey {
+    pub name: String,
+    pub public_key: String,
+    pub app_ids: Option<Vec<i64>>,
+}
+
+impl RecipientKey {
+    pub fn may_read(&self, app_id: i64) -> bool {
+        self.app_ids.is_none() || self.app_ids.as_ref().unwrap().contains(&app_id)
+    }
+}
+
+/// One line of the export. Envelope is base64 encoded and decrypts only with
+/// the private key of one of the listed recipients.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EncryptedRecord {
+    pub app_id: i64,
+    pub recipients: Vec<String>,
+    pub envelope: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub records: usize,
+    /// Records none of the recipients may read, they are left out of the export.
+    pub skipped: usize,
+}
+
+/// Reads the recipients file, a JSON array of `Recipient`, and loads their public keys.
+pub fn read_recipients(path: &Path) -> Result<Vec<RecipientKey>, String> {
+    let boxed_content = fs::read_to_string(path);
+    if boxed_content.is_err() {
+        let message = format!("unable to read recipients {}: {}", path.display(), boxed_content.err().unwrap());
+        return Err(message)
+    }
+
+    let boxed_recipients: Result<Vec<Recipient>, _> = serde_json::from_str(boxed_content.unwrap().as_str());
+    if boxed_recipients.is_err() {
+        let message = format!("unable to parse recipients {}: {}", path.display(), boxed_recipients.err().unwrap());
+        return Err(message)
+    }
+    let recipients = boxed_recipients.unwrap();
+    if recipients.is_empty() {
+        return Err(format!("no recipients in {}", path.display()));
+    }
+
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut recipient_keys: Vec<RecipientKey> = vec![];
+    for recipient in recipients {
+        let public_key_path = directory.join(recipient.public_key.as_str());
+        let boxed_public_key = fs::read_to_string(&public_key_path);
+        if boxed_public_key.is_err() {
+            let message = format!("unable to read public key of {} from {}: {}", recipient.name, public_key_path.display(), boxed_public_key.err().unwrap());
+            return Err(message)
+        }
+        recipient_keys.push(RecipientKey {
+            name: recipient.name,
+            public_key: boxed_public_key.unwrap(),
+            app_ids: recipient.app_ids,
+        });
+    }
+    Ok(recipient_keys)
+}
+
+/// Encrypts the app details document of every app to the recipients allowed to
+/// read it and writes one `EncryptedRecord` per line. App id is authenticated with
+/// the record, so an envelope moved to another app id does not decrypt. Apps without
+/// a stored document are left out. Export is signed with the keyring, the signature
+/// is written to the file with `SIGNATURE_SUFFIX` next to it.
+pub fn export_records<F>(app_ids: &[i64], read_document: F, recipients: &[RecipientKey], output: &Path, keyring: &Keyring) -> Result<ExportSummary, String>
+where
+    F: Fn(i64) -> io::Result<Vec<u8>>,
+{
+    let temporary_path = get_pending_path(output);
+    let boxed_file = File::create(&temporary_path);
+    if boxed_file.is_err() {
+        let message = format!("unable to create {}: {}", temporary_path.display(), boxed_file.err().unwrap());
+        return Err(message)
+    }
+    let mut file = boxed_file.unwrap();
+
+    let mut hasher = Sha256::new();
+    let mut summary = ExportSummary::default();
+    for app_id in app_ids {
+        let app_id = *app_id;
+        let public_keys: Vec<&str> = recipients.iter()
+            .filter(|recipient| recipient.may_read(app_id))
+            .map(|recipient| recipient.public_key.as_str())
+            .collect();
+        if public_keys.is_empty() {
+            summary.skipped += 1;
+            continue;
+        }
+
+        let boxed_document = read_document(app_id);
+        if boxed_document.is_err() {
+            let error = boxed_document.err().unwrap();
+            if error.kind() == ErrorKind::NotFound {
+                continue;
+            }
+            let _ = fs::remove_file(&temporary_path);
+            let message = format!("unable to read app id {}: {}", app_id, error);
+            return Err(message)
+        }
+
+        let boxed_sealed = envelope::seal_for(public_keys.as_slice(), get_associated_data(app_id).as_bytes(), boxed_document.unwrap().as_slice());
+        if boxed_sealed.is_err() {
+            let _ = fs::remove_file(&temporary_path);
+            let message = format!("unable to encrypt app id {}: {}", app_id, boxed_sealed.err().unwrap());
+            return Err(message)
+        }
+        let sealed = boxed_sealed.unwrap();
+
+        let record = EncryptedRecord {
+            app_id,
+            recipients: envelope::get_recipients(sealed.as_slice()).unwrap_or_default(),
+            envelope: base64::encode(sealed.as_slice()),
+        };
+        let boxed_line = serde_json::to_string(&record);
+        if boxed_line.is_err() {
+            let _ = fs::remove_file(&temporary_path);
+            let message = format!("unable to serialize app id {}: {}", app_id, boxed_line.err().unwrap());
+            return Err(message)
+        }
+        let line = [boxed_line.unwrap().as_str(), "\n"].join("");
+
+        let boxed_write = file.write_all(line.as_bytes());
+        if boxed_write.is_err() {
+            let _ = fs::remove_file(&temporary_path);
+            let message = format!("unable to write {}: {}", temporary_path.display(), boxed_write.err().unwrap());
+            return Err(message)
+        }
+        hasher.update(line.as_bytes());
+        summary.records += 1;
+    }
+
+    let boxed_sync = file.sync_all();
+    if boxed_sync.is_err() {
+        let _ = fs::remove_file(&temporary_path);
+        let message = format!("unable to write {}: {}", temporary_path.display(), boxed_sync.err().unwrap());
+        return Err(message)
+    }
+
+    let boxed_signature = keyring.sign(hex::encode(hasher.finish()).as_bytes());
+    if boxed_signature.is_err() {
+        let _ = fs::remove_file(&temporary_path);
+        let message = format!("unable to sign export: {}", boxed_signature.err().unwrap());
+        return Err(message)
+    }
+
+    let boxed_rename = fs::rename(&temporary_path, output);
+    if boxed_rename.is_err() {
+        let message = format!("unable to replace {}: {}", output.display(), boxed_rename.err().unwrap());
+        return Err(message)
+    }
+
+    let boxed_write = write_file(get_signature_path(output).as_path(), boxed_signature.unwrap().as_bytes());
+    if boxed_write.is_err() {
+        return Err(boxed_write.err().unwrap());
+    }
+
+    Ok(summary)
+}
+
+/// Checks the export against the signature next to it with the public key of the
+/// keyring which signed it, as printed by `export-public-key`.
+pub fn verify_export(export: &Path, public_key: &str) -> Result<(), String> {
+    let signature_path = get_signature_path(export);
+    let boxed_signature = fs::read_to_string(&signature_path);
+    if boxed_signature.is_err() {
+        let message = format!("unable to read export signature {}: {}", signature_path.display(), boxed_signature.err().unwrap());
+        return Err(message)
+    }
+    let signature = boxed_signature.unwrap();
+    let signature = signature.trim();
+    let signature = signature.split_once(SIGNATURE_KEY_ID_SEPARATOR).map(|(_, signature)| signature).unwrap_or(signature);
+
+    let boxed_file = File::open(export);
+    if boxed_file.is_err() {
+        let message = format!("unable to read export {}: {}", export.display(), boxed_file.err().unwrap());
+        return Err(message)
+    }
+    let mut reader = BufReader::new(boxed_file.unwrap());
+    let mut hasher = Sha256::new();
+    let mut chunk = [0; 8192];
+    loop {
+        let boxed_read = reader.read(&mut chunk);
+        if boxed_read.is_err() {
+            let message = format!("unable to read export {}: {}", export.display(), boxed_read.err().unwrap());
+            return Err(message)
+        }
+        let length = boxed_read.unwrap();
+        if length == 0 {
+            break;
+        }
+        hasher.update(&chunk[..length]);
+    }
+
+    let boxed_verify = verify(public_key, hex::encode(hasher.finish()).as_bytes(), signature);
+    if boxed_verify.is_err() {
+        let message = format!("unable to verify export: {}", boxed_verify.err().unwrap());
+        return Err(message)
+    }
+    if !boxed_verify.unwrap() {
+        return Err("export signature does not match".to_string());
+    }
+    Ok(())
+}
+
+/// Decrypts the records of the export addressed to the private key, others are skipped.
+/// Returns app ids with their app details documents.
+pub fn decrypt_records(export: &Path, private_key: &str) -> Result<Vec<(i64, String)>, String> {
+    let boxed_content = fs::read_to_string(export);
+    if boxed_content.is_err() {
+        let message = format!("unable to read export {}: {}", export.display(), boxed_content.err().unwrap());
+        return Err(message)
+    }
+
+    let mut documents: Vec<(i64, String)> = vec![];
+    for (index, line) in boxed_content.unwrap().lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let boxed_record: Result<EncryptedRecord, _> = serde_json::from_str(line);
+        if boxed_record.is_err() {
+            let message = format!("unable to parse record on line {}: {}", index + 1, boxed_record.err().unwrap());
+            return Err(message)
+        }
+        let record = boxed_record.unwrap();
+
+        let boxed_sealed = base64::decode(record.envelope.as_str());
+        if boxed_sealed.is_err() {
+            let message = format!("unable to decode app id {}: {}", record.app_id, boxed_sealed.err().unwrap());
+            return Err(message)
+        }
+
+        let boxed_document = envelope::open_with(private_key, get_associated_data(record.app_id).as_bytes(), boxed_sealed.unwrap().as_slice());
+        if boxed_document.is_err() {
+            let error = boxed_document.err().unwrap();
+            if matches!(error, CryptoError::UnknownKey(_)) {
+                continue;
+            }
+            let message = format!("unable to decrypt app id {}: {}", record.app_id, error);
+            return Err(message)
+        }
+
+        let boxed_utf8 = String::from_utf8(boxed_document.unwrap());
+        if boxed_utf8.is_err() {
+            let message = format!("app id {} is not valid utf-8", record.app_id);
+            return Err(message)
+        }
+        documents.push((record.app_id, boxed_utf8.unwrap()));
+    }
+    Ok(documents)
+}
+
+fn get_associated_data(app_id: i64) -> String {
+    ["appdetails", app_id.to_string().as_str()].join(":")
+}
+
+fn get_pending_path(path: &Path) -> PathBuf {
+    let mut pending_path = path.as_os_str().to_owned();
+    pending_path.push(PENDING_SUFFIX);
+    PathBuf::from(pending_path)
+}
+
+fn get_signature_path(export: &Path) -> PathBuf {
+    let mut signature_path = export.as_os_str().to_owned();
+    signature_path.push(SIGNATURE_SUFFIX);
+    PathBuf::from(signature_path)
+}
+
+/// File is written next to the previous one and renamed over it.
+fn write_file(path: &Path, content: &[u8]) -> Result<(), String> {
+    let temporary_path = get_pending_path(path);
+    let boxed_write = File::create(&temporary_path).and_then(|mut file| file.write_all(content).and_then(|_| file.sync_all()));
+    if boxed_write.is_err() {
+        let message = format!("unable to write {}: {}", temporary_path.display(), boxed_write.err().unwrap());
+        return Err(message)
+    }
+
+    let boxed_rename = fs::rename(&temporary_path, path);
+    if boxed_rename.is_err() {
+        let message = format!("unable to replace {}: {}", path.display(), boxed_rename.err().unwrap());
+        return Err(message)
+    }
+    Ok(())
+}
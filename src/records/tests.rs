@@ -0,0 +1,125 @@
+use std::{fs, io};
+use std::path::Path;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use crate::crypto_ext::KeyOptions;
+use crate::crypto_ext::algorithm::{KeyAlgorithm, KeyUsage};
+use crate::crypto_ext::key_provider::MemoryKeyProvider;
+use crate::crypto_ext::keyring::Keyring;
+use crate::records::{decrypt_records, EncryptedRecord, export_records, ExportSummary, read_recipients, SIGNATURE_SUFFIX, verify_export};
+
+const TEST_SECRET: &str = "records test secret";
+/// App 30 was processed, but its details were never stored.
+const APP_IDS: [i64; 4] = [10, 20, 30, 10500];
+
+fn setup(name: &str) -> (String, Keyring) {
+    // target directory is ignored by git, documents and export are recreated on every run
+    let directory = ["target/records_tests/", name].join("");
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all([directory.as_str(), "/documents"].join("")).unwrap();
+    for (app_id, name) in [(10, "Counter-Strike"), (20, "Team Fortress Classic"), (10500, "Dark Messiah")] {
+        fs::write(format!("{}/documents/{}.json", directory, app_id), format!("{{\"name\":\"{}\"}}", name)).unwrap();
+    }
+    let keyring = Keyring::open_with_options(&MemoryKeyProvider::new(), TEST_SECRET, &KeyOptions::new(KeyAlgorithm::Ed25519)).unwrap();
+    (directory, keyring)
+}
+
+fn get_document_reader(directory: &str) -> impl Fn(i64) -> io::Result<Vec<u8>> + '_ {
+    move |app_id| fs::read(format!("{}/documents/{}.json", directory, app_id))
+}
+
+/// Writes the public key next to the recipients file and returns the private key.
+fn create_partner_key(directory: &str, name: &str, rsa: bool) -> String {
+    let pkey = if rsa {
+        PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap()
+    } else {
+        PKey::generate_x25519().unwrap()
+    };
+    fs::write(format!("{}/{}.pem", directory, name), pkey.public_key_to_pem().unwrap()).unwrap();
+    String::from_utf8(pkey.private_key_to_pem_pkcs8().unwrap()).unwrap()
+}
+
+#[test]
+fn selective_sharing() {
+    let (directory, keyring) = setup("selective_sharing");
+
+    let partner_a = create_partner_key(directory.as_str(), "partner-a", true);
+    let partner_b = create_partner_key(directory.as_str(), "partner-b", false);
+    let outsider = create_partner_key(directory.as_str(), "outsider", false);
+    let recipients_path = format!("{}/recipients.json", directory);
+    fs::write(&recipients_path, r#"[
+        {"name": "partner-a", "public_key": "partner-a.pem"},
+        {"name": "partner-b", "public_key": "partner-b.pem", "app_ids": [20]}
+    ]"#).unwrap();
+    let recipients = read_recipients(Path::new(&recipients_path)).unwrap();
+    assert!(recipients[0].may_read(10500));
+    assert!(!recipients[1].may_read(10500));
+
+    // export is produced once, each partner reads only what was shared with them
+    let export_path = format!("{}/export.jsonl", directory);
+    let summary = export_records(&APP_IDS, get_document_reader(directory.as_str()), &recipients, Path::new(&export_path), &keyring).unwrap();
+    assert_eq!(ExportSummary { records: 3, skipped: 0 }, summary);
+    assert!(!Path::new(&format!("{}.tmp", export_path)).exists());
+
+    let documents = decrypt_records(Path::new(&export_path), partner_a.as_str()).unwrap();
+    assert_eq!(vec![10, 20, 10500], documents.iter().map(|(app_id, _)| *app_id).collect::<Vec<i64>>());
+    assert_eq!("{\"name\":\"Counter-Strike\"}", documents[0].1);
+
+    let documents = decrypt_records(Path::new(&export_path), partner_b.as_str()).unwrap();
+    assert_eq!(vec![(20, "{\"name\":\"Team Fortress Classic\"}".to_string())], documents);
+
+    assert!(decrypt_records(Path::new(&export_path), outsider.as_str()).unwrap().is_empty());
+
+    // records no recipient may read are left out
+    let summary = export_records(&APP_IDS, get_document_reader(directory.as_str()), &recipients[1..], Path::new(&export_path), &keyring).unwrap();
+    assert_eq!(ExportSummary { records: 1, skipped: 3 }, summary);
+}
+
+#[test]
+fn signed_export() {
+    let (directory, keyring) = setup("signed_export");
+    create_partner_key(directory.as_str(), "partner", false);
+    let recipients_path = format!("{}/recipients.json", directory);
+    fs::write(&recipients_path, r#"[{"name": "partner", "public_key": "partner.pem"}]"#).unwrap();
+    let recipients = read_recipients(Path::new(&recipients_path)).unwrap();
+    let export_path = format!("{}/export.jsonl", directory);
+    export_records(&APP_IDS, get_document_reader(directory.as_str()), &recipients, Path::new(&export_path), &keyring).unwrap();
+
+    // partners check the export with the public key of the signing key
+    let public_key = keyring.get_public_key(keyring.active_for(KeyUsage::Signing).unwrap().id.as_str()).unwrap();
+    assert!(fs::read_to_string(format!("{}{}", export_path, SIGNATURE_SUFFIX)).unwrap().starts_with(keyring.active_for(KeyUsage::Signing).unwrap().id.as_str()));
+    verify_export(Path::new(&export_path), public_key.as_str()).unwrap();
+
+    let content = fs::read_to_string(&export_path).unwrap();
+    let first_line_end = content.find('\n').unwrap() + 1;
+    fs::write(&export_path, &content[first_line_end..]).unwrap();
+    assert_eq!("export signature does not match", verify_export(Path::new(&export_path), public_key.as_str()).err().unwrap());
+
+    let other_public_key = String::from_utf8(PKey::generate_ed25519().unwrap().public_key_to_pem().unwrap()).unwrap();
+    fs::write(&export_path, content).unwrap();
+    assert!(verify_export(Path::new(&export_path), other_public_key.as_str()).is_err());
+    fs::remove_file(format!("{}{}", export_path, SIGNATURE_SUFFIX)).unwrap();
+    assert!(verify_export(Path::new(&export_path), public_key.as_str()).err().unwrap().starts_with("unable to read export signature"));
+}
+
+#[test]
+fn tampered_records() {
+    let (directory, keyring) = setup("tampered_records");
+    let partner = create_partner_key(directory.as_str(), "partner", false);
+    let recipients_path = format!("{}/recipients.json", directory);
+    fs::write(&recipients_path, r#"[{"name": "partner", "public_key": "partner.pem"}]"#).unwrap();
+    let recipients = read_recipients(Path::new(&recipients_path)).unwrap();
+    let export_path = format!("{}/export.jsonl", directory);
+    export_records(&APP_IDS, get_document_reader(directory.as_str()), &recipients, Path::new(&export_path), &keyring).unwrap();
+
+    // envelope moved to another app id does not decrypt
+    let content = fs::read_to_string(&export_path).unwrap();
+    let mut records: Vec<EncryptedRecord> = content.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    records[0].app_id = 20;
+    records.truncate(1);
+    fs::write(&export_path, serde_json::to_string(&records[0]).unwrap()).unwrap();
+    let error = decrypt_records(Path::new(&export_path), partner.as_str()).err().unwrap();
+    assert!(error.starts_with("unable to decrypt app id 20"));
+
+    assert!(read_recipients(Path::new(&format!("{}/missing.json", directory))).is_err());
+}
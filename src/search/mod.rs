@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::catalog::get_details;
+
+#[cfg(test)]
+mod tests;
+
+/// One line per indexed app in the cache directory, later lines replace earlier ones.
+pub const INDEX_FILENAME: &str = "search_index.jsonl";
+/// A name term counts as much as this many occurrences in the description.
+pub const NAME_WEIGHT: f64 = 3.0;
+/// Terms only starting with the last word of the query count for less than the word itself.
+pub const PREFIX_WEIGHT: f64 = 0.5;
+pub const DEFAULT_LIMIT: usize = 20;
+
+/// Lowercase with the accents and ligatures of Latin letters folded, "Pokémon Æon" is "pokemon aeon".
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for character in text.chars().flat_map(char::to_lowercase) {
+        // combining marks of decomposed text
+        if ('\u{0300}'..='\u{036f}').contains(&character) {
+            continue;
+        }
+        let replacement = match character {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+            'æ' => "ae",
+            'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+            'ď' | 'đ' | 'ð' => "d",
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+            'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+            'ĥ' | 'ħ' => "h",
+            'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+            'ĳ' => "ij",
+            'ĵ' => "j",
+            'ķ' => "k",
+            'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+            'ñ' | 'ń' | 'ņ' | 'ň' | 'ŉ' => "n",
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+            'œ' => "oe",
+            'ŕ' | 'ŗ' | 'ř' => "r",
+            'ś' | 'ŝ' | 'ş' | 'š' | 'ș' => "s",
+            'ß' => "ss",
+            'ţ' | 'ť' | 'ŧ' | 'ț' => "t",
+            'þ' => "th",
+            'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+            'ŵ' => "w",
+            'ý' | 'ÿ' | 'ŷ' => "y",
+            'ź' | 'ż' | 'ž' => "z",
+            _ => {
+                folded.push(character);
+                continue;
+            }
+        };
+        folded.push_str(replacement);
+    }
+    folded
+}
+
+/// Folded words of the text, split on anything but letters and digits.
+pub fn tokenize(text: &str) -> Vec<String> {
+    fold(text).split(|character: char| !character.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_string())
+        .collect()
+}
+
+/// Text of HTML descriptions, tags are dropped and common entities decoded.
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut is_in_tag = false;
+    for character in html.chars() {
+        match character {
+            '<' => is_in_tag = true,
+            '>' if is_in_tag => {
+                is_in_tag = false;
+                text.push(' ');
+            }
+            _ if !is_in_tag => text.push(character),
+            _ => {}
+        }
+    }
+    text.replace("&nbsp;", " ").replace("&quot;", "\"").replace("&#39;", "'")
+        .replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+fn count_terms(text: &str) -> BTreeMap<String, u32> {
+    let mut terms: BTreeMap<String, u32> = BTreeMap::new();
+    for token in tokenize(text) {
+        *terms.entry(token).or_insert(0) += 1;
+    }
+    terms
+}
+
+/// Indexed form of an app, the name as is and the terms of its descriptions.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub app_id: i64,
+    pub name: String,
+    pub description_terms: BTreeMap<String, u32>,
+}
+
+impl IndexEntry {
+    pub fn new(app_id: i64, name: &str, description: &str) -> IndexEntry {
+        IndexEntry { app_id, name: name.to_string(), description_terms: count_terms(description) }
+    }
+
+    /// Entry of a stored appdetails response, from its short description and about the
+    /// game text. `None` if the store returned no details.
+    pub fn from_document(app_id: i64, document: &str) -> Result<Option<IndexEntry>, String> {
+        let boxed_data = get_details(app_id, document);
+        if boxed_data.is_err() {
+            return Err(boxed_data.err().unwrap());
+        }
+        let entry = boxed_data.unwrap().map(|data| {
+            let description = [
+                strip_html(data["short_description"].as_str().unwrap_or_default()),
+                strip_html(data["about_the_game"].as_str().unwrap_or_default()),
+            ].join(" ");
+            IndexEntry::new(app_id, data["name"].as_str().unwrap_or_default(), description.as_str())
+        });
+        Ok(entry)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    pub app_id: i64,
+    pub name: String,
+    pub score: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Posting {
+    name_count: u32,
+    description_count: u32,
+}
+
+#[derive(Clone, Debug)]
+struct IndexedApp {
+    name: String,
+    name_terms: Vec<String>,
+    terms: BTreeSet<String>,
+}
+
+/// Inverted index of app names and descriptions, terms map to the apps containing them.
+#[derive(Clone, Debug, Default)]
+pub struct SearchIndex {
+    apps: BTreeMap<i64, IndexedApp>,
+    postings: BTreeMap<String, BTreeMap<i64, Posting>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> SearchIndex {
+        SearchIndex::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.apps.len()
+    }
+
+    /// Replaces what was indexed for the app.
+    pub fn insert(&mut self, entry: &IndexEntry) {
+        self.remove(entry.app_id);
+        let name_terms = tokenize(entry.name.as_str());
+        let mut postings: BTreeMap<&str, Posting> = BTreeMap::new();
+        for term in name_terms.iter() {
+            postings.entry(term.as_str()).or_default().name_count += 1;
+        }
+        for (term, count) in entry.description_terms.iter() {
+            postings.entry(term.as_str()).or_default().description_count += count;
+        }
+        for (term, posting) in postings.iter() {
+            self.postings.entry(term.to_string()).or_default().insert(entry.app_id, *posting);
+        }
+        let terms = postings.keys().map(|term| term.to_string()).collect();
+        self.apps.insert(entry.app_id, IndexedApp { name: entry.name.to_string(), name_terms, terms });
+    }
+
+    /// Indexes the name of an app from the app list unless its details were indexed.
+    pub fn insert_name(&mut self, app_id: i64, name: &str) {
+        if !self.apps.contains_key(&app_id) {
+            self.insert(&IndexEntry::new(app_id, name, ""));
+        }
+    }
+
+    fn remove(&mut self, app_id: i64) {
+        let boxed_app = self.apps.remove(&app_id);
+        if boxed_app.is_none() {
+            return;
+        }
+        for term in boxed_app.unwrap().terms.iter() {
+            if let Some(postings) = self.postings.get_mut(term) {
+                postings.remove(&app_id);
+                if postings.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+    }
+
+    /// Apps containing every word of the query, the last one also as a prefix, best first.
+    /// Words count for more the fewer apps contain them and in the name more than in the
+    /// description, an app named exactly like the query ranks first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
+        let words = tokenize(query);
+        if words.is_empty() {
+            return vec![];
+        }
+        let app_count = self.apps.len() as f64;
+        let mut scores: BTreeMap<i64, f64> = BTreeMap::new();
+        for (index, word) in words.iter().enumerate() {
+            let is_last = index == words.len() - 1;
+            let mut word_scores: BTreeMap<i64, f64> = BTreeMap::new();
+            let matching_terms = self.postings.range(word.to_string()..)
+                .take_while(|(term, _)| term.as_str() == word || (is_last && term.starts_with(word.as_str())));
+            for (term, postings) in matching_terms {
+                let inverse_frequency = (1.0 + app_count / postings.len() as f64).ln();
+                let weight = if term == word { 1.0 } else { PREFIX_WEIGHT };
+                for (app_id, posting) in postings.iter() {
+                    let frequency = NAME_WEIGHT * posting.name_count as f64 + (1.0 + posting.description_count as f64).ln();
+                    let score = weight * inverse_frequency * frequency;
+                    let best = word_scores.entry(*app_id).or_insert(0.0);
+                    if score > *best {
+                        *best = score;
+                    }
+                }
+            }
+            if index == 0 {
+                scores = word_scores;
+            } else {
+                scores = scores.into_iter()
+                    .filter_map(|(app_id, score)| word_scores.get(&app_id).map(|word_score| (app_id, score + word_score)))
+                    .collect();
+            }
+        }
+
+        let mut results: Vec<SearchResult> = scores.into_iter().map(|(app_id, score)| {
+            let app = &self.apps[&app_id];
+            let score = if app.name_terms == words { score * 2.0 } else { score };
+            SearchResult { app_id, name: app.name.to_string(), score: (score * 1000.0).round() / 1000.0 }
+        }).collect();
+        results.sort_by(|first, second| second.score.partial_cmp(&first.score).unwrap_or(std::cmp::Ordering::Equal)
+            .then(first.name.len().cmp(&second.name.len()))
+            .then(first.app_id.cmp(&second.app_id)));
+        results.truncate(limit);
+        results
+    }
+}
+
+/// Index entries kept on disk, appended to as apps are stored, compacted at the end of
+/// a crawl and rewritten on rebuild.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexFile {
+    path: PathBuf,
+}
+
+impl IndexFile {
+    pub fn new<P: AsRef<Path>>(path: P) -> IndexFile {
+        IndexFile { path: path.as_ref().to_path_buf() }
+    }
+
+    pub fn append(&self, entry: &IndexEntry) -> Result<(), String> {
+        let boxed_file = fs::OpenOptions::new().create(true).append(true).open(&self.path);
+        if boxed_file.is_err() {
+            return Err(format!("unable to open search index {}: {}", self.path.display(), boxed_file.err().unwrap()));
+        }
+        let line = [serde_json::to_string(entry).unwrap_or_default(), "\n".to_string()].join("");
+        let boxed_write = boxed_file.unwrap().write_all(line.as_bytes());
+        if boxed_write.is_err() {
+            return Err(format!("unable to write search index {}: {}", self.path.display(), boxed_write.err().unwrap()));
+        }
+        Ok(())
+    }
+
+    /// Index of the entries in the file, empty if there is no file yet. Line cut short
+    /// by a crash is skipped.
+    pub fn load(&self) -> Result<SearchIndex, String> {
+        let boxed_entries = self.read_entries();
+        if boxed_entries.is_err() {
+            return Err(boxed_entries.err().unwrap());
+        }
+        let mut index = SearchIndex::new();
+        for entry in boxed_entries.unwrap().0.values() {
+            index.insert(entry);
+        }
+        Ok(index)
+    }
+
+    /// Rewrites the file with the latest entry of every app once superseded and broken
+    /// lines outnumber those entries. Returns the number of lines dropped, 0 if the file
+    /// was left as is. The file is appended to by the crawler, call it with the cache locked.
+    pub fn compact(&self) -> Result<usize, String> {
+        let boxed_entries = self.read_entries();
+        if boxed_entries.is_err() {
+            return Err(boxed_entries.err().unwrap());
+        }
+        let (entries, line_count) = boxed_entries.unwrap();
+        let dropped_line_count = line_count - entries.len();
+        if dropped_line_count <= entries.len() {
+            return Ok(0);
+        }
+
+        let entries: Vec<IndexEntry> = entries.into_values().collect();
+        let boxed_rewrite = self.rewrite(&entries);
+        if boxed_rewrite.is_err() {
+            return Err(boxed_rewrite.err().unwrap());
+        }
+        Ok(dropped_line_count)
+    }
+
+    /// Latest entry of every app in the file and the number of lines read.
+    fn read_entries(&self) -> Result<(BTreeMap<i64, IndexEntry>, usize), String> {
+        let mut entries: BTreeMap<i64, IndexEntry> = BTreeMap::new();
+        if !self.path.is_file() {
+            return Ok((entries, 0));
+        }
+        let boxed_content = fs::read_to_string(&self.path);
+        if boxed_content.is_err() {
+            return Err(format!("unable to read search index {}: {}", self.path.display(), boxed_content.err().unwrap()));
+        }
+        let mut line_count = 0;
+        for line in boxed_content.unwrap().lines() {
+            line_count += 1;
+            if let Ok(entry) = serde_json::from_str::<IndexEntry>(line) {
+                entries.insert(entry.app_id, entry);
+            }
+        }
+        Ok((entries, line_count))
+    }
+
+    /// Replaces the file with the entries at once.
+    pub fn rewrite(&self, entries: &[IndexEntry]) -> Result<(), String> {
+        let temporary_path = PathBuf::from([self.path.to_string_lossy().to_string(), ".tmp".to_string()].join(""));
+        let mut content = String::new();
+        for entry in entries {
+            content.push_str(serde_json::to_string(entry).unwrap_or_default().as_str());
+            content.push('\n');
+        }
+        let boxed_write = fs::write(&temporary_path, content).and_then(|_| fs::rename(&temporary_path, &self.path));
+        if boxed_write.is_err() {
+            return Err(format!("unable to write search index {}: {}", self.path.display(), boxed_write.err().unwrap()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs;
+use crate::search::{fold, strip_html, tokenize, IndexEntry, IndexFile, SearchIndex};
+
+fn get_app_ids(index: &SearchIndex, query: &str) -> Vec<i64> {
+    index.search(query, 10).iter().map(|result| result.app_id).collect()
+}
+
+#[test]
+fn folding() {
+    assert_eq!("pokemon aeon strasse smorgasbord", fold("Pokémon Æon Straße Smörgåsbord"));
+    // decomposed e and combining acute accent
+    assert_eq!("cafe", fold("Cafe\u{0301}"));
+    assert_eq!(vec!["half", "life", "2", "лето"], tokenize("Half-Life 2: ЛЕТО!"));
+    assert_eq!(" Fight & win ", strip_html("<p>Fight &amp; win</p>"));
+}
+
+#[test]
+fn ranking() {
+    let mut index = SearchIndex::new();
+    index.insert(&IndexEntry::new(10, "Portal", "A puzzle game about portals and a cake."));
+    index.insert(&IndexEntry::new(20, "Portal 2", "Puzzle sequel. Cooperative puzzle mode."));
+    index.insert(&IndexEntry::new(30, "Café Simulator", "Run a café, brew coffee."));
+    index.insert(&IndexEntry::new(40, "Space Station", "Build a station, then a portal to another world."));
+    index.insert_name(50, "Portal Knights");
+    index.insert_name(10, "renamed in the app list");
+    assert_eq!(5, index.len());
+
+    // name matches rank above description matches, the exact name first
+    assert_eq!(vec![10, 20, 50, 40], get_app_ids(&index, "portal"));
+    assert_eq!(vec![20, 10], get_app_ids(&index, "PUZZLE"));
+    assert_eq!(vec![20], get_app_ids(&index, "portal puzzle 2"));
+    assert_eq!(vec![30], get_app_ids(&index, "cafe simulator"));
+    assert_eq!(vec![30], get_app_ids(&index, "CAFÉ"));
+    // last word is also a prefix
+    assert_eq!(vec![30], get_app_ids(&index, "caf"));
+    assert_eq!(vec![10, 20, 50, 40], get_app_ids(&index, "port"));
+    assert!(get_app_ids(&index, "por simulator").is_empty());
+    assert!(index.search(" - ", 10).is_empty());
+    assert_eq!(1, index.search("portal", 1).len());
+
+    // reindexed app loses the terms it no longer has
+    index.insert(&IndexEntry::new(40, "Space Station", "Build a station."));
+    assert_eq!(vec![10, 20, 50], get_app_ids(&index, "portal"));
+    assert_eq!("Portal", index.search("portal", 1)[0].name);
+}
+
+#[test]
+fn entries_and_file() {
+    let document = r#"{"30":{"success":true,"data":{"name":"Café Simulator","short_description":"Brew &amp; serve",
+        "about_the_game":"<h1>About</h1><p>Serve coffee</p>"}}}"#;
+    let entry = IndexEntry::from_document(30, document).unwrap().unwrap();
+    assert_eq!("Café Simulator", entry.name);
+    assert_eq!(vec![("about", 1), ("brew", 1), ("coffee", 1), ("serve", 2)],
+               entry.description_terms.iter().map(|(term, count)| (term.as_str(), *count)).collect::<Vec<_>>());
+    assert_eq!(None, IndexEntry::from_document(20, r#"{"20":{"success":false}}"#).unwrap());
+    assert!(IndexEntry::from_document(20, "<html>").is_err());
+
+    // target directory is ignored by git, index file is recreated on every run
+    let directory = "target/search_tests";
+    let _ = fs::remove_dir_all(directory);
+    fs::create_dir_all(directory).unwrap();
+    let path = [directory, "/search_index.jsonl"].join("");
+    let index_file = IndexFile::new(path.as_str());
+    assert_eq!(0, index_file.load().unwrap().len());
+
+    index_file.append(&IndexEntry::new(10, "Portal", "")).unwrap();
+    index_file.append(&entry).unwrap();
+    index_file.append(&IndexEntry::new(10, "Portal", "puzzle")).unwrap();
+    fs::OpenOptions::new().append(true).open(path.as_str()).and_then(|mut file| std::io::Write::write_all(&mut file, b"{\"app_id\":4")).unwrap();
+    let index = index_file.load().unwrap();
+    assert_eq!(2, index.len());
+    assert_eq!(vec![10], get_app_ids(&index, "puzzle"));
+    assert_eq!(vec![30], get_app_ids(&index, "coffee"));
+
+    index_file.rewrite(std::slice::from_ref(&entry)).unwrap();
+    let index = index_file.load().unwrap();
+    assert_eq!(1, index.len());
+    assert_eq!(1, fs::read_to_string(path.as_str()).unwrap().lines().count());
+
+    // compacted once the superseded lines outnumber the entries
+    index_file.append(&IndexEntry::new(10, "Portal", "")).unwrap();
+    index_file.append(&entry).unwrap();
+    index_file.append(&IndexEntry::new(10, "Portal", "puzzle game")).unwrap();
+    assert_eq!(0, index_file.compact().unwrap());
+    index_file.append(&entry).unwrap();
+    assert_eq!(3, index_file.compact().unwrap());
+    assert_eq!(2, fs::read_to_string(path.as_str()).unwrap().lines().count());
+    assert_eq!(vec![10], get_app_ids(&index_file.load().unwrap(), "game"));
+    assert_eq!(0, index_file.compact().unwrap());
+}
@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::{Deserialize, Serialize};
+use crate::lock::LockHolder;
+use crate::metrics::MetricsTotals;
+
+#[cfg(test)]
+mod tests;
+
+/// Directory in the cache holding one report per session, `<id>.json`.
+pub const SESSIONS_DIRECTORY: &str = "sessions";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionOutcome {
+    /// Written at start, a session whose process is gone was interrupted.
+    Running,
+    /// Every app of the session was processed.
+    Completed,
+    /// Stopped by a signal or from the dashboard before every app was processed.
+    Stopped,
+}
+
+/// What a crawl session did, from the start of the process to its end.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SessionReport {
+    pub id: String,
+    pub mode: String,
+    pub pid: u32,
+    pub hostname: String,
+    pub started_at: u64,
+    pub ended_at: Option<u64>,
+    pub outcome: SessionOutcome,
+    pub app_list_size: usize,
+    /// Apps the app list grew by since the previous session.
+    pub new_apps: usize,
+    pub apps_to_fetch: usize,
+    /// Apps processed, fetched or given up on.
+    pub attempted: u64,
+    pub succeeded: u64,
+    /// Apps given up on.
+    pub failed: u64,
+    /// Failed requests by error class, retried ones included.
+    pub failed_by_class: BTreeMap<String, u64>,
+    pub retried: u64,
+    pub bytes_stored: u64,
+    pub backups_taken: u64,
+    pub backups_failed: u64,
+    pub restores: u64,
+    pub restores_failed: u64,
+    /// Cache integrity check results, see `metrics::INTEGRITY_RESULTS`.
+    pub integrity_events: BTreeMap<String, u64>,
+}
+
+impl SessionReport {
+    pub fn new(mode: &str, pid: u32, hostname: &str, started_at: u64) -> SessionReport {
+        SessionReport {
+            id: format!("{}-{}", started_at, pid),
+            mode: mode.to_string(),
+            pid,
+            hostname: hostname.to_string(),
+            started_at,
+            ended_at: None,
+            outcome: SessionOutcome::Running,
+            app_list_size: 0,
+            new_apps: 0,
+            apps_to_fetch: 0,
+            attempted: 0,
+            succeeded: 0,
+            failed: 0,
+            failed_by_class: BTreeMap::new(),
+            retried: 0,
+            bytes_stored: 0,
+            backups_taken: 0,
+            backups_failed: 0,
+            restores: 0,
+            restores_failed: 0,
+            integrity_events: BTreeMap::new(),
+        }
+    }
+
+    /// Takes the counters of the session, `previous` is the report of the session before it.
+    pub fn update(&mut self, totals: &MetricsTotals, previous: Option<&SessionReport>) {
+        self.app_list_size = totals.apps_total;
+        self.new_apps = previous
+            .filter(|previous| previous.app_list_size > 0)
+            .map(|previous| totals.apps_total.saturating_sub(previous.app_list_size))
+            .unwrap_or(0);
+        self.apps_to_fetch = totals.apps_to_fetch;
+        self.failed_by_class = totals.errors.iter().filter(|(_, count)| **count > 0).map(|(class, count)| (class.to_string(), *count)).collect();
+        self.attempted = totals.apps_processed;
+        self.succeeded = totals.apps_fetched;
+        self.failed = totals.apps_processed.saturating_sub(totals.apps_fetched);
+        self.retried = totals.retries;
+        self.bytes_stored = totals.stored_bytes;
+        self.backups_taken = totals.backups;
+        self.backups_failed = totals.backups_failed;
+        self.restores = totals.restores;
+        self.restores_failed = totals.restores_failed;
+        self.integrity_events = totals.integrity_checks.clone();
+    }
+
+    pub fn finish(&mut self, outcome: SessionOutcome, ended_at: u64) {
+        self.outcome = outcome;
+        self.ended_at = Some(ended_at);
+    }
+
+    /// Session still marked as running whose process is gone.
+    pub fn is_interrupted(&self) -> bool {
+        let holder = LockHolder { pid: self.pid, hostname: self.hostname.to_string(), started_at: self.started_at };
+        self.outcome == SessionOutcome::Running && !holder.is_alive()
+    }
+}
+
+/// Reports of past and running sessions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionLog {
+    directory: PathBuf,
+}
+
+impl SessionLog {
+    pub fn new<P: AsRef<Path>>(directory: P) -> SessionLog {
+        SessionLog { directory: directory.as_ref().to_path_buf() }
+    }
+
+    /// Replaces the report of the session at once.
+    pub fn write(&self, report: &SessionReport) -> Result<(), String> {
+        let boxed_create = fs::create_dir_all(&self.directory);
+        if boxed_create.is_err() {
+            return Err(format!("unable to create sessions directory {}: {}", self.directory.display(), boxed_create.err().unwrap()));
+        }
+        let path = self.directory.join([report.id.as_str(), ".json"].join(""));
+        let temporary_path = self.directory.join([report.id.as_str(), ".json.tmp"].join(""));
+        let serialized = serde_json::to_string_pretty(report).unwrap_or_default();
+        let boxed_write = fs::write(&temporary_path, serialized).and_then(|_| fs::rename(&temporary_path, &path));
+        if boxed_write.is_err() {
+            return Err(format!("unable to write session report {}: {}", path.display(), boxed_write.err().unwrap()));
+        }
+        Ok(())
+    }
+
+    /// Reports oldest first, unreadable ones are left out.
+    pub fn list(&self) -> Result<Vec<SessionReport>, String> {
+        let mut reports: Vec<SessionReport> = vec![];
+        if !self.directory.is_dir() {
+            return Ok(reports);
+        }
+        let boxed_entries = fs::read_dir(&self.directory);
+        if boxed_entries.is_err() {
+            return Err(format!("unable to read sessions directory {}: {}", self.directory.display(), boxed_entries.err().unwrap()));
+        }
+        for entry in boxed_entries.unwrap().flatten() {
+            let path = entry.path();
+            if path.extension().map(|extension| extension != "json").unwrap_or(true) {
+                continue;
+            }
+            let boxed_report = fs::read_to_string(&path).ok().and_then(|content| serde_json::from_str::<SessionReport>(content.as_str()).ok());
+            if let Some(report) = boxed_report {
+                reports.push(report);
+            }
+        }
+        reports.sort_by(|first, second| first.started_at.cmp(&second.started_at).then(first.id.cmp(&second.id)));
+        Ok(reports)
+    }
+}
+
+static IS_STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn stop_on_signal(signal: libc::c_int) {
+    IS_STOP_REQUESTED.store(true, Ordering::SeqCst);
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+    }
+}
+
+/// SIGTERM and SIGINT stop the crawl once the current apps are done, so the
+/// session report is written. The second signal ends the process at once.
+#[cfg(unix)]
+pub fn install_stop_handler() {
+    let handler = stop_on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install_stop_handler() {}
+
+/// Stop requested from the dashboard ends the session like a signal.
+pub fn request_stop() {
+    IS_STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+pub fn is_stop_requested() -> bool {
+    IS_STOP_REQUESTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use crate::metrics::{Metrics, MetricsTotals};
+use crate::crawler::FetchEvent;
+use crate::session::{SessionLog, SessionOutcome, SessionReport};
+
+#[test]
+fn report_from_totals() {
+    let metrics = Metrics::new(60);
+    metrics.set_apps(1_010, 30);
+    metrics.record_event(&FetchEvent::Failed { app_id: 1, error: "Operation timed out (API call)".to_string() });
+    metrics.record_event(&FetchEvent::Retried { app_id: 1 });
+    metrics.record_event(&FetchEvent::Fetched { app_id: 1, name: "one".to_string() });
+    metrics.record_event(&FetchEvent::Finished { app_id: 1 });
+    metrics.record_event(&FetchEvent::Failed { app_id: 2, error: "no response from API".to_string() });
+    metrics.record_event(&FetchEvent::Finished { app_id: 2 });
+    metrics.record_stored(1_500);
+    metrics.record_backup(true);
+    metrics.record_integrity_check("verified");
+
+    let mut previous = SessionReport::new("local", 10, "host", 100);
+    previous.update(&MetricsTotals { apps_total: 1_000, ..MetricsTotals::default() }, None);
+    assert_eq!(0, previous.new_apps);
+
+    let mut report = SessionReport::new("local", 11, "host", 200);
+    report.update(&metrics.get_totals(), Some(&previous));
+    assert_eq!("200-11", report.id);
+    assert_eq!((1_010, 10, 30), (report.app_list_size, report.new_apps, report.apps_to_fetch));
+    assert_eq!((2, 1, 1, 1), (report.attempted, report.succeeded, report.failed, report.retried));
+    assert_eq!(vec![("no_response", 1), ("timeout", 1)], report.failed_by_class.iter().map(|(class, count)| (class.as_str(), *count)).collect::<Vec<_>>());
+    assert_eq!((1_500, 1, 0), (report.bytes_stored, report.backups_taken, report.backups_failed));
+    assert_eq!(Some(&1), report.integrity_events.get("verified"));
+    assert_eq!((SessionOutcome::Running, None), (report.outcome, report.ended_at));
+
+    report.finish(SessionOutcome::Completed, 300);
+    assert_eq!((SessionOutcome::Completed, Some(300)), (report.outcome, report.ended_at));
+}
+
+#[test]
+fn session_log() {
+    // target directory is ignored by git, sessions directory is recreated on every run
+    let directory = "target/session_tests";
+    let _ = fs::remove_dir_all(directory);
+    let session_log = SessionLog::new(directory);
+    assert!(session_log.list().unwrap().is_empty());
+
+    let mut second = SessionReport::new("dashboard", 2, "host", 200);
+    session_log.write(&second).unwrap();
+    let first = SessionReport::new("local", 1, "host", 100);
+    session_log.write(&first).unwrap();
+    fs::write([directory, "/notes.txt"].join(""), "not a report").unwrap();
+    fs::write([directory, "/broken.json"].join(""), "{").unwrap();
+
+    second.finish(SessionOutcome::Stopped, 250);
+    session_log.write(&second).unwrap();
+    assert_eq!(vec![first, second], session_log.list().unwrap());
+}
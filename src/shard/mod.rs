@@ -0,0 +1,511 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::crypto_ext::keyring::get_unix_timestamp;
+
+#[cfg(test)]
+mod tests;
+
+/// Work directory file naming the current generation.
+pub const CURRENT_GENERATION_FILENAME: &str = "current";
+/// Written last when a generation is started, shards are ready once it exists.
+pub const GENERATION_FILENAME: &str = "generation.json";
+
+/// One pass over the app list, every app of it is fetched once.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Generation {
+    pub number: u64,
+    pub created_at: u64,
+    pub shard_size: usize,
+    pub shard_count: usize,
+    pub app_count: usize,
+}
+
+/// Claim of a worker on a shard, valid until it expires unless renewed.
+/// Expiry is compared with the clock of the reading worker, clocks of the
+/// machines sharing the work directory have to be roughly in sync.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Lease {
+    pub worker_id: String,
+    pub acquired_at: u64,
+    pub expires_at: u64,
+}
+
+impl Lease {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at <= now
+    }
+}
+
+/// Marker of a completed shard.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ShardDone {
+    pub worker_id: String,
+    pub finished_at: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GenerationStatus {
+    pub shards: usize,
+    pub done: usize,
+    pub leased: usize,
+    pub expired: usize,
+    pub pending: usize,
+    pub fetched_apps: usize,
+    pub app_count: usize,
+}
+
+impl GenerationStatus {
+    pub fn is_complete(&self) -> bool {
+        self.done == self.shards
+    }
+}
+
+/// Directory shared by the workers, locally or over a network file system.
+/// Shards are claimed by creating their lease file exclusively. Fetched apps are
+/// appended to the progress file of the shard, so a worker reclaiming the shard
+/// of a dead one continues where it stopped.
+///
+/// ```text
+/// current
+/// generation-<n>/generation.json
+/// generation-<n>/shard-<i>.json       app ids
+/// generation-<n>/shard-<i>.lease      Lease
+/// generation-<n>/shard-<i>.progress   fetched app ids, one per line
+/// generation-<n>/shard-<i>.done       ShardDone
+/// generation-<n>.tmp-<pid>/           generation being started, renamed once complete
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkDirectory {
+    path: PathBuf,
+}
+
+impl WorkDirectory {
+    pub fn new<P: AsRef<Path>>(path: P) -> WorkDirectory {
+        WorkDirectory { path: path.as_ref().to_path_buf() }
+    }
+
+    pub fn get_path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// Generation named by the `current` file, `None` before the first one is started.
+    pub fn get_current_generation(&self) -> Result<Option<Generation>, String> {
+        let current_path = self.path.join(CURRENT_GENERATION_FILENAME);
+        if !current_path.is_file() {
+            return Ok(None);
+        }
+        let boxed_content = fs::read_to_string(&current_path);
+        if boxed_content.is_err() {
+            let message = format!("unable to read {}: {}", current_path.display(), boxed_content.err().unwrap());
+            return Err(message)
+        }
+        let boxed_number = boxed_content.unwrap().trim().parse::<u64>();
+        if boxed_number.is_err() {
+            let message = format!("unable to parse {}: {}", current_path.display(), boxed_number.err().unwrap());
+            return Err(message)
+        }
+
+        let boxed_generation = read_json(self.get_generation_directory(boxed_number.unwrap()).join(GENERATION_FILENAME).as_path());
+        if boxed_generation.is_err() {
+            return Err(boxed_generation.err().unwrap());
+        }
+        Ok(Some(boxed_generation.unwrap()))
+    }
+
+    /// Splits the app ids into shards of the next generation and makes it current.
+    /// Fails if the current generation is not complete. A worker starting the same
+    /// generation as another one at the same time gets the generation of the first.
+    pub fn start_generation(&self, app_ids: &[i64], shard_size: usize) -> Result<Generation, String> {
+        if shard_size == 0 {
+            return Err("shard size must be at least 1".to_string());
+        }
+
+        let boxed_current = self.get_current_generation();
+        if boxed_current.is_err() {
+            return Err(boxed_current.err().unwrap());
+        }
+        let current = boxed_current.unwrap();
+        if let Some(generation) = current.as_ref() {
+            let boxed_status = self.get_status(generation);
+            if boxed_status.is_err() {
+                return Err(boxed_status.err().unwrap());
+            }
+            let status = boxed_status.unwrap();
+            if !status.is_complete() {
+                let message = format!("generation {} is not complete, {} of {} shards done", generation.number, status.done, status.shards);
+                return Err(message)
+            }
+        }
+        let number = current.map(|generation| generation.number + 1).unwrap_or(1);
+
+        let boxed_create = fs::create_dir_all(&self.path);
+        if boxed_create.is_err() {
+            let message = format!("unable to create {}: {}", self.path.display(), boxed_create.err().unwrap());
+            return Err(message)
+        }
+        // shards are written to a directory of this worker and renamed into place once
+        // complete, a worker dying half way leaves no generation without its marker behind
+        let generation_directory = self.get_generation_directory(number);
+        let temporary_directory = PathBuf::from(format!("{}.tmp-{}", generation_directory.display(), std::process::id()));
+        let boxed_generation = write_generation(temporary_directory.as_path(), number, app_ids, shard_size);
+        if boxed_generation.is_err() {
+            let _ = fs::remove_dir_all(&temporary_directory);
+            return Err(boxed_generation.err().unwrap());
+        }
+        let mut generation = boxed_generation.unwrap();
+
+        // rename fails if the directory exists and is not empty, another worker started the generation
+        let boxed_rename = fs::rename(&temporary_directory, &generation_directory);
+        if boxed_rename.is_err() {
+            let _ = fs::remove_dir_all(&temporary_directory);
+            let marker_path = generation_directory.join(GENERATION_FILENAME);
+            if generation_directory.is_dir() && !marker_path.is_file() {
+                let message = format!("generation {} was left incomplete by a worker, remove {} to start it again", number, generation_directory.display());
+                return Err(message)
+            }
+            let boxed_started = read_json::<Generation>(marker_path.as_path());
+            if boxed_started.is_err() {
+                let message = format!("unable to rename {}: {}", temporary_directory.display(), boxed_rename.err().unwrap());
+                return Err(message)
+            }
+            generation = boxed_started.unwrap();
+        }
+        // the worker which started the generation may have died before making it current
+        let is_current = self.get_current_generation()
+            .map(|boxed_current| boxed_current.map(|current| current.number >= number).unwrap_or(false))
+            .unwrap_or(false);
+        if !is_current {
+            let boxed_write = write_atomically(self.path.join(CURRENT_GENERATION_FILENAME).as_path(), number.to_string().as_bytes());
+            if boxed_write.is_err() {
+                return Err(boxed_write.err().unwrap());
+            }
+        }
+
+        Ok(generation)
+    }
+
+    /// Claims the first shard which is neither done nor leased by a live worker.
+    /// Returns `None` if there is none left.
+    pub fn claim(&self, generation: &Generation, worker_id: &str, lease_seconds: u64) -> Result<Option<ShardClaim>, String> {
+        let generation_directory = self.get_generation_directory(generation.number);
+        for shard in 0..generation.shard_count {
+            if get_shard_path(&generation_directory, shard, "done").is_file() {
+                continue;
+            }
+
+            let lease_path = get_shard_path(&generation_directory, shard, "lease");
+            let now = get_unix_timestamp();
+            let lease = Lease {
+                worker_id: worker_id.to_string(),
+                acquired_at: now,
+                expires_at: now + lease_seconds,
+            };
+
+            let boxed_created = create_lease(lease_path.as_path(), &lease);
+            if boxed_created.is_err() {
+                return Err(boxed_created.err().unwrap());
+            }
+            let mut is_claimed = boxed_created.unwrap();
+
+            if !is_claimed {
+                let boxed_existing = read_json::<Lease>(lease_path.as_path());
+                // lease released or replaced meanwhile, the shard is left for the next pass
+                if boxed_existing.is_err() || !boxed_existing.as_ref().unwrap().is_expired(now) {
+                    continue;
+                }
+                let boxed_taken_over = take_over_lease(lease_path.as_path(), &boxed_existing.unwrap(), &lease);
+                if boxed_taken_over.is_err() {
+                    return Err(boxed_taken_over.err().unwrap());
+                }
+                is_claimed = boxed_taken_over.unwrap();
+            }
+            if !is_claimed {
+                continue;
+            }
+
+            let boxed_claim = ShardClaim::new(generation_directory.as_path(), generation.number, shard, lease, lease_seconds);
+            if boxed_claim.is_err() {
+                return Err(boxed_claim.err().unwrap());
+            }
+            return Ok(Some(boxed_claim.unwrap()));
+        }
+        Ok(None)
+    }
+
+    pub fn get_status(&self, generation: &Generation) -> Result<GenerationStatus, String> {
+        let generation_directory = self.get_generation_directory(generation.number);
+        let now = get_unix_timestamp();
+        let mut status = GenerationStatus {
+            shards: generation.shard_count,
+            app_count: generation.app_count,
+            ..GenerationStatus::default()
+        };
+
+        for shard in 0..generation.shard_count {
+            let boxed_progress = read_progress(get_shard_path(&generation_directory, shard, "progress").as_path());
+            if boxed_progress.is_err() {
+                return Err(boxed_progress.err().unwrap());
+            }
+            status.fetched_apps += boxed_progress.unwrap().len();
+
+            if get_shard_path(&generation_directory, shard, "done").is_file() {
+                status.done += 1;
+                continue;
+            }
+            match read_json::<Lease>(get_shard_path(&generation_directory, shard, "lease").as_path()) {
+                Err(_) => status.pending += 1,
+                Ok(lease) if lease.is_expired(now) => status.expired += 1,
+                Ok(_) => status.leased += 1,
+            }
+        }
+        Ok(status)
+    }
+
+    fn get_generation_directory(&self, number: u64) -> PathBuf {
+        self.path.join(format!("generation-{}", number))
+    }
+}
+
+/// Shard claimed by this worker. Lease is renewed while apps are fetched, a lease
+/// taken over by another worker after it expired ends the claim.
+#[derive(Debug)]
+pub struct ShardClaim {
+    generation_directory: PathBuf,
+    pub generation: u64,
+    pub shard: usize,
+    lease: Lease,
+    lease_seconds: u64,
+    /// Apps of the shard not fetched yet, in shard order.
+    pub remaining_app_ids: Vec<i64>,
+}
+
+impl ShardClaim {
+    fn new(generation_directory: &Path, generation: u64, shard: usize, lease: Lease, lease_seconds: u64) -> Result<ShardClaim, String> {
+        let boxed_app_ids = read_json::<Vec<i64>>(get_shard_path(generation_directory, shard, "json").as_path());
+        if boxed_app_ids.is_err() {
+            return Err(boxed_app_ids.err().unwrap());
+        }
+        let boxed_progress = read_progress(get_shard_path(generation_directory, shard, "progress").as_path());
+        if boxed_progress.is_err() {
+            return Err(boxed_progress.err().unwrap());
+        }
+        let fetched_app_ids = boxed_progress.unwrap();
+
+        Ok(ShardClaim {
+            generation_directory: generation_directory.to_path_buf(),
+            generation,
+            shard,
+            lease,
+            lease_seconds,
+            remaining_app_ids: boxed_app_ids.unwrap().into_iter().filter(|app_id| !fetched_app_ids.contains(app_id)).collect(),
+        })
+    }
+
+    pub fn get_lease(&self) -> &Lease {
+        &self.lease
+    }
+
+    /// Renews the lease once half of it has passed. Fails if the lease is no longer ours.
+    pub fn renew(&mut self) -> Result<(), String> {
+        let now = get_unix_timestamp();
+        if self.lease.expires_at > now + self.lease_seconds / 2 {
+            return Ok(());
+        }
+
+        let lease_path = get_shard_path(&self.generation_directory, self.shard, "lease");
+        let boxed_existing = read_json::<Lease>(lease_path.as_path());
+        if boxed_existing.is_err() || boxed_existing.as_ref().unwrap() != &self.lease {
+            let message = format!("lease of shard {} of generation {} was taken over by another worker", self.shard, self.generation);
+            return Err(message)
+        }
+
+        let lease = Lease { expires_at: now + self.lease_seconds, ..self.lease.clone() };
+        let boxed_write = write_json_atomically(lease_path.as_path(), &lease);
+        if boxed_write.is_err() {
+            return Err(boxed_write.err().unwrap());
+        }
+        self.lease = lease;
+        Ok(())
+    }
+
+    /// Records the app in the progress file of the shard and renews the lease if due.
+    pub fn record_fetched(&mut self, app_id: i64) -> Result<(), String> {
+        let boxed_renew = self.renew();
+        if boxed_renew.is_err() {
+            return Err(boxed_renew.err().unwrap());
+        }
+
+        let progress_path = get_shard_path(&self.generation_directory, self.shard, "progress");
+        let boxed_file = fs::OpenOptions::new().create(true).append(true).open(&progress_path);
+        if boxed_file.is_err() {
+            let message = format!("unable to open {}: {}", progress_path.display(), boxed_file.err().unwrap());
+            return Err(message)
+        }
+        let mut file = boxed_file.unwrap();
+        let boxed_write = file.write_all([app_id.to_string().as_str(), "\n"].join("").as_bytes()).and_then(|_| file.sync_data());
+        if boxed_write.is_err() {
+            let message = format!("unable to write {}: {}", progress_path.display(), boxed_write.err().unwrap());
+            return Err(message)
+        }
+        self.remaining_app_ids.retain(|remaining_app_id| *remaining_app_id != app_id);
+        Ok(())
+    }
+
+    /// Marks the shard done and releases the lease.
+    pub fn complete(self) -> Result<(), String> {
+        let done = ShardDone { worker_id: self.lease.worker_id.to_string(), finished_at: get_unix_timestamp() };
+        let boxed_write = write_json_atomically(get_shard_path(&self.generation_directory, self.shard, "done").as_path(), &done);
+        if boxed_write.is_err() {
+            return Err(boxed_write.err().unwrap());
+        }
+        self.release();
+        Ok(())
+    }
+
+    /// Gives the shard up before its lease expires, another worker may claim it right away.
+    pub fn release(self) {
+        let lease_path = get_shard_path(&self.generation_directory, self.shard, "lease");
+        let boxed_existing = read_json::<Lease>(lease_path.as_path());
+        if boxed_existing.is_ok() && boxed_existing.unwrap() == self.lease {
+            let _ = fs::remove_file(lease_path);
+        }
+    }
+}
+
+/// Writes the shards of the generation and its marker to the directory.
+fn write_generation(directory: &Path, number: u64, app_ids: &[i64], shard_size: usize) -> Result<Generation, String> {
+    let _ = fs::remove_dir_all(directory);
+    let boxed_create = fs::create_dir(directory);
+    if boxed_create.is_err() {
+        let message = format!("unable to create {}: {}", directory.display(), boxed_create.err().unwrap());
+        return Err(message)
+    }
+
+    let shards: Vec<&[i64]> = app_ids.chunks(shard_size).collect();
+    for (shard, shard_app_ids) in shards.iter().enumerate() {
+        let boxed_write = write_json_atomically(get_shard_path(directory, shard, "json").as_path(), shard_app_ids);
+        if boxed_write.is_err() {
+            return Err(boxed_write.err().unwrap());
+        }
+    }
+
+    let generation = Generation {
+        number,
+        created_at: get_unix_timestamp(),
+        shard_size,
+        shard_count: shards.len(),
+        app_count: app_ids.len(),
+    };
+    let boxed_write = write_json_atomically(directory.join(GENERATION_FILENAME).as_path(), &generation);
+    if boxed_write.is_err() {
+        return Err(boxed_write.err().unwrap());
+    }
+    Ok(generation)
+}
+
+fn get_shard_path(generation_directory: &Path, shard: usize, extension: &str) -> PathBuf {
+    generation_directory.join(format!("shard-{}.{}", shard, extension))
+}
+
+/// Returns false if the lease file exists already.
+fn create_lease(lease_path: &Path, lease: &Lease) -> Result<bool, String> {
+    let boxed_file = fs::OpenOptions::new().write(true).create_new(true).open(lease_path);
+    if boxed_file.is_err() {
+        let error = boxed_file.err().unwrap();
+        if error.kind() == ErrorKind::AlreadyExists {
+            return Ok(false);
+        }
+        let message = format!("unable to create {}: {}", lease_path.display(), error);
+        return Err(message)
+    }
+
+    let mut file = boxed_file.unwrap();
+    let content = serde_json::to_string(lease).unwrap_or_default();
+    let boxed_write = file.write_all(content.as_bytes()).and_then(|_| file.sync_all());
+    if boxed_write.is_err() {
+        let _ = fs::remove_file(lease_path);
+        let message = format!("unable to write {}: {}", lease_path.display(), boxed_write.err().unwrap());
+        return Err(message)
+    }
+    Ok(true)
+}
+
+/// Expired lease is moved aside by rename, which only one worker wins. If the moved
+/// lease turns out to be a fresh one, another worker took the shard over in the
+/// meantime and its lease is put back.
+fn take_over_lease(lease_path: &Path, expired_lease: &Lease, lease: &Lease) -> Result<bool, String> {
+    let moved_path = PathBuf::from(format!("{}.expired-{}", lease_path.display(), lease.worker_id));
+    let boxed_rename = fs::rename(lease_path, &moved_path);
+    if boxed_rename.is_err() {
+        let error = boxed_rename.err().unwrap();
+        if error.kind() == ErrorKind::NotFound {
+            return Ok(false);
+        }
+        let message = format!("unable to move expired lease {}: {}", lease_path.display(), error);
+        return Err(message)
+    }
+
+    let boxed_moved = read_json::<Lease>(moved_path.as_path());
+    if boxed_moved.is_err() || &boxed_moved.unwrap() != expired_lease {
+        let _ = fs::hard_link(&moved_path, lease_path);
+        let _ = fs::remove_file(&moved_path);
+        return Ok(false);
+    }
+
+    let _ = fs::remove_file(&moved_path);
+    create_lease(lease_path, lease)
+}
+
+fn read_progress(path: &Path) -> Result<Vec<i64>, String> {
+    if !path.is_file() {
+        return Ok(vec![]);
+    }
+    let boxed_content = fs::read_to_string(path);
+    if boxed_content.is_err() {
+        let message = format!("unable to read {}: {}", path.display(), boxed_content.err().unwrap());
+        return Err(message)
+    }
+    // line cut short by a crash is not a fetched app
+    Ok(boxed_content.unwrap().lines().filter_map(|line| line.trim().parse::<i64>().ok()).collect())
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, String> {
+    let boxed_content = fs::read_to_string(path);
+    if boxed_content.is_err() {
+        let message = format!("unable to read {}: {}", path.display(), boxed_content.err().unwrap());
+        return Err(message)
+    }
+    let boxed_value = serde_json::from_str(boxed_content.unwrap().as_str());
+    if boxed_value.is_err() {
+        let message = format!("unable to parse {}: {}", path.display(), boxed_value.err().unwrap());
+        return Err(message)
+    }
+    Ok(boxed_value.unwrap())
+}
+
+fn write_json_atomically<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), String> {
+    let boxed_serialized = serde_json::to_string(value);
+    if boxed_serialized.is_err() {
+        let message = format!("unable to serialize {}: {}", path.display(), boxed_serialized.err().unwrap());
+        return Err(message)
+    }
+    write_atomically(path, boxed_serialized.unwrap().as_bytes())
+}
+
+/// Readers on other machines see either the previous or the new content.
+fn write_atomically(path: &Path, content: &[u8]) -> Result<(), String> {
+    let temporary_path = PathBuf::from(format!("{}.tmp-{}", path.display(), std::process::id()));
+    let boxed_write = fs::File::create(&temporary_path).and_then(|mut file| file.write_all(content).and_then(|_| file.sync_all()));
+    if boxed_write.is_err() {
+        let message = format!("unable to write {}: {}", temporary_path.display(), boxed_write.err().unwrap());
+        return Err(message)
+    }
+    let boxed_rename = fs::rename(&temporary_path, path);
+    if boxed_rename.is_err() {
+        let message = format!("unable to replace {}: {}", path.display(), boxed_rename.err().unwrap());
+        return Err(message)
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use std::fs;
+use crate::shard::{CURRENT_GENERATION_FILENAME, GenerationStatus, WorkDirectory};
+
+fn setup(name: &str) -> WorkDirectory {
+    // target directory is ignored by git, work directory is recreated on every run
+    let directory = ["target/shard_tests/", name].join("");
+    let _ = fs::remove_dir_all(&directory);
+    WorkDirectory::new(directory)
+}
+
+#[test]
+fn shard_claims() {
+    let work_directory = setup("shard_claims");
+    assert_eq!(None, work_directory.get_current_generation().unwrap());
+
+    let app_ids: Vec<i64> = (1..=25).collect();
+    let generation = work_directory.start_generation(&app_ids, 10).unwrap();
+    assert_eq!(1, generation.number);
+    assert_eq!(3, generation.shard_count);
+    assert_eq!(Some(generation.clone()), work_directory.get_current_generation().unwrap());
+    assert!(work_directory.start_generation(&app_ids, 10).is_err());
+
+    // every worker gets a different shard until none is left
+    let first = work_directory.claim(&generation, "worker-1", 600).unwrap().unwrap();
+    let second = work_directory.claim(&generation, "worker-2", 600).unwrap().unwrap();
+    let third = work_directory.claim(&generation, "worker-3", 600).unwrap().unwrap();
+    assert_eq!((0, 1, 2), (first.shard, second.shard, third.shard));
+    assert_eq!(vec![21, 22, 23, 24, 25], third.remaining_app_ids);
+    assert!(work_directory.claim(&generation, "worker-4", 600).unwrap().is_none());
+    assert_eq!(3, work_directory.get_status(&generation).unwrap().leased);
+
+    // released shard is claimed again right away
+    second.release();
+    let mut second = work_directory.claim(&generation, "worker-4", 600).unwrap().unwrap();
+    assert_eq!(1, second.shard);
+
+    let remaining_app_ids = second.remaining_app_ids.clone();
+    for app_id in remaining_app_ids {
+        second.record_fetched(app_id).unwrap();
+    }
+    second.complete().unwrap();
+    first.complete().unwrap();
+    third.complete().unwrap();
+
+    let status = work_directory.get_status(&generation).unwrap();
+    assert_eq!(GenerationStatus { shards: 3, done: 3, leased: 0, expired: 0, pending: 0, fetched_apps: 10, app_count: 25 }, status);
+    assert!(status.is_complete());
+
+    // empty directory left by a worker which died starting the generation is replaced
+    fs::create_dir(work_directory.get_path().join("generation-2")).unwrap();
+    let generation = work_directory.start_generation(&app_ids, 100).unwrap();
+    assert_eq!(2, generation.number);
+
+    // worker which read the previous generation gets the one started meanwhile
+    fs::write(work_directory.get_path().join(CURRENT_GENERATION_FILENAME), "1").unwrap();
+    assert_eq!(generation, work_directory.start_generation(&app_ids, 10).unwrap());
+    assert_eq!(Some(generation.clone()), work_directory.get_current_generation().unwrap());
+
+    // generation half written by an earlier version is not taken for a started one
+    work_directory.claim(&generation, "worker-1", 600).unwrap().unwrap().complete().unwrap();
+    let generation_directory = work_directory.get_path().join("generation-3");
+    fs::create_dir(&generation_directory).unwrap();
+    fs::write(generation_directory.join("shard-0.json"), "[1]").unwrap();
+    let error = work_directory.start_generation(&app_ids, 100).err().unwrap();
+    assert!(error.starts_with("generation 3 was left incomplete by a worker"));
+}
+
+#[test]
+fn expired_leases() {
+    let work_directory = setup("expired_leases");
+    let app_ids: Vec<i64> = (1..=4).collect();
+    let generation = work_directory.start_generation(&app_ids, 4).unwrap();
+
+    // worker dies after fetching two apps, its lease is not renewed
+    let mut dead = work_directory.claim(&generation, "worker-1", 1).unwrap().unwrap();
+    dead.record_fetched(1).unwrap();
+    dead.record_fetched(2).unwrap();
+    assert_eq!(1, work_directory.get_status(&generation).unwrap().leased);
+
+    std::thread::sleep(std::time::Duration::from_millis(2100));
+    assert_eq!(1, work_directory.get_status(&generation).unwrap().expired);
+
+    // reclaimed shard continues after the fetched apps
+    let mut reclaimed = work_directory.claim(&generation, "worker-2", 600).unwrap().unwrap();
+    assert_eq!(vec![3, 4], reclaimed.remaining_app_ids);
+    assert!(dead.renew().is_err());
+    reclaimed.record_fetched(3).unwrap();
+    reclaimed.record_fetched(4).unwrap();
+    reclaimed.complete().unwrap();
+
+    // release of the old claim leaves the lease of the new holder alone
+    dead.release();
+    let status = work_directory.get_status(&generation).unwrap();
+    assert_eq!((1, 4), (status.done, status.fetched_apps));
+}
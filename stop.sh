@@ -1 +1,3 @@
-kill -9 27696
+# SIGTERM lets the crawler finish its current apps and write the session report,
+# waits for it to exit so the next instance can lock the cache
+killall -w retrieve-all-steam-apps-details-demo-app